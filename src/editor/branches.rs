@@ -0,0 +1,74 @@
+//! # Branch switcher
+//!
+//! The branches (local and remote) and the tags of the repository are listed in a
+//! scratch buffer, one per line, the current branch being marked with a `*`. From there
+//! the user can check one out or create a new branch at HEAD. Like with the history
+//! browser, the editor keeps the working buffer aside in the meantime, and reloads it
+//! once the checkout updated its file on disk.
+
+use super::view::file::git::GitRef;
+
+/// State of the branch switcher
+pub struct Branches {
+    /// The branches and the tags, in the order they are listed
+    refs: Vec<GitRef>,
+}
+
+impl Branches {
+    pub fn new(refs: Vec<GitRef>) -> Self {
+        Self { refs }
+    }
+
+    /// The reference at the given line of the list
+    pub fn entry(&self, index: usize) -> Option<&GitRef> {
+        self.refs.get(index)
+    }
+
+    /// Line of the current branch (the first line if HEAD is detached)
+    pub fn current_index(&self) -> usize {
+        self.refs
+            .iter()
+            .position(|git_ref| git_ref.current)
+            .unwrap_or_default()
+    }
+
+    /// Render the list as the content of a buffer (one reference per line)
+    pub fn render(&self) -> String {
+        self.refs
+            .iter()
+            .map(GitRef::to_string)
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::editor::view::file::git::RefKind;
+
+    fn git_ref(name: &str, kind: RefKind, current: bool) -> GitRef {
+        GitRef {
+            name: name.into(),
+            kind,
+            current,
+        }
+    }
+
+    #[test]
+    fn branches_render() {
+        let branches = Branches::new(vec![
+            git_ref("feature", RefKind::Local, false),
+            git_ref("main", RefKind::Local, true),
+            git_ref("origin/main", RefKind::Remote, false),
+            git_ref("v1.0", RefKind::Tag, false),
+        ]);
+        assert_eq!(
+            branches.render(),
+            "  feature\n* main\n  origin/main (remote)\n  v1.0 (tag)"
+        );
+        assert_eq!(branches.current_index(), 1);
+        assert_eq!(branches.entry(3).unwrap().kind, RefKind::Tag);
+        assert_eq!(branches.entry(4), None);
+    }
+}
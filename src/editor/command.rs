@@ -10,25 +10,39 @@
 //! multiple `Command`s. This is done using the `CommandBlock` variant.
 //! It allows for composite commands such as 'I' (go to the beginning of the line
 //! and enter insert mode) or 'A' (go to the end of the line and enter insert mode).
+//!
+//! ## Key sequences
+//!
+//! Some commands are made of two keys (such as `]c` to go to the next hunk). The
+//! first key is parsed into a `Pending` command, the editor then parses the next
+//! key with `Command::parse_sequence`.
+//!
+//! ## Command line
+//!
+//! The input of the command line (`:`) is parsed into a `Command` as well, with
+//! `Command::parse_command_line`.
 
 use termion::event::Key;
 
-use crate::editor::Mode;
+use crate::editor::{conflict::Resolution, substitute::Substitution, Mode};
 
 /// Commands that can be executed by the editor
 #[derive(Debug, PartialEq)]
+#[allow(clippy::enum_variant_names)]
 pub enum Command {
     /// Quit the editor
     Quit,
+    /// Quit the editor with a non-zero exit status
+    Abort,
+    /// Suspend the editor to the shell (Ctrl-Z)
+    Suspend,
     /// Move the cursor by dx, dy
     Move(isize, isize),
     /// Save the file
     Save,
-    /// Rename the file
-    Rename(Option<char>),
     /// Toggle mode
     ToggleMode,
-    /// Toggle rename
+    /// Start renaming the file
     ToggleRename,
     /// Insert a character
     Insert(char),
@@ -40,15 +54,147 @@ pub enum Command {
     CommandBlock(Vec<Command>),
     /// Delete a line
     DeleteLine,
+    /// Open the file explorer on the directory of the current file
+    OpenExplorer,
+    /// Choose what the buffer is compared to in the gutter
+    ChangeDiffBase,
+    /// First key of a key sequence
+    Pending(char),
+    /// Go to the next hunk of the diff
+    NextHunk,
+    /// Go to the previous hunk of the diff
+    PreviousHunk,
+    /// Show the original text of the hunk under the cursor
+    PreviewHunk,
+    /// Revert the changes under the cursor back to HEAD
+    RevertHunk,
+    /// Stage the hunk under the cursor (or the selected lines)
+    StageHunk,
+    /// Unstage the hunk under the cursor (or the selected lines)
+    UnstageHunk,
+    /// Start or stop selecting lines
+    ToggleVisual,
+    /// Show or hide the blame annotations
+    ToggleBlame,
+    /// Show the commit that last changed the line under the cursor
+    ShowCommit,
+    /// Undo the last group of edits
+    Undo,
+    /// Replace a string with another one
+    Substitute(Substitution),
+    /// Browse the history of the current file
+    OpenHistory,
+    /// Open the revision under the cursor in the history
+    HistoryOpen,
+    /// Open the previous (older) revision of the file
+    HistoryOlder,
+    /// Open the next (newer) revision of the file
+    HistoryNewer,
+    /// Compare the working buffer to the selected revision
+    HistoryDiff,
+    /// Go back to the list of revisions, or to the working buffer
+    HistoryClose,
+    /// List the branches and the tags to check one out
+    OpenBranches,
+    /// Check out the branch or the tag under the cursor
+    BranchCheckout,
+    /// Create a new branch at HEAD and switch to it
+    BranchCreate,
+    /// Close the branch switcher
+    BranchesClose,
+    /// Show the diff of the stash under the cursor
+    StashOpen,
+    /// Apply the selected stash and remove it from the list
+    StashPop,
+    /// Go back to the stash list, or close it
+    StashesClose,
+    /// Stash the changes of the repository
+    StashSave,
+    /// Apply the latest stash and remove it from the list
+    StashPopLatest,
+    /// Browse the stash list
+    OpenStashes,
+    /// Write the message of a commit of the staged changes
+    StartCommit,
+    /// Compare two versions of the file side by side
+    SideBySide,
+    /// Compare the saved file to the buffer side by side
+    DiffOrig,
+    /// Leave the side-by-side diff
+    CloseSideBySide,
+    /// Go to the next merge conflict
+    NextConflict,
+    /// Go to the previous merge conflict
+    PreviousConflict,
+    /// Resolve the merge conflict under the cursor
+    ResolveConflict(Resolution),
+    /// Compare our, the base and their versions of a conflicted file
+    ThreeWay,
+    /// Open the file or toggle the directory under the cursor in the explorer
+    ExplorerOpen,
+    /// Collapse the directory under the cursor in the explorer
+    ExplorerCollapse,
+    /// Create a file next to the entry under the cursor in the explorer
+    ExplorerCreate,
+    /// Rename the entry under the cursor in the explorer
+    ExplorerRename,
+    /// Move the entry under the cursor in the explorer
+    ExplorerMove,
+    /// Delete the entry under the cursor in the explorer
+    ExplorerDelete,
+    /// Replace the action of the line of a rebase todo list with the next one
+    NextRebaseAction,
+    /// Replace the action of the line of a rebase todo list with the previous one
+    PreviousRebaseAction,
+    /// Open the command line (`:`) in the status bar
+    OpenCommandLine,
+    /// Edit the input of the prompt
+    PromptInput(Option<char>),
+    /// Complete the path typed in the prompt
+    PromptComplete,
+    /// Submit the prompt
+    PromptSubmit,
+    /// Cancel the prompt
+    PromptCancel,
 }
 
 impl Command {
     /// Parse a command from a termion::event::Key object
     pub fn parse(key: Key, mode: &Mode) -> Result<Self, &'static str> {
+        // Ctrl-Z suspends the editor in every mode
+        if key == Key::Ctrl('z') {
+            return Ok(Command::Suspend);
+        }
         match mode {
             Mode::Normal => Self::parse_normal_mode(key),
             Mode::Insert => Self::parse_insert_mode(key),
-            Mode::Rename => Self::parse_rename_mode(key),
+            Mode::Visual => Self::parse_visual_mode(key),
+            Mode::Explorer => Self::parse_explorer_mode(key),
+            Mode::History => Self::parse_history_mode(key),
+            Mode::Branches => Self::parse_branches_mode(key),
+            Mode::Stashes => Self::parse_stashes_mode(key),
+            Mode::Diff => Self::parse_diff_mode(key),
+            Mode::Prompt(_) => Self::parse_prompt_mode(key),
+        }
+    }
+
+    /// Parse the input of the command line (without the `:`)
+    pub fn parse_command_line(input: &str) -> Result<Self, &'static str> {
+        match input.trim() {
+            "w" => Ok(Command::Save),
+            "q" => Ok(Command::Quit),
+            "wq" | "x" => Ok(Command::CommandBlock(vec![Command::Save, Command::Quit])),
+            "cq" => Ok(Command::Abort),
+            "Gcommit" => Ok(Command::StartCommit),
+            "Gbranch" => Ok(Command::OpenBranches),
+            "Gstash" => Ok(Command::StashSave),
+            "Gstash pop" => Ok(Command::StashPopLatest),
+            "Gstash list" => Ok(Command::OpenStashes),
+            "DiffOrig" => Ok(Command::DiffOrig),
+            input if input.starts_with("s/") || input.starts_with("%s/") => {
+                Substitution::parse(input).map(Command::Substitute)
+            }
+            _ => Err("Not an editor command"),
         }
     }
 
@@ -95,6 +241,21 @@ impl Command {
             Key::Char('R') => Ok(Command::ToggleRename),
             // DeleteLine
             Key::Char('d') => Ok(Command::DeleteLine),
+            // Explorer
+            Key::Char('-') => Ok(Command::OpenExplorer),
+            // Undo
+            Key::Char('u') => Ok(Command::Undo),
+            // Select lines
+            Key::Char('V') => Ok(Command::ToggleVisual),
+            // Command line
+            Key::Char(':') => Ok(Command::OpenCommandLine),
+            // Git
+            Key::Char('B') => Ok(Command::ChangeDiffBase),
+            // Rebase todo list
+            Key::Ctrl('a') => Ok(Command::NextRebaseAction),
+            Key::Ctrl('x') => Ok(Command::PreviousRebaseAction),
+            // Key sequences
+            Key::Char(c @ (']' | '[' | 'g' | 'c')) => Ok(Command::Pending(c)),
             _ => Err("Invalid command"),
         }
     }
@@ -117,11 +278,147 @@ impl Command {
         }
     }
 
-    fn parse_rename_mode(key: Key) -> Result<Self, &'static str> {
+    /// Parse the second key of a key sequence (in normal mode)
+    pub fn parse_sequence(first: char, key: Key) -> Result<Self, &'static str> {
+        match (first, key) {
+            // Hunks
+            (']', Key::Char('c')) => Ok(Command::NextHunk),
+            ('[', Key::Char('c')) => Ok(Command::PreviousHunk),
+            ('g', Key::Char('p')) => Ok(Command::PreviewHunk),
+            ('g', Key::Char('r')) => Ok(Command::RevertHunk),
+            ('g', Key::Char('s')) => Ok(Command::StageHunk),
+            ('g', Key::Char('u')) => Ok(Command::UnstageHunk),
+            // Blame
+            ('g', Key::Char('b')) => Ok(Command::ToggleBlame),
+            ('g', Key::Char('m')) => Ok(Command::ShowCommit),
+            // History
+            ('g', Key::Char('l')) => Ok(Command::OpenHistory),
+            // Branches
+            ('g', Key::Char('B')) => Ok(Command::OpenBranches),
+            // Side-by-side diff
+            ('g', Key::Char('d')) => Ok(Command::SideBySide),
+            // Merge conflicts
+            (']', Key::Char('n')) => Ok(Command::NextConflict),
+            ('[', Key::Char('n')) => Ok(Command::PreviousConflict),
+            ('c', Key::Char('o')) => Ok(Command::ResolveConflict(Resolution::Ours)),
+            ('c', Key::Char('t')) => Ok(Command::ResolveConflict(Resolution::Theirs)),
+            ('c', Key::Char('b')) => Ok(Command::ResolveConflict(Resolution::Both)),
+            ('g', Key::Char('c')) => Ok(Command::ThreeWay),
+            _ => Err("Invalid command"),
+        }
+    }
+
+    /// Parse a command in visual mode from a termion::event::Key object
+    fn parse_visual_mode(key: Key) -> Result<Self, &'static str> {
+        match key {
+            // Go back to normal mode
+            Key::Esc | Key::Char('V') => Ok(Command::ToggleVisual),
+            // Extend the selection
+            Key::Char('j') | Key::Down => Ok(Command::Move(0, 1)),
+            Key::Char('k') | Key::Up => Ok(Command::Move(0, -1)),
+            // Stage or unstage the selection (gs/gu)
+            Key::Char('g') => Ok(Command::Pending('g')),
+            _ => Err("Invalid command"),
+        }
+    }
+
+    /// Parse a command in explorer mode from a termion::event::Key object
+    fn parse_explorer_mode(key: Key) -> Result<Self, &'static str> {
+        match key {
+            // Quit
+            Key::Char('q') => Ok(Command::Quit),
+            // Move
+            Key::Char('j') | Key::Down => Ok(Command::Move(0, 1)),
+            Key::Char('k') | Key::Up => Ok(Command::Move(0, -1)),
+            // Open/Expand and collapse
+            Key::Char('\n') | Key::Char('l') | Key::Right => Ok(Command::ExplorerOpen),
+            Key::Char('h') | Key::Left => Ok(Command::ExplorerCollapse),
+            // File operations
+            Key::Char('a') => Ok(Command::ExplorerCreate),
+            Key::Char('r') => Ok(Command::ExplorerRename),
+            Key::Char('m') => Ok(Command::ExplorerMove),
+            Key::Char('d') => Ok(Command::ExplorerDelete),
+            _ => Err("Invalid command"),
+        }
+    }
+
+    /// Parse a command in history mode from a termion::event::Key object
+    fn parse_history_mode(key: Key) -> Result<Self, &'static str> {
         match key {
-            Key::Backspace => Ok(Command::Rename(None)),
-            Key::Char('\n') => Ok(Command::ToggleMode),
-            Key::Char(c) => Ok(Command::Rename(Some(c))),
+            // Go back
+            Key::Char('q') | Key::Esc => Ok(Command::HistoryClose),
+            // Move
+            Key::Char('j') | Key::Down => Ok(Command::Move(0, 1)),
+            Key::Char('k') | Key::Up => Ok(Command::Move(0, -1)),
+            Key::Char('h') | Key::Left => Ok(Command::Move(-1, 0)),
+            Key::Char('l') | Key::Right => Ok(Command::Move(1, 0)),
+            // Browse the revisions
+            Key::Char('\n') => Ok(Command::HistoryOpen),
+            Key::Char('[') => Ok(Command::HistoryOlder),
+            Key::Char(']') => Ok(Command::HistoryNewer),
+            Key::Char('d') => Ok(Command::HistoryDiff),
+            Key::Char('D') => Ok(Command::SideBySide),
+            _ => Err("Invalid command"),
+        }
+    }
+
+    /// Parse a command in branches mode from a termion::event::Key object
+    fn parse_branches_mode(key: Key) -> Result<Self, &'static str> {
+        match key {
+            // Go back
+            Key::Char('q') | Key::Esc => Ok(Command::BranchesClose),
+            // Move
+            Key::Char('j') | Key::Down => Ok(Command::Move(0, 1)),
+            Key::Char('k') | Key::Up => Ok(Command::Move(0, -1)),
+            // Check out or create a branch
+            Key::Char('\n') => Ok(Command::BranchCheckout),
+            Key::Char('n') => Ok(Command::BranchCreate),
+            _ => Err("Invalid command"),
+        }
+    }
+
+    /// Parse a command in stashes mode from a termion::event::Key object
+    fn parse_stashes_mode(key: Key) -> Result<Self, &'static str> {
+        match key {
+            // Go back
+            Key::Char('q') | Key::Esc => Ok(Command::StashesClose),
+            // Move
+            Key::Char('j') | Key::Down => Ok(Command::Move(0, 1)),
+            Key::Char('k') | Key::Up => Ok(Command::Move(0, -1)),
+            Key::Char('h') | Key::Left => Ok(Command::Move(-1, 0)),
+            Key::Char('l') | Key::Right => Ok(Command::Move(1, 0)),
+            // Show or pop a stash
+            Key::Char('\n') => Ok(Command::StashOpen),
+            Key::Char('p') => Ok(Command::StashPop),
+            _ => Err("Invalid command"),
+        }
+    }
+
+    /// Parse a command in side-by-side diff mode from a termion::event::Key object
+    fn parse_diff_mode(key: Key) -> Result<Self, &'static str> {
+        match key {
+            // Go back
+            Key::Char('q') | Key::Esc => Ok(Command::CloseSideBySide),
+            // Move
+            Key::Char('j') | Key::Down => Ok(Command::Move(0, 1)),
+            Key::Char('k') | Key::Up => Ok(Command::Move(0, -1)),
+            Key::Char('h') | Key::Left => Ok(Command::Move(-1, 0)),
+            Key::Char('l') | Key::Right => Ok(Command::Move(1, 0)),
+            // Changes
+            Key::Char(']') => Ok(Command::NextHunk),
+            Key::Char('[') => Ok(Command::PreviousHunk),
+            _ => Err("Invalid command"),
+        }
+    }
+
+    /// Parse a command in prompt mode from a termion::event::Key object
+    fn parse_prompt_mode(key: Key) -> Result<Self, &'static str> {
+        match key {
+            Key::Esc => Ok(Command::PromptCancel),
+            Key::Char('\n') => Ok(Command::PromptSubmit),
+            Key::Char('\t') => Ok(Command::PromptComplete),
+            Key::Backspace => Ok(Command::PromptInput(None)),
+            Key::Char(c) => Ok(Command::PromptInput(Some(c))),
             _ => Err("Invalid command"),
         }
     }
@@ -147,6 +444,7 @@ impl Command {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::editor::prompt::{Prompt, PromptKind};
 
     #[test]
     fn parse_normal_mode() {
@@ -194,6 +492,102 @@ mod tests {
             Command::parse(Key::Char('i'), &Mode::Normal),
             Ok(Command::ToggleMode)
         );
+        assert_eq!(
+            Command::parse(Key::Char('B'), &Mode::Normal),
+            Ok(Command::ChangeDiffBase)
+        );
+        assert_eq!(
+            Command::parse(Key::Char(':'), &Mode::Normal),
+            Ok(Command::OpenCommandLine)
+        );
+        assert_eq!(
+            Command::parse(Key::Ctrl('a'), &Mode::Normal),
+            Ok(Command::NextRebaseAction)
+        );
+        assert_eq!(
+            Command::parse(Key::Ctrl('x'), &Mode::Normal),
+            Ok(Command::PreviousRebaseAction)
+        );
+        assert_eq!(
+            Command::parse(Key::Ctrl('z'), &Mode::Insert),
+            Ok(Command::Suspend)
+        );
+    }
+
+    #[test]
+    fn parse_sequence() {
+        assert_eq!(
+            Command::parse(Key::Char(']'), &Mode::Normal),
+            Ok(Command::Pending(']'))
+        );
+        assert_eq!(
+            Command::parse_sequence(']', Key::Char('c')),
+            Ok(Command::NextHunk)
+        );
+        assert_eq!(
+            Command::parse_sequence('[', Key::Char('c')),
+            Ok(Command::PreviousHunk)
+        );
+        assert_eq!(
+            Command::parse_sequence('g', Key::Char('p')),
+            Ok(Command::PreviewHunk)
+        );
+        assert_eq!(
+            Command::parse_sequence('g', Key::Char('r')),
+            Ok(Command::RevertHunk)
+        );
+        assert_eq!(
+            Command::parse_sequence('g', Key::Char('s')),
+            Ok(Command::StageHunk)
+        );
+        assert_eq!(
+            Command::parse_sequence('g', Key::Char('u')),
+            Ok(Command::UnstageHunk)
+        );
+        assert_eq!(
+            Command::parse_sequence('g', Key::Char('b')),
+            Ok(Command::ToggleBlame)
+        );
+        assert_eq!(
+            Command::parse_sequence('g', Key::Char('m')),
+            Ok(Command::ShowCommit)
+        );
+        assert_eq!(
+            Command::parse_sequence(']', Key::Char('x')),
+            Err("Invalid command")
+        );
+    }
+
+    #[test]
+    fn parse_conflict_sequence() {
+        assert_eq!(
+            Command::parse(Key::Char('c'), &Mode::Normal),
+            Ok(Command::Pending('c'))
+        );
+        assert_eq!(
+            Command::parse_sequence(']', Key::Char('n')),
+            Ok(Command::NextConflict)
+        );
+        assert_eq!(
+            Command::parse_sequence('[', Key::Char('n')),
+            Ok(Command::PreviousConflict)
+        );
+        assert_eq!(
+            Command::parse_sequence('c', Key::Char('o')),
+            Ok(Command::ResolveConflict(Resolution::Ours))
+        );
+        assert_eq!(
+            Command::parse_sequence('c', Key::Char('t')),
+            Ok(Command::ResolveConflict(Resolution::Theirs))
+        );
+        assert_eq!(
+            Command::parse_sequence('c', Key::Char('b')),
+            Ok(Command::ResolveConflict(Resolution::Both))
+        );
+        assert_eq!(
+            Command::parse_sequence('g', Key::Char('c')),
+            Ok(Command::ThreeWay)
+        );
     }
 
     #[test]
@@ -273,23 +667,222 @@ mod tests {
         );
     }
 
+    #[test]
+    fn parse_visual_mode() {
+        assert_eq!(
+            Command::parse(Key::Char('V'), &Mode::Normal),
+            Ok(Command::ToggleVisual)
+        );
+        assert_eq!(
+            Command::parse(Key::Esc, &Mode::Visual),
+            Ok(Command::ToggleVisual)
+        );
+        assert_eq!(
+            Command::parse(Key::Char('j'), &Mode::Visual),
+            Ok(Command::Move(0, 1))
+        );
+        assert_eq!(
+            Command::parse(Key::Char('g'), &Mode::Visual),
+            Ok(Command::Pending('g'))
+        );
+        assert_eq!(
+            Command::parse(Key::Char('i'), &Mode::Visual),
+            Err("Invalid command")
+        );
+    }
+
+    #[test]
+    fn parse_explorer_mode() {
+        assert_eq!(
+            Command::parse(Key::Char('\n'), &Mode::Explorer),
+            Ok(Command::ExplorerOpen)
+        );
+        assert_eq!(
+            Command::parse(Key::Char('h'), &Mode::Explorer),
+            Ok(Command::ExplorerCollapse)
+        );
+        assert_eq!(
+            Command::parse(Key::Char('j'), &Mode::Explorer),
+            Ok(Command::Move(0, 1))
+        );
+        assert_eq!(
+            Command::parse(Key::Char('a'), &Mode::Explorer),
+            Ok(Command::ExplorerCreate)
+        );
+        assert_eq!(
+            Command::parse(Key::Char('-'), &Mode::Normal),
+            Ok(Command::OpenExplorer)
+        );
+    }
+
+    #[test]
+    fn parse_history_mode() {
+        assert_eq!(
+            Command::parse_sequence('g', Key::Char('l')),
+            Ok(Command::OpenHistory)
+        );
+        assert_eq!(
+            Command::parse(Key::Char('\n'), &Mode::History),
+            Ok(Command::HistoryOpen)
+        );
+        assert_eq!(
+            Command::parse(Key::Char('['), &Mode::History),
+            Ok(Command::HistoryOlder)
+        );
+        assert_eq!(
+            Command::parse(Key::Char(']'), &Mode::History),
+            Ok(Command::HistoryNewer)
+        );
+        assert_eq!(
+            Command::parse(Key::Char('d'), &Mode::History),
+            Ok(Command::HistoryDiff)
+        );
+        assert_eq!(
+            Command::parse(Key::Esc, &Mode::History),
+            Ok(Command::HistoryClose)
+        );
+        assert_eq!(
+            Command::parse(Key::Char('i'), &Mode::History),
+            Err("Invalid command")
+        );
+    }
+
+    #[test]
+    fn parse_branches_mode() {
+        assert_eq!(
+            Command::parse_sequence('g', Key::Char('B')),
+            Ok(Command::OpenBranches)
+        );
+        assert_eq!(
+            Command::parse(Key::Char('\n'), &Mode::Branches),
+            Ok(Command::BranchCheckout)
+        );
+        assert_eq!(
+            Command::parse(Key::Char('n'), &Mode::Branches),
+            Ok(Command::BranchCreate)
+        );
+        assert_eq!(
+            Command::parse(Key::Char('q'), &Mode::Branches),
+            Ok(Command::BranchesClose)
+        );
+        assert_eq!(
+            Command::parse(Key::Char('i'), &Mode::Branches),
+            Err("Invalid command")
+        );
+    }
+
+    #[test]
+    fn parse_command_line() {
+        assert_eq!(Command::parse_command_line("w"), Ok(Command::Save));
+        assert_eq!(
+            Command::parse_command_line(" wq "),
+            Ok(Command::CommandBlock(vec![Command::Save, Command::Quit]))
+        );
+        assert_eq!(Command::parse_command_line("cq"), Ok(Command::Abort));
+        assert_eq!(
+            Command::parse_command_line("Gstash pop"),
+            Ok(Command::StashPopLatest)
+        );
+        assert_eq!(
+            Command::parse_command_line("%s/a/b/g"),
+            Ok(Command::Substitute(
+                Substitution::parse("%s/a/b/g").unwrap()
+            ))
+        );
+        assert_eq!(
+            Command::parse_command_line("s/a"),
+            Err("Missing replacement")
+        );
+        assert_eq!(
+            Command::parse_command_line("nope"),
+            Err("Not an editor command")
+        );
+    }
+
+    #[test]
+    fn parse_stashes_mode() {
+        assert_eq!(
+            Command::parse(Key::Char('\n'), &Mode::Stashes),
+            Ok(Command::StashOpen)
+        );
+        assert_eq!(
+            Command::parse(Key::Char('p'), &Mode::Stashes),
+            Ok(Command::StashPop)
+        );
+        assert_eq!(
+            Command::parse(Key::Esc, &Mode::Stashes),
+            Ok(Command::StashesClose)
+        );
+        assert_eq!(
+            Command::parse(Key::Char('i'), &Mode::Stashes),
+            Err("Invalid command")
+        );
+    }
+
+    #[test]
+    fn parse_diff_mode() {
+        assert_eq!(
+            Command::parse_sequence('g', Key::Char('d')),
+            Ok(Command::SideBySide)
+        );
+        assert_eq!(
+            Command::parse(Key::Char('D'), &Mode::History),
+            Ok(Command::SideBySide)
+        );
+        assert_eq!(
+            Command::parse(Key::Char(']'), &Mode::Diff),
+            Ok(Command::NextHunk)
+        );
+        assert_eq!(
+            Command::parse(Key::Char('q'), &Mode::Diff),
+            Ok(Command::CloseSideBySide)
+        );
+        assert_eq!(
+            Command::parse(Key::Char('i'), &Mode::Diff),
+            Err("Invalid command")
+        );
+    }
+
+    #[test]
+    fn parse_prompt_mode() {
+        let mode = Mode::Prompt(Prompt::new(PromptKind::ExplorerCreate(0), "", ""));
+        assert_eq!(
+            Command::parse(Key::Char('a'), &mode),
+            Ok(Command::PromptInput(Some('a')))
+        );
+        assert_eq!(
+            Command::parse(Key::Backspace, &mode),
+            Ok(Command::PromptInput(None))
+        );
+        assert_eq!(
+            Command::parse(Key::Char('\n'), &mode),
+            Ok(Command::PromptSubmit)
+        );
+        assert_eq!(Command::parse(Key::Esc, &mode), Ok(Command::PromptCancel));
+    }
+
     #[test]
     fn parse_rename_mode() {
+        let mode = Mode::Prompt(Prompt::new(PromptKind::Rename, "", ""));
         assert_eq!(
-            Command::parse(Key::Backspace, &Mode::Rename),
-            Ok(Command::Rename(None))
+            Command::parse(Key::Char('R'), &Mode::Normal),
+            Ok(Command::ToggleRename)
         );
         assert_eq!(
-            Command::parse(Key::Char('a'), &Mode::Rename),
-            Ok(Command::Rename(Some('a')))
+            Command::parse(Key::Backspace, &mode),
+            Ok(Command::PromptInput(None))
         );
         assert_eq!(
-            Command::parse(Key::Char('b'), &Mode::Rename),
-            Ok(Command::Rename(Some('b')))
+            Command::parse(Key::Char('a'), &mode),
+            Ok(Command::PromptInput(Some('a')))
         );
         assert_eq!(
-            Command::parse(Key::Char('\n'), &Mode::Rename),
-            Ok(Command::ToggleMode)
+            Command::parse(Key::Char('\t'), &mode),
+            Ok(Command::PromptComplete)
+        );
+        assert_eq!(
+            Command::parse(Key::Char('\n'), &mode),
+            Ok(Command::PromptSubmit)
         );
     }
 }
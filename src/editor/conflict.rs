@@ -0,0 +1,228 @@
+//! # Merge conflicts
+//!
+//! When a merge (or a rebase, a cherry-pick...) stops on a conflict, git writes both
+//! sides of each conflicting region in the file between markers:
+//! ```text
+//! <<<<<<< HEAD
+//! our version
+//! ||||||| base
+//! common ancestor (only with `merge.conflictStyle = diff3`)
+//! =======
+//! their version
+//! >>>>>>> branch
+//! ```
+//! This module finds these regions in the buffer, highlights them and computes the
+//! text replacing a region once the user has chosen how to resolve it.
+
+use std::ops::Range;
+
+use super::view::{Highlight, Highlights};
+
+/// Which side of a conflict to keep
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Resolution {
+    Ours,
+    Theirs,
+    /// Ours followed by theirs
+    Both,
+}
+
+/// A conflicting region of the buffer (lines of its markers, 0-indexed)
+#[derive(Clone, Debug, PartialEq)]
+pub struct Conflict {
+    /// Line of the `<<<<<<<` marker
+    pub start: usize,
+    /// Line of the `|||||||` marker (diff3 style only)
+    pub base: Option<usize>,
+    /// Line of the `=======` marker
+    pub separator: usize,
+    /// Line of the `>>>>>>>` marker
+    pub end: usize,
+}
+
+impl Conflict {
+    /// Lines of our side
+    pub fn ours(&self) -> Range<usize> {
+        self.start + 1..self.base.unwrap_or(self.separator)
+    }
+
+    /// Lines of the common ancestor (empty without diff3 style)
+    pub fn base_lines(&self) -> Range<usize> {
+        match self.base {
+            Some(base) => base + 1..self.separator,
+            None => self.separator..self.separator,
+        }
+    }
+
+    /// Lines of their side
+    pub fn theirs(&self) -> Range<usize> {
+        self.separator + 1..self.end
+    }
+
+    /// Number of lines of the region, markers included
+    pub fn len(&self) -> usize {
+        self.end + 1 - self.start
+    }
+
+    /// Whether the given line is part of the region
+    pub fn contains(&self, line: usize) -> bool {
+        (self.start..=self.end).contains(&line)
+    }
+
+    /// Text replacing the whole region for the given resolution
+    pub fn resolve(&self, lines: &[&str], resolution: Resolution) -> String {
+        let text = |range: Range<usize>| {
+            lines[range]
+                .iter()
+                .map(|line| format!("{}\n", line))
+                .collect::<String>()
+        };
+        match resolution {
+            Resolution::Ours => text(self.ours()),
+            Resolution::Theirs => text(self.theirs()),
+            Resolution::Both => text(self.ours()) + &text(self.theirs()),
+        }
+    }
+}
+
+/// Whether the line is the given conflict marker: the marker character seven times,
+/// then the end of the line or a space (before a label such as `HEAD`). The separator
+/// has no label. Longer runs (Markdown and RST underlines) are not markers.
+fn is_marker(line: &str, c: char) -> bool {
+    let Some(rest) = line.strip_prefix(&c.to_string().repeat(7)) else {
+        return false;
+    };
+    match c {
+        '=' => rest.is_empty(),
+        _ => rest.is_empty() || rest.starts_with(' '),
+    }
+}
+
+/// Find the conflicting regions of a buffer. Incomplete regions are ignored.
+pub fn find_conflicts(lines: &[&str]) -> Vec<Conflict> {
+    let mut conflicts = vec![];
+    let (mut start, mut base, mut separator) = (None, None, None);
+    for (index, line) in lines.iter().enumerate() {
+        if is_marker(line, '<') {
+            (start, base, separator) = (Some(index), None, None);
+        } else if is_marker(line, '|') && start.is_some() && separator.is_none() {
+            base = Some(index);
+        } else if is_marker(line, '=') && start.is_some() && separator.is_none() {
+            separator = Some(index);
+        } else if is_marker(line, '>') {
+            if let (Some(start), Some(separator)) = (start, separator) {
+                conflicts.push(Conflict {
+                    start,
+                    base,
+                    separator,
+                    end: index,
+                });
+            }
+            (start, base, separator) = (None, None, None);
+        }
+    }
+    conflicts
+}
+
+/// Highlight the markers and the sides of the conflicting regions
+pub fn highlights(conflicts: &[Conflict]) -> Highlights {
+    let mut highlights = Highlights::new();
+    let whole_line = |style| vec![(0..usize::MAX, style)];
+    for conflict in conflicts {
+        for marker in [
+            Some(conflict.start),
+            conflict.base,
+            Some(conflict.separator),
+            Some(conflict.end),
+        ]
+        .into_iter()
+        .flatten()
+        {
+            highlights.insert(marker, whole_line(Highlight::ConflictMarker));
+        }
+        for (lines, style) in [
+            (conflict.ours(), Highlight::Ours),
+            (conflict.base_lines(), Highlight::Base),
+            (conflict.theirs(), Highlight::Theirs),
+        ] {
+            for line in lines {
+                highlights.insert(line, whole_line(style));
+            }
+        }
+    }
+    highlights
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str =
+        "a\n<<<<<<< HEAD\nours\n||||||| base\nbase\n=======\ntheirs\n>>>>>>> other\nb";
+
+    #[test]
+    fn find_conflict_regions() {
+        let lines = TEXT.lines().collect::<Vec<_>>();
+        let conflicts = find_conflicts(&lines);
+        assert_eq!(
+            conflicts,
+            vec![Conflict {
+                start: 1,
+                base: Some(3),
+                separator: 5,
+                end: 7
+            }]
+        );
+        assert_eq!(conflicts[0].ours(), 2..3);
+        assert_eq!(conflicts[0].base_lines(), 4..5);
+        assert_eq!(conflicts[0].theirs(), 6..7);
+        assert_eq!(conflicts[0].len(), 7);
+
+        // Incomplete region
+        assert_eq!(find_conflicts(&["<<<<<<< HEAD", "ours", "======="]), vec![]);
+    }
+
+    #[test]
+    fn markdown_underlines_are_not_markers() {
+        let text = "<<<<<<< HEAD\nTitle\n========\nours\n=======\ntheirs\n>>>>>>>>\n>>>>>>> other";
+        let lines = text.lines().collect::<Vec<_>>();
+        let conflicts = find_conflicts(&lines);
+        assert_eq!(
+            conflicts,
+            vec![Conflict {
+                start: 0,
+                base: None,
+                separator: 4,
+                end: 7
+            }]
+        );
+        let ours = conflicts[0].resolve(&lines, Resolution::Ours);
+        assert_eq!(ours, "Title\n========\nours\n");
+        assert_eq!(
+            find_conflicts(&["<<<<<<<<", "a", "=======", "b", ">>>>>>>"]),
+            vec![]
+        );
+        assert!(!is_marker("======= x", '='));
+        assert!(is_marker(">>>>>>>", '>'));
+    }
+
+    #[test]
+    fn resolve_conflict() {
+        let lines = TEXT.lines().collect::<Vec<_>>();
+        let conflict = &find_conflicts(&lines)[0];
+        assert_eq!(conflict.resolve(&lines, Resolution::Ours), "ours\n");
+        assert_eq!(conflict.resolve(&lines, Resolution::Theirs), "theirs\n");
+        assert_eq!(conflict.resolve(&lines, Resolution::Both), "ours\ntheirs\n");
+    }
+
+    #[test]
+    fn conflict_highlights() {
+        let lines = TEXT.lines().collect::<Vec<_>>();
+        let highlights = highlights(&find_conflicts(&lines));
+        assert_eq!(highlights.get(&0), None);
+        assert_eq!(highlights[&1][0].1, Highlight::ConflictMarker);
+        assert_eq!(highlights[&2][0].1, Highlight::Ours);
+        assert_eq!(highlights[&4][0].1, Highlight::Base);
+        assert_eq!(highlights[&6][0].1, Highlight::Theirs);
+    }
+}
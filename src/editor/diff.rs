@@ -0,0 +1,389 @@
+//! # Side-by-side diff
+//!
+//! Two buffers (the file and HEAD, two revisions or two files given on the command
+//! line) are compared line by line and displayed in columns of a scratch view.
+//! Filler rows are inserted so that the lines of each hunk stay aligned, which keeps
+//! the scrolling of the columns synchronized. Within changed lines, the words that
+//! differ are highlighted.
+//!
+//! The three versions of a conflicted file (ours, the common ancestor and theirs) are
+//! displayed the same way, ours and theirs being both aligned on the ancestor.
+
+use std::ops::Range;
+
+use super::view::{file::git::changed_lines, Highlight, Highlights};
+
+/// Separator between the columns
+const SEPARATOR: &str = " │ ";
+/// Character used to fill the missing lines of a column
+const FILLER: char = '╱';
+
+/// Kind of a row of a two-way diff
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RowKind {
+    /// Same line on both sides
+    Equal,
+    /// A line of the left buffer replaced by a line of the right buffer
+    Changed,
+    /// A line only in the left buffer
+    Deleted,
+    /// A line only in the right buffer
+    Added,
+}
+
+/// A row of a two-way diff: a line of each buffer (`None` for a filler line)
+#[derive(Debug, PartialEq)]
+pub struct Row {
+    pub left: Option<String>,
+    pub right: Option<String>,
+    pub kind: RowKind,
+}
+
+/// A line of a column, `None` for a filler line
+#[derive(Debug, PartialEq)]
+struct Cell {
+    line: Option<String>,
+    /// Style of the whole cell
+    style: Option<Highlight>,
+    /// Characters that differ from the other version of the line
+    words: Option<Range<usize>>,
+}
+
+/// Buffers aligned row by row, displayed in columns
+pub struct SideBySide {
+    /// Names of the buffers, one per column
+    titles: Vec<String>,
+    rows: Vec<Vec<Cell>>,
+}
+
+impl SideBySide {
+    /// Align the lines of two buffers
+    pub fn new(left: &str, right: &str, titles: (&str, &str)) -> Result<Self, git2::Error> {
+        let rows = align(left, right)?
+            .into_iter()
+            .map(|row| {
+                let (left_style, right_style) = match row.kind {
+                    RowKind::Equal => (None, None),
+                    RowKind::Changed => (Some(Highlight::Changed), Some(Highlight::Changed)),
+                    RowKind::Deleted => (Some(Highlight::Deleted), Some(Highlight::Filler)),
+                    RowKind::Added => (Some(Highlight::Filler), Some(Highlight::Added)),
+                };
+                let (left_words, right_words) = match (&row.left, &row.right, row.kind) {
+                    (Some(left), Some(right), RowKind::Changed) => {
+                        let (left, right) = changed_words(left, right);
+                        (Some(left), Some(right))
+                    }
+                    _ => (None, None),
+                };
+                vec![
+                    Cell {
+                        line: row.left,
+                        style: left_style,
+                        words: left_words,
+                    },
+                    Cell {
+                        line: row.right,
+                        style: right_style,
+                        words: right_words,
+                    },
+                ]
+            })
+            .collect();
+
+        Ok(Self {
+            titles: vec![titles.0.into(), titles.1.into()],
+            rows,
+        })
+    }
+
+    /// Align ours and theirs on their common ancestor (in this order: ours, base, theirs)
+    pub fn three_way(
+        base: &str,
+        ours: &str,
+        theirs: &str,
+        titles: (&str, &str, &str),
+    ) -> Result<Self, git2::Error> {
+        let (ours, theirs) = (align(base, ours)?, align(base, theirs)?);
+        let cell = |line: Option<&String>, changed: bool, style| Cell {
+            line: line.cloned(),
+            style: (changed || line.is_none()).then_some(style),
+            words: None,
+        };
+        let (mut i, mut j) = (0, 0);
+        let mut rows = vec![];
+        while i < ours.len() || j < theirs.len() {
+            // Lines inserted on one side face filler lines on the two other columns
+            let (our_row, their_row) = (ours.get(i), theirs.get(j));
+            let (our_row, their_row) = match (our_row, their_row) {
+                (Some(row), _) if row.left.is_none() => {
+                    i += 1;
+                    (Some(row), None)
+                }
+                (_, Some(row)) if row.left.is_none() => {
+                    j += 1;
+                    (None, Some(row))
+                }
+                (our_row, their_row) => {
+                    i += 1;
+                    j += 1;
+                    (our_row, their_row)
+                }
+            };
+            let changed = |row: Option<&Row>| row.is_some_and(|row| row.kind != RowKind::Equal);
+            let base = our_row.or(their_row).and_then(|row| row.left.as_ref());
+            let base_changed = changed(our_row) || changed(their_row);
+            rows.push(vec![
+                cell(
+                    our_row.and_then(|row| row.right.as_ref()),
+                    changed(our_row),
+                    Highlight::Ours,
+                ),
+                cell(base, base_changed, Highlight::Base),
+                cell(
+                    their_row.and_then(|row| row.right.as_ref()),
+                    changed(their_row),
+                    Highlight::Theirs,
+                ),
+            ]);
+        }
+
+        Ok(Self {
+            titles: vec![titles.0.into(), titles.1.into(), titles.2.into()],
+            rows,
+        })
+    }
+
+    /// Name of the buffer displaying the diff
+    pub fn title(&self) -> String {
+        self.titles.join(" ↔ ")
+    }
+
+    /// First row of each group of changed rows
+    pub fn change_starts(&self) -> Vec<usize> {
+        let changed = |row: &Vec<Cell>| row.iter().any(|cell| cell.style.is_some());
+        (0..self.rows.len())
+            .filter(|&i| changed(&self.rows[i]) && (i == 0 || !changed(&self.rows[i - 1])))
+            .collect()
+    }
+
+    /// Render the columns in the given width, with the highlights of each line
+    pub fn render(&self, width: usize) -> (String, Highlights) {
+        let separator = SEPARATOR.chars().count();
+        let count = self.titles.len();
+        let column = width.saturating_sub(separator * (count - 1)) / count;
+        let mut highlights = Highlights::new();
+
+        let lines = self
+            .rows
+            .iter()
+            .enumerate()
+            .map(|(index, row)| {
+                let mut spans = vec![];
+                for (i, cell) in row.iter().enumerate() {
+                    let offset = i * (column + separator);
+                    if let Some(style) = cell.style {
+                        spans.push((offset..offset + column, style));
+                    }
+                    if let Some(words) = &cell.words {
+                        spans.push((clip(words.clone(), column, offset), Highlight::ChangedText));
+                    }
+                }
+                highlights.insert(index, spans);
+
+                row.iter()
+                    .map(|cell| render_cell(cell.line.as_deref(), column))
+                    .collect::<Vec<_>>()
+                    .join(SEPARATOR)
+                    .trim_end()
+                    .to_string()
+            })
+            .collect::<Vec<_>>();
+        (lines.join("\n"), highlights)
+    }
+}
+
+/// Align the lines of two buffers: lines of each hunk are paired while both sides
+/// have some, the remaining lines face filler lines
+fn align(left: &str, right: &str) -> Result<Vec<Row>, git2::Error> {
+    let left_lines = left.lines().collect::<Vec<_>>();
+    let right_lines = right.lines().collect::<Vec<_>>();
+    let line = |lines: &[&str], index: usize| lines.get(index).map(|line| line.to_string());
+
+    let mut rows = vec![];
+    let (mut l, mut r) = (0, 0);
+    let push_equal = |rows: &mut Vec<Row>, l: &mut usize, r: &mut usize, until: usize| {
+        while *r < until {
+            rows.push(Row {
+                left: line(&left_lines, *l),
+                right: line(&right_lines, *r),
+                kind: RowKind::Equal,
+            });
+            *l += 1;
+            *r += 1;
+        }
+    };
+    for (old, new) in changed_lines(left, right)? {
+        push_equal(&mut rows, &mut l, &mut r, new.start);
+        for i in 0..old.len().max(new.len()) {
+            let (left, right) = (
+                (i < old.len())
+                    .then(|| line(&left_lines, old.start + i))
+                    .flatten(),
+                (i < new.len())
+                    .then(|| line(&right_lines, new.start + i))
+                    .flatten(),
+            );
+            let kind = match (&left, &right) {
+                (Some(_), Some(_)) => RowKind::Changed,
+                (Some(_), None) => RowKind::Deleted,
+                _ => RowKind::Added,
+            };
+            rows.push(Row { left, right, kind });
+        }
+        (l, r) = (old.end, new.end);
+    }
+    push_equal(&mut rows, &mut l, &mut r, right_lines.len());
+    Ok(rows)
+}
+
+/// Content of a column: the line cut or padded to the width, or a filler line
+fn render_cell(line: Option<&str>, width: usize) -> String {
+    match line {
+        Some(line) => {
+            let line = line.chars().take(width).collect::<String>();
+            let padding = width - line.chars().count();
+            line + &" ".repeat(padding)
+        }
+        None => FILLER.to_string().repeat(width),
+    }
+}
+
+/// Shift a range of characters of a column to its position in the row, cutting it to the column
+fn clip(range: Range<usize>, width: usize, offset: usize) -> Range<usize> {
+    offset + range.start.min(width)..offset + range.end.min(width)
+}
+
+/// Character ranges of the words of a line (runs of alphanumeric characters, or single
+/// other characters)
+fn words(line: &str) -> Vec<Range<usize>> {
+    let chars = line.chars().collect::<Vec<_>>();
+    let mut words = vec![];
+    let mut start = 0;
+    while start < chars.len() {
+        let mut end = start + 1;
+        if chars[start].is_alphanumeric() || chars[start] == '_' {
+            while end < chars.len() && (chars[end].is_alphanumeric() || chars[end] == '_') {
+                end += 1;
+            }
+        }
+        words.push(start..end);
+        start = end;
+    }
+    words
+}
+
+/// Character ranges of the words that differ between two versions of a line: the
+/// words between the common leading words and the common trailing words
+pub fn changed_words(left: &str, right: &str) -> (Range<usize>, Range<usize>) {
+    let (left_chars, right_chars) = (
+        left.chars().collect::<Vec<_>>(),
+        right.chars().collect::<Vec<_>>(),
+    );
+    let (left_words, right_words) = (words(left), words(right));
+    let same = |a: &Range<usize>, b: &Range<usize>| left_chars[a.clone()] == right_chars[b.clone()];
+
+    let prefix = left_words
+        .iter()
+        .zip(&right_words)
+        .take_while(|(a, b)| same(a, b))
+        .count();
+    let suffix = left_words[prefix..]
+        .iter()
+        .rev()
+        .zip(right_words[prefix..].iter().rev())
+        .take_while(|(a, b)| same(a, b))
+        .count();
+
+    let range = |words: &[Range<usize>], len: usize| {
+        let start = words.get(prefix).map_or(len, |word| word.start);
+        let end = match suffix {
+            0 => len,
+            _ => words[words.len() - suffix].start,
+        };
+        start..end.max(start)
+    };
+    (
+        range(&left_words, left_chars.len()),
+        range(&right_words, right_chars.len()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn align_hunks() {
+        let rows = align("a\nb\nc\nd\n", "a\nB\nc\nnew\nd\n").unwrap();
+        let kinds = rows.iter().map(|row| row.kind).collect::<Vec<_>>();
+        use RowKind::*;
+        assert_eq!(kinds, vec![Equal, Changed, Equal, Added, Equal]);
+        assert_eq!(rows[3].left, None);
+
+        let rows = align("a\nb\nc\n", "c\n").unwrap();
+        let kinds = rows.iter().map(|row| row.kind).collect::<Vec<_>>();
+        assert_eq!(kinds, vec![Deleted, Deleted, Equal]);
+        assert_eq!(rows[2].left.as_deref(), Some("c"));
+    }
+
+    #[test]
+    fn side_by_side_render() {
+        let diff = SideBySide::new("a\nb\nc\nd\n", "a\nB\nc\nnew\nd\n", ("old", "new")).unwrap();
+        assert_eq!(diff.title(), "old ↔ new");
+        assert_eq!(diff.change_starts(), vec![1, 3]);
+
+        let (text, highlights) = diff.render(11);
+        assert_eq!(text, "a    │ a\nb    │ B\nc    │ c\n╱╱╱╱ │ new\nd    │ d");
+        assert_eq!(highlights[&0], vec![]);
+        assert_eq!(
+            highlights[&3],
+            vec![(0..4, Highlight::Filler), (7..11, Highlight::Added)]
+        );
+    }
+
+    #[test]
+    fn three_way_render() {
+        let diff = SideBySide::three_way(
+            "a\nb\n",
+            "a\nours\nb\n",
+            "a\nB\n",
+            ("ours", "base", "theirs"),
+        )
+        .unwrap();
+        let (text, highlights) = diff.render(15);
+        assert_eq!(text, "a   │ a   │ a\nour │ ╱╱╱ │ ╱╱╱\nb   │ b   │ B");
+        assert_eq!(
+            highlights[&1],
+            vec![
+                (0..3, Highlight::Ours),
+                (6..9, Highlight::Base),
+                (12..15, Highlight::Theirs)
+            ]
+        );
+        assert_eq!(
+            highlights[&2],
+            vec![(6..9, Highlight::Base), (12..15, Highlight::Theirs)]
+        );
+        assert_eq!(diff.change_starts(), vec![1]);
+    }
+
+    #[test]
+    fn changed_words_in_line() {
+        assert_eq!(
+            changed_words("let a = foo(1);", "let a = bar(1);"),
+            (8..11, 8..11)
+        );
+        assert_eq!(changed_words("same", "same"), (4..4, 4..4));
+        assert_eq!(changed_words("a b", "a b c"), (3..3, 3..5));
+    }
+}
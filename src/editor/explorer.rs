@@ -0,0 +1,366 @@
+//! # Built-in file explorer
+//!
+//! When giga is given a directory instead of a file, it opens a tree explorer buffer.
+//! The `Explorer` keeps track of which directories are expanded and flattens the
+//! visible part of the tree into a list of entries: the nth line of the explorer
+//! buffer is the nth entry. Each entry is decorated with its git status when the
+//! directory lives in a git repository.
+//!
+//! The explorer also performs the file operations requested by the user (creating,
+//! renaming, moving and deleting files), the editor only forwards the user input.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use git2::{Repository, Status, StatusOptions};
+
+/// A visible entry of the explorer tree
+#[derive(Debug, PartialEq)]
+pub struct Entry {
+    /// Path of the entry (relative to the current directory of the process)
+    pub path: PathBuf,
+    /// Depth of the entry in the tree (0 for the children of the root)
+    pub depth: usize,
+    /// Whether the entry is a directory
+    pub is_dir: bool,
+}
+
+/// State of the tree explorer
+pub struct Explorer {
+    /// The directory the explorer was opened on
+    root: PathBuf,
+    /// The set of expanded directories
+    expanded: HashSet<PathBuf>,
+    /// The flattened list of visible entries
+    entries: Vec<Entry>,
+    /// Git status marker of each changed path (canonical paths)
+    statuses: HashMap<PathBuf, char>,
+}
+
+impl Explorer {
+    /// Open an explorer on the given directory
+    pub fn new(root: &str) -> Self {
+        let mut explorer = Self {
+            root: PathBuf::from(root),
+            expanded: HashSet::new(),
+            entries: vec![],
+            statuses: HashMap::new(),
+        };
+        explorer.refresh();
+        explorer
+    }
+
+    /// The directory the explorer was opened on
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Get the entry displayed at the given line
+    pub fn entry(&self, index: usize) -> Option<&Entry> {
+        self.entries.get(index)
+    }
+
+    /// Get the line at which the given path is displayed
+    pub fn position(&self, path: &Path) -> Option<usize> {
+        self.entries.iter().position(|entry| entry.path == path)
+    }
+
+    /// Re-read the directory tree and the git statuses from disk
+    pub fn refresh(&mut self) {
+        self.statuses = Self::read_statuses(&self.root);
+        self.entries = vec![];
+        let root = self.root.clone();
+        self.read_dir(&root, 0);
+    }
+
+    /// Expand or collapse the directory at the given line
+    pub fn toggle(&mut self, index: usize) {
+        let Some(entry) = self.entries.get(index) else {
+            return;
+        };
+        if !entry.is_dir {
+            return;
+        }
+        let path = entry.path.clone();
+        if !self.expanded.remove(&path) {
+            self.expanded.insert(path);
+        }
+        self.refresh();
+    }
+
+    /// Collapse the directory at the given line, or the directory containing it
+    /// if it is a file or an already collapsed directory.
+    /// Returns the line of the collapsed directory.
+    pub fn collapse(&mut self, index: usize) -> Option<usize> {
+        let entry = self.entries.get(index)?;
+        let dir = if entry.is_dir && self.expanded.contains(&entry.path) {
+            entry.path.clone()
+        } else {
+            entry.path.parent()?.to_path_buf()
+        };
+        if !self.expanded.remove(&dir) {
+            return None;
+        }
+        self.refresh();
+        self.position(&dir)
+    }
+
+    /// Create a file (or a directory if the name ends with a `/`) next to the
+    /// entry at the given line, or inside it if it is an expanded directory.
+    pub fn create(&mut self, index: usize, name: &str) -> io::Result<PathBuf> {
+        let path = self.target_dir(index).join(name);
+        if path.exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "File already exists",
+            ));
+        }
+        if name.ends_with('/') {
+            fs::create_dir_all(&path)?;
+        } else {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&path, "")?;
+        }
+        self.reveal(&path);
+        Ok(path)
+    }
+
+    /// Rename the entry at the given line. The new name is relative to the
+    /// directory containing the entry so it can also be used to move it.
+    pub fn rename(&mut self, index: usize, new_name: &str) -> io::Result<PathBuf> {
+        let entry = self.entries.get(index).ok_or(io::ErrorKind::NotFound)?;
+        let parent = entry.path.parent().unwrap_or(&self.root);
+        let destination = parent.join(new_name);
+        let source = entry.path.clone();
+        self.move_path(&source, &destination)?;
+        Ok(destination)
+    }
+
+    /// Move the entry at the given line into the given directory (relative to
+    /// the root of the explorer).
+    pub fn move_to(&mut self, index: usize, dir: &str) -> io::Result<PathBuf> {
+        let entry = self.entries.get(index).ok_or(io::ErrorKind::NotFound)?;
+        let file_name = entry.path.file_name().ok_or(io::ErrorKind::NotFound)?;
+        let destination = self.root.join(dir).join(file_name);
+        let source = entry.path.clone();
+        self.move_path(&source, &destination)?;
+        Ok(destination)
+    }
+
+    /// Delete the entry at the given line (recursively for directories)
+    pub fn delete(&mut self, index: usize) -> io::Result<()> {
+        let entry = self.entries.get(index).ok_or(io::ErrorKind::NotFound)?;
+        if entry.is_dir {
+            fs::remove_dir_all(&entry.path)?;
+        } else {
+            fs::remove_file(&entry.path)?;
+        }
+        self.refresh();
+        Ok(())
+    }
+
+    /// Render the explorer as the content of a buffer (one entry per line)
+    /// ```text
+    /// M ▾ src/
+    /// M     main.rs
+    ///   ▸ tests/
+    /// ? README.md
+    /// ```
+    pub fn render(&self) -> String {
+        self.entries
+            .iter()
+            .map(|entry| {
+                let name = entry.path.file_name().unwrap_or_default().to_string_lossy();
+                let (icon, suffix) = match (entry.is_dir, self.expanded.contains(&entry.path)) {
+                    (true, true) => ("▾ ", "/"),
+                    (true, false) => ("▸ ", "/"),
+                    (false, _) => ("  ", ""),
+                };
+                format!(
+                    "{} {}{}{}{}",
+                    self.status(&entry.path),
+                    "  ".repeat(entry.depth),
+                    icon,
+                    name,
+                    suffix
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Git status marker of the given path (' ' if unchanged or not in a repository)
+    fn status(&self, path: &Path) -> char {
+        fs::canonicalize(path)
+            .ok()
+            .and_then(|path| self.statuses.get(&path).copied())
+            .unwrap_or(' ')
+    }
+
+    /// Directory in which new files are created for the entry at the given line
+    fn target_dir(&self, index: usize) -> PathBuf {
+        match self.entries.get(index) {
+            Some(entry) if entry.is_dir && self.expanded.contains(&entry.path) => {
+                entry.path.clone()
+            }
+            Some(entry) => entry.path.parent().unwrap_or(&self.root).to_path_buf(),
+            None => self.root.clone(),
+        }
+    }
+
+    /// Move a file or a directory, creating the missing parent directories
+    fn move_path(&mut self, source: &Path, destination: &Path) -> io::Result<()> {
+        if destination.exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "File already exists",
+            ));
+        }
+        if let Some(parent) = destination.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::rename(source, destination)?;
+        self.reveal(destination);
+        Ok(())
+    }
+
+    /// Expand all the directories leading to the given path and refresh the tree
+    fn reveal(&mut self, path: &Path) {
+        let mut dir = path.parent();
+        while let Some(d) = dir {
+            if d == self.root || !d.starts_with(&self.root) {
+                break;
+            }
+            self.expanded.insert(d.to_path_buf());
+            dir = d.parent();
+        }
+        self.refresh();
+    }
+
+    /// Recursively list the visible entries of a directory, directories first
+    fn read_dir(&mut self, dir: &Path, depth: usize) {
+        let Ok(read_dir) = fs::read_dir(dir) else {
+            return;
+        };
+        let mut children = read_dir
+            .flatten()
+            .filter(|child| child.file_name() != ".git")
+            .map(|child| (!child.path().is_dir(), child.file_name(), child.path()))
+            .collect::<Vec<_>>();
+        children.sort();
+
+        for (is_file, _, path) in children {
+            let expanded = self.expanded.contains(&path);
+            self.entries.push(Entry {
+                path: path.clone(),
+                depth,
+                is_dir: !is_file,
+            });
+            if !is_file && expanded {
+                self.read_dir(&path, depth + 1);
+            }
+        }
+    }
+
+    /// Read the git status of every changed path of the repository containing `root`.
+    /// Directories containing changes are marked with a `•`.
+    fn read_statuses(root: &Path) -> HashMap<PathBuf, char> {
+        let mut statuses = HashMap::new();
+        let Ok(repo) = Repository::discover(root) else {
+            return statuses;
+        };
+        let Some(workdir) = repo.workdir().and_then(|w| fs::canonicalize(w).ok()) else {
+            return statuses;
+        };
+        let mut options = StatusOptions::new();
+        options.include_untracked(true).recurse_untracked_dirs(true);
+        let Ok(entries) = repo.statuses(Some(&mut options)) else {
+            return statuses;
+        };
+
+        for entry in entries.iter() {
+            let Some(path) = entry.path() else {
+                continue;
+            };
+            let path = workdir.join(path);
+            let mut dir = path.parent();
+            while let Some(d) = dir {
+                if !d.starts_with(&workdir) {
+                    break;
+                }
+                statuses.entry(d.to_path_buf()).or_insert('•');
+                dir = d.parent();
+            }
+            statuses.insert(path, status_marker(entry.status()));
+        }
+        statuses
+    }
+}
+
+/// Single character summary of a git status
+fn status_marker(status: Status) -> char {
+    if status.is_conflicted() {
+        'U'
+    } else if status.is_index_new() {
+        'A'
+    } else if status.is_wt_new() {
+        '?'
+    } else if status.intersects(Status::WT_DELETED | Status::INDEX_DELETED) {
+        'D'
+    } else if status.intersects(Status::WT_RENAMED | Status::INDEX_RENAMED) {
+        'R'
+    } else {
+        'M'
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::editor::test_utils::temp_dir;
+
+    #[test]
+    fn explorer_lists_directories_first() {
+        let dir = temp_dir("explorer-list");
+        fs::write(dir.join("a.txt"), "").unwrap();
+        fs::create_dir(dir.join("z")).unwrap();
+        fs::write(dir.join("z/b.txt"), "").unwrap();
+
+        let mut explorer = Explorer::new(dir.to_str().unwrap());
+        assert_eq!(explorer.render(), "  ▸ z/\n    a.txt");
+
+        explorer.toggle(0);
+        assert_eq!(explorer.render(), "  ▾ z/\n      b.txt\n    a.txt");
+        assert_eq!(explorer.collapse(1), Some(0));
+        assert_eq!(explorer.render(), "  ▸ z/\n    a.txt");
+    }
+
+    #[test]
+    fn explorer_file_operations() {
+        let dir = temp_dir("explorer-ops");
+        let mut explorer = Explorer::new(dir.to_str().unwrap());
+
+        let created = explorer.create(0, "sub/new.txt").unwrap();
+        assert!(created.is_file());
+        assert_eq!(explorer.position(&created), Some(1));
+        assert!(explorer.create(1, "new.txt").is_err());
+
+        let renamed = explorer.rename(1, "renamed.txt").unwrap();
+        assert!(!created.exists());
+        assert_eq!(renamed, dir.join("sub/renamed.txt"));
+
+        let moved = explorer
+            .move_to(explorer.position(&renamed).unwrap(), "other")
+            .unwrap();
+        assert_eq!(moved, dir.join("other/renamed.txt"));
+        assert!(moved.is_file());
+
+        explorer.delete(explorer.position(&moved).unwrap()).unwrap();
+        assert!(!moved.exists());
+    }
+}
@@ -0,0 +1,270 @@
+//! # Files edited on behalf of git
+//!
+//! With `GIT_EDITOR=giga` (or `core.editor`), git opens the editor on a file of the git
+//! directory and waits for it to exit:
+//! - a message: `COMMIT_EDITMSG`, `TAG_EDITMSG`, `MERGE_MSG`. The summary should fit in
+//!   50 columns, be followed by a blank line, and the body should wrap at 72 columns.
+//! - the todo list of an interactive rebase: `git-rebase-todo`, one line per commit
+//!   starting with the action to perform (`pick`, `reword`, `squash`...).
+//!
+//! This module recognizes these files and highlights their structure. Git cancels the
+//! operation if the editor exits with a non-zero status (`:cq`).
+
+use super::view::{Highlight, Highlights};
+
+/// Actions of the rebase todo list that apply to a commit, in the order they are cycled
+pub const REBASE_ACTIONS: [&str; 6] = ["pick", "reword", "edit", "squash", "fixup", "drop"];
+
+/// Other commands of the rebase todo list (with their one-letter abbreviations)
+const REBASE_COMMANDS: [&str; 13] = [
+    "exec",
+    "x",
+    "break",
+    "b",
+    "label",
+    "l",
+    "reset",
+    "t",
+    "merge",
+    "m",
+    "update-ref",
+    "u",
+    "noop",
+];
+
+/// Columns of the rulers of a message: the summary ends before the first one, the body
+/// before the second one
+const MESSAGE_RULERS: [usize; 2] = [50, 72];
+
+/// Line after which a commit message is ignored (`git commit --verbose`)
+const SCISSORS: &str = "# ------------------------ >8 ------------------------";
+
+/// A file git asks the user to edit
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GitFile {
+    /// Message of a commit, a tag or a merge
+    Message,
+    /// Todo list of an interactive rebase
+    RebaseTodo,
+}
+
+impl GitFile {
+    /// Recognize a file git asks the user to edit from its name
+    pub fn detect(file_name: &str) -> Option<Self> {
+        match file_name {
+            "COMMIT_EDITMSG" | "TAG_EDITMSG" | "MERGE_MSG" => Some(GitFile::Message),
+            "git-rebase-todo" => Some(GitFile::RebaseTodo),
+            _ => None,
+        }
+    }
+
+    /// Columns where a ruler is drawn
+    pub fn rulers(&self) -> Vec<usize> {
+        match self {
+            GitFile::Message => MESSAGE_RULERS.to_vec(),
+            GitFile::RebaseTodo => vec![],
+        }
+    }
+
+    /// Highlight the structure of the file
+    pub fn highlights(&self, lines: &[&str]) -> Highlights {
+        match self {
+            GitFile::Message => message_highlights(lines),
+            GitFile::RebaseTodo => rebase_todo_highlights(lines),
+        }
+    }
+}
+
+/// Highlight the comments of a message and the text beyond its rulers: a summary longer
+/// than 50 columns, a second line that is not blank, body lines longer than 72 columns
+fn message_highlights(lines: &[&str]) -> Highlights {
+    let mut highlights = Highlights::new();
+    let mut scissors = false;
+    // Index of the line among the lines that are not comments
+    let mut index = 0;
+    for (line, text) in lines.iter().enumerate() {
+        scissors |= *text == SCISSORS;
+        if scissors || text.starts_with('#') {
+            highlights.insert(line, vec![(0..usize::MAX, Highlight::Comment)]);
+            continue;
+        }
+        let spans = match index {
+            0 => vec![
+                (0..MESSAGE_RULERS[0], Highlight::Summary),
+                (MESSAGE_RULERS[0]..usize::MAX, Highlight::Overflow),
+            ],
+            1 if !text.is_empty() => vec![(0..usize::MAX, Highlight::Overflow)],
+            _ => vec![(MESSAGE_RULERS[1]..usize::MAX, Highlight::Overflow)],
+        };
+        highlights.insert(line, spans);
+        index += 1;
+    }
+    highlights
+}
+
+/// Highlight the actions, the commits and the comments of a rebase todo list
+fn rebase_todo_highlights(lines: &[&str]) -> Highlights {
+    let mut highlights = Highlights::new();
+    for (line, text) in lines.iter().enumerate() {
+        if text.trim_start().starts_with('#') {
+            highlights.insert(line, vec![(0..usize::MAX, Highlight::Comment)]);
+            continue;
+        }
+        let mut words = word_ranges(text);
+        let Some(action) = words.next() else {
+            continue;
+        };
+        let name = &text[action.clone()];
+        let mut spans = vec![];
+        if parse_action(name).is_some() {
+            spans.push((action, Highlight::Keyword));
+            // `fixup -C` and `fixup -c` reuse the message of the commit
+            let commit = match words.next() {
+                Some(flag) if text[flag.clone()].starts_with('-') => words.next(),
+                commit => commit,
+            };
+            if let Some(commit) = commit {
+                spans.push((commit, Highlight::CommitId));
+            }
+        } else if REBASE_COMMANDS.contains(&name) {
+            spans.push((action, Highlight::Keyword));
+        }
+        highlights.insert(line, spans);
+    }
+    highlights
+}
+
+/// Ranges of the words of a line (separated by spaces)
+fn word_ranges(text: &str) -> impl Iterator<Item = std::ops::Range<usize>> + '_ {
+    let mut start = None;
+    text.char_indices()
+        .chain(std::iter::once((text.len(), ' ')))
+        .filter_map(move |(i, c)| match (c.is_whitespace(), start) {
+            (false, None) => {
+                start = Some(i);
+                None
+            }
+            (true, Some(s)) => {
+                start = None;
+                Some(s..i)
+            }
+            _ => None,
+        })
+}
+
+/// Index in `REBASE_ACTIONS` of an action (or of its one-letter abbreviation)
+fn parse_action(word: &str) -> Option<usize> {
+    REBASE_ACTIONS
+        .iter()
+        .position(|action| *action == word || action[..1] == *word)
+}
+
+/// Replace the action of a line of the todo list with the next (or previous) one.
+/// Returns `None` if the line does not apply an action to a commit.
+pub fn cycle_action(line: &str, forward: bool) -> Option<String> {
+    let indent = line.len() - line.trim_start().len();
+    let mut words = line[indent..].splitn(2, ' ');
+    let index = parse_action(words.next()?)?;
+    let mut rest = words.next().unwrap_or_default();
+    // The flag of `fixup -C` does not apply to the other actions
+    if REBASE_ACTIONS[index] == "fixup" && rest.starts_with('-') {
+        rest = rest
+            .split_once(' ')
+            .map(|(_, rest)| rest)
+            .unwrap_or_default();
+    }
+    let len = REBASE_ACTIONS.len();
+    let next = match forward {
+        true => (index + 1) % len,
+        false => (index + len - 1) % len,
+    };
+    Some(format!(
+        "{}{} {}",
+        &line[..indent],
+        REBASE_ACTIONS[next],
+        rest
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_git_files() {
+        assert_eq!(GitFile::detect("COMMIT_EDITMSG"), Some(GitFile::Message));
+        assert_eq!(GitFile::detect("TAG_EDITMSG"), Some(GitFile::Message));
+        assert_eq!(GitFile::detect("MERGE_MSG"), Some(GitFile::Message));
+        assert_eq!(
+            GitFile::detect("git-rebase-todo"),
+            Some(GitFile::RebaseTodo)
+        );
+        assert_eq!(GitFile::detect("main.rs"), None);
+        assert_eq!(GitFile::Message.rulers(), vec![50, 72]);
+    }
+
+    #[test]
+    fn highlight_message() {
+        let summary = "a".repeat(55);
+        let lines = [summary.as_str(), "not blank", "body", "# comment"];
+        let highlights = GitFile::Message.highlights(&lines);
+        assert_eq!(
+            highlights[&0],
+            vec![
+                (0..50, Highlight::Summary),
+                (50..usize::MAX, Highlight::Overflow)
+            ]
+        );
+        assert_eq!(highlights[&1], vec![(0..usize::MAX, Highlight::Overflow)]);
+        assert_eq!(highlights[&2], vec![(72..usize::MAX, Highlight::Overflow)]);
+        assert_eq!(highlights[&3], vec![(0..usize::MAX, Highlight::Comment)]);
+
+        // Everything below the scissors line is ignored by git
+        let highlights = GitFile::Message.highlights(&["summary", SCISSORS, "diff"]);
+        assert_eq!(highlights[&2], vec![(0..usize::MAX, Highlight::Comment)]);
+    }
+
+    #[test]
+    fn highlight_rebase_todo() {
+        let lines = [
+            "pick a1b2c3d Summary",
+            "fixup -C e4f5a6b Other",
+            "exec make",
+            "# Commands:",
+        ];
+        let highlights = GitFile::RebaseTodo.highlights(&lines);
+        assert_eq!(
+            highlights[&0],
+            vec![(0..4, Highlight::Keyword), (5..12, Highlight::CommitId)]
+        );
+        assert_eq!(
+            highlights[&1],
+            vec![(0..5, Highlight::Keyword), (9..16, Highlight::CommitId)]
+        );
+        assert_eq!(highlights[&2], vec![(0..4, Highlight::Keyword)]);
+        assert_eq!(highlights[&3], vec![(0..usize::MAX, Highlight::Comment)]);
+    }
+
+    #[test]
+    fn cycle_rebase_actions() {
+        let line = "pick a1b2c3d Summary";
+        assert_eq!(
+            cycle_action(line, true),
+            Some("reword a1b2c3d Summary".into())
+        );
+        assert_eq!(
+            cycle_action(line, false),
+            Some("drop a1b2c3d Summary".into())
+        );
+        assert_eq!(
+            cycle_action("s a1b2c3d Summary", true),
+            Some("fixup a1b2c3d Summary".into())
+        );
+        assert_eq!(
+            cycle_action("fixup -C a1b2c3d Summary", true),
+            Some("drop a1b2c3d Summary".into())
+        );
+        assert_eq!(cycle_action("exec make", true), None);
+        assert_eq!(cycle_action("# comment", true), None);
+    }
+}
@@ -0,0 +1,127 @@
+//! # File history browser
+//!
+//! The history of the current file (the commits that changed it) is displayed in a
+//! scratch buffer, one commit per line. From there the user can open any revision of
+//! the file (read-only) and step to the older or newer revisions, or compare the
+//! working buffer to a revision. The `History` keeps track of the commits and of the
+//! one being looked at; the editor keeps the working buffer aside in the meantime.
+
+use super::view::file::git::Revision;
+
+/// State of the history browser
+pub struct History {
+    /// Name of the file whose history is browsed
+    file_name: String,
+    /// The commits that changed the file, most recent first
+    revisions: Vec<Revision>,
+    /// Index of the selected revision
+    selected: usize,
+    /// Whether the selected revision is displayed (instead of the list)
+    showing_revision: bool,
+}
+
+impl History {
+    /// Browse the given history of a file
+    pub fn new(file_name: &str, revisions: Vec<Revision>) -> Self {
+        Self {
+            file_name: file_name.into(),
+            revisions,
+            selected: 0,
+            showing_revision: false,
+        }
+    }
+
+    /// The selected revision
+    pub fn selected(&self) -> Option<&Revision> {
+        self.revisions.get(self.selected)
+    }
+
+    /// The revision at the given line of the list
+    pub fn revision(&self, index: usize) -> Option<&Revision> {
+        self.revisions.get(index)
+    }
+
+    /// Index of the selected revision (its line in the list)
+    pub fn selected_index(&self) -> usize {
+        self.selected
+    }
+
+    /// Select the revision at the given line of the list
+    pub fn select(&mut self, index: usize) -> bool {
+        if index >= self.revisions.len() {
+            return false;
+        }
+        self.selected = index;
+        true
+    }
+
+    /// Select the revision before (older) or after (newer) the selected one
+    pub fn step(&mut self, older: bool) -> bool {
+        match older {
+            true => self.select(self.selected + 1),
+            false if self.selected > 0 => self.select(self.selected - 1),
+            false => false,
+        }
+    }
+
+    /// Whether the selected revision is displayed (instead of the list)
+    pub fn showing_revision(&self) -> bool {
+        self.showing_revision
+    }
+
+    pub fn set_showing_revision(&mut self, showing_revision: bool) {
+        self.showing_revision = showing_revision;
+    }
+
+    /// Name of the buffer displaying the list or the selected revision
+    pub fn title(&self) -> String {
+        match (self.showing_revision, self.selected()) {
+            (true, Some(revision)) => format!("{}@{}", self.file_name, revision.short_id()),
+            _ => format!("history: {}", self.file_name),
+        }
+    }
+
+    /// Render the list of revisions as the content of a buffer (one per line)
+    pub fn render(&self) -> String {
+        self.revisions
+            .iter()
+            .map(Revision::to_string)
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use git2::Oid;
+
+    fn revision(summary: &str) -> Revision {
+        Revision {
+            commit: Oid::from_str("0123456789abcdef0123456789abcdef01234567").unwrap(),
+            summary: summary.into(),
+            author: "giga".into(),
+            time: 0,
+        }
+    }
+
+    #[test]
+    fn history_step() {
+        let mut history = History::new("a.txt", vec![revision("new"), revision("old")]);
+        assert_eq!(history.title(), "history: a.txt");
+        assert!(!history.step(false));
+        assert!(history.step(true));
+        assert_eq!(history.selected().unwrap().summary, "old");
+        assert!(!history.step(true));
+        assert!(!history.select(2));
+
+        history.set_showing_revision(true);
+        assert_eq!(history.title(), "a.txt@0123456");
+    }
+
+    #[test]
+    fn history_render() {
+        let history = History::new("a.txt", vec![revision("first")]);
+        assert!(history.render().starts_with("0123456 first (giga, "));
+    }
+}
@@ -10,9 +10,9 @@
 //! There are three threads in the program:
 //! - Main thread, responsible for handling user input and modifying the view
 //! - Git thread, responsible for computing the diff between the current file and
-//!  the current commit and updating the diff field of the editor
+//!   the current commit (and the state of the repository) when they change
 //! - The tui thread, responsible for drawing the view to the terminal. This thread listens
-//! to both the Main thread and the Git thread (using message passing) and redraws the view when needed.
+//!   to both the Main thread and the Git thread (using message passing) and redraws the view when needed.
 //!
 //! ## The View
 //!
@@ -40,17 +40,56 @@
 //! to abstract the drawing system from the rest of the program (for modularity
 //! and mocking purposes). The `TerminalDrawer` trait is implemented in the `terminal`
 //! module. The current implementation uses the termion crate to draw to the terminal.
+//! The editor loop (`Editor::run_with`) is generic over the drawer and the source of the
+//! keys: the tests run it with a sequence of keys on an in-memory screen (the
+//! `terminal::screen` module) and check what it displays. Without a terminal, the
+//! editor runs the lines of a script instead (`Editor::run_script`, see the `script`
+//! module), with the same commands. The keys and the resize events of a session can be
+//! recorded, then fed back to `Editor::run_with` (see the `record` module).
 //!
 //! ## Git integration
 //!
 //! If the current file is in a git repository, the editor will display the current
-//! branch/commit/tag in the status bar and will display the diff between the current
+//! branch (or the tag/`describe` output of a detached HEAD) in the status bar, along with
+//! the state of the repository (uncommitted changes, commits ahead of/behind the upstream,
+//! merge or rebase in progress...) and will display the diff between the current
 //! file and the current commit in the left margin. This is done by the `git` module.
+//! By default the margin distinguishes unstaged changes (buffer vs index) from staged
+//! changes (index vs HEAD), the user can also compare the buffer to HEAD or to any
+//! revision (`B` in normal mode). Hunks (or lines selected in visual mode) can be
+//! staged and unstaged directly in the index. The lines can also be annotated with
+//! the commit that last changed them (blame). The history of the file is browsed in
+//! scratch views (`history` module) while the working buffer is kept aside. Two versions
+//! of the file can also be compared side by side (`diff` module). Merge conflicts are
+//! highlighted and resolved in the buffer (`conflict` module), the three versions of a
+//! conflicted file stored in the index can be compared side by side. The staged
+//! changes are committed from the command line (`:Gcommit`) with a message written in
+//! a buffer, like with `git commit`. Finally, the branches are listed in another scratch
+//! view (`branches` module) to switch to one of them, and so are the stashes (`stashes`
+//! module) to look at their changes and apply them.
+//!
+//! When git opens the editor (`GIT_EDITOR=giga`) on a commit message or on the todo list
+//! of an interactive rebase, their structure is highlighted (`git_file` module) and the
+//! user can abort the operation by quitting with a non-zero exit status (`:cq`).
+//!
+//! Outside of a git repository, the margin shows the unsaved changes instead: the buffer
+//! is compared to the file saved on disk (`saved` module, another implementation of the
+//! `Vcs` trait). The saved file can also be compared to the buffer side by side (`:DiffOrig`).
 //!
 //! This task is performed asynchronously by the git thread (`Editor::init_git_thread` method).
-//! The git thread computes the diff between the current file and the current commit and stores
-//! it in the `diff` field. It then sends a unit signal to the tui thread to notify it that the
-//! diff has changed.
+//! The git thread sleeps until it is notified that the buffer may have changed (after each
+//! command) or that the repository changed (HEAD and the index are watched by the `watch`
+//! module). Once the events settle, it takes a snapshot of the buffer, computes its diff
+//! without holding the lock of the view (with its own instance of the repository) and stores
+//! it in the view, unless the buffer was edited meanwhile. It then sends a `GitIndicators`
+//! refresh order to the tui thread to notify it that the diff has changed.
+//!
+//! ## File explorer
+//!
+//! When the editor is opened on a directory, it displays a tree explorer (`explorer` module)
+//! instead of a file. The explorer is rendered into a scratch view (a view that is not backed
+//! by a file) and the editor switches to the `Explorer` mode. Commands that need an argument
+//! from the user (file names, confirmations) use a prompt in the status bar (`prompt` module).
 //!
 //! ## Resizing the terminal
 //!
@@ -58,32 +97,66 @@
 //! and sends a unit signal to the tui thread to notify it that the terminal has been resized.
 //! This logic is handled by the `signal` module, which is called in the (`Editor::init_resize_listener` method).
 //!
+mod branches;
 mod command;
+mod conflict;
+mod diff;
+mod explorer;
+mod git_file;
+mod history;
+mod prompt;
+pub mod record;
+pub mod script;
 mod signal;
+mod stashes;
+mod substitute;
 mod terminal;
+#[cfg(test)]
+mod test_utils;
 mod view;
+mod watch;
 
 use std::{
     collections::HashSet,
+    error::Error,
     fmt::Display,
     io,
-    ops::DerefMut,
+    ops::{Deref, DerefMut},
+    path::Path,
     process::exit,
     sync::{
-        mpsc::{self, Receiver, SendError, Sender},
+        mpsc::{self, Receiver, RecvTimeoutError, SendError, Sender},
         Arc, Mutex,
     },
-    thread,
+    thread::{self, JoinHandle},
     time::Duration,
 };
 
-use termion::input::TermRead;
+use termion::{event::Key, input::TermRead};
 
-use self::view::FileView;
+use self::view::{
+    file::{
+        self,
+        git::{self, DiffBase, DiffResult, FileStatus, Git, Hunk, RepoStatus, Revision, Vcs},
+        saved, Snapshot,
+    },
+    FileView,
+};
 
 use {
+    branches::Branches,
     command::Command,
-    terminal::{termion::TermionTerminalDrawer, StatusBarInfos, TerminalDrawer},
+    conflict::{Conflict, Resolution},
+    diff::SideBySide,
+    explorer::Explorer,
+    git_file::GitFile,
+    history::History,
+    prompt::{Prompt, PromptKind},
+    record::{Event, Recorder},
+    script::Step,
+    stashes::Stashes,
+    substitute::Substitution,
+    terminal::{restore, termion::TermionTerminalDrawer, Popup, StatusBarInfos, TerminalDrawer},
     view::View,
 };
 
@@ -101,6 +174,37 @@ pub struct Editor {
     view: Arc<Mutex<View>>,
     /// The mode of the editor
     mode: Arc<Mutex<Mode>>,
+    /// Message displayed in the status bar (errors, confirmations...)
+    message: Arc<Mutex<Option<String>>>,
+    /// State of the repository of the current file (computed by the git thread)
+    repo_status: Arc<Mutex<Option<RepoStatus>>>,
+    /// Notifies the git thread that the diff may have to be computed again
+    git_events: Option<Sender<GitEvent>>,
+    /// The file explorer, if it has been opened
+    explorer: Option<Explorer>,
+    /// The history of the current file, if it is being browsed
+    history: Option<History>,
+    /// The branch switcher, if it is open
+    branches: Option<Branches>,
+    /// The stash list viewer, if it is open
+    stashes: Option<Stashes>,
+    /// The working buffer, kept aside while browsing its history, the branches or
+    /// the stashes
+    working_view: Option<View>,
+    /// The side-by-side diff being displayed
+    side_by_side: Option<SideBySide>,
+    /// The view and mode to go back to when leaving the side-by-side diff
+    before_side_by_side: Option<(View, Mode)>,
+    /// Whether the buffer contains merge conflicts (they are tracked while editing)
+    has_conflicts: bool,
+    /// The working buffer, kept aside while writing a commit message
+    before_commit: Option<View>,
+    /// First key of a key sequence being typed
+    pending: Option<char>,
+    /// Whether a popup is displayed (it is closed by the next key)
+    popup_open: bool,
+    /// Logs the keys and the resize events, if the session is recorded
+    recorder: Option<Arc<Mutex<Recorder>>>,
 }
 
 #[derive(Clone)]
@@ -110,8 +214,20 @@ pub enum Mode {
     Normal,
     /// Insert mode
     Insert,
-    /// Rename mode
-    Rename,
+    /// Selecting lines
+    Visual,
+    /// File explorer mode
+    Explorer,
+    /// Browsing the history of the file
+    History,
+    /// Choosing a branch to check out
+    Branches,
+    /// Browsing the stash list
+    Stashes,
+    /// Comparing two buffers side by side
+    Diff,
+    /// Waiting for the user to answer a prompt
+    Prompt(Prompt),
 }
 
 impl Display for Mode {
@@ -119,83 +235,1287 @@ impl Display for Mode {
         let mode = match self {
             Mode::Normal => "NORMAL",
             Mode::Insert => "INSERT",
-            Mode::Rename => "RENAME",
+            Mode::Visual => "VISUAL",
+            Mode::Explorer => "EXPLORER",
+            Mode::History => "HISTORY",
+            Mode::Branches => "BRANCHES",
+            Mode::Stashes => "STASHES",
+            Mode::Diff => "DIFF",
+            Mode::Prompt(Prompt {
+                kind: PromptKind::Rename | PromptKind::RenameOverwrite(_),
+                ..
+            }) => "RENAME",
+            Mode::Prompt(Prompt {
+                kind: PromptKind::Command,
+                ..
+            }) => "COMMAND",
+            Mode::Prompt(_) => "PROMPT",
+        };
+        write!(f, "{}", mode)
+    }
+}
+
+pub enum RefreshOrder {
+    /// Terminate the editor
+    Terminate,
+    /// Terminate the editor with a non-zero exit status (git then cancels the operation
+    /// it opened the editor for)
+    Abort,
+    /// No need to refresh the screen
+    None,
+    /// Refresh the cursor position
+    CursorPos,
+    /// Refresh the given lines
+    Lines(HashSet<usize>),
+    /// Refresh the status bar
+    StatusBar,
+    /// Refresh the git indicators
+    GitIndicators,
+    /// Refresh the whole screen
+    AllLines,
+    /// Resize the screen to the size of the view and refresh it
+    Resize,
+    /// Give the terminal back to the shell and stop the editor
+    Suspend,
+    /// Take the terminal again and redraw the editor after it was resumed
+    Resume,
+    /// Display a popup on top of the editor
+    Popup(Popup),
+}
+
+/// Events that may change the diff of the buffer, sent to the git thread
+#[derive(Debug, PartialEq)]
+pub enum GitEvent {
+    /// The buffer may have been edited
+    BufferChanged,
+    /// HEAD, the index or the working tree changed
+    RepositoryChanged,
+}
+
+/// Time without events after which the git thread computes the diff again
+const GIT_DEBOUNCE: Duration = Duration::from_millis(150);
+
+impl Editor {
+    /// Open a file in the editor, or the file explorer if the path is a directory
+    pub fn open(path: &str) -> Self {
+        if Path::new(path).is_dir() {
+            let explorer = Explorer::new(path);
+            let mut editor = Self::new(View::scratch(&explorer.render(), path), Mode::Explorer);
+            editor.explorer = Some(explorer);
+            return editor;
+        }
+
+        let mut editor = Self::new(View::new(path), Mode::Normal);
+        let conflicts = editor.refresh_conflicts().len();
+        if conflicts > 0 {
+            editor.set_message(&format!(
+                "{} merge conflict(s): ]n to jump, co/ct/cb to resolve",
+                conflicts
+            ));
+        }
+        // Opened by git (GIT_EDITOR)
+        editor.refresh_git_file();
+        if GitFile::detect(&editor.view.lock().unwrap().file_name()).is_some() {
+            editor.set_message("Save (w) and quit (q) to continue, :cq to abort");
+        }
+        editor
+    }
+
+    /// Open two files in a side-by-side diff (leaving the diff quits the editor)
+    pub fn open_diff(left: &str, right: &str) -> Result<Self, Box<dyn Error>> {
+        let content = |path| std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e));
+        let diff = SideBySide::new(&content(left)?, &content(right)?, (left, right))?;
+        let mut editor = Self::new(View::scratch("", &diff.title()), Mode::Diff);
+        // The diff is rendered once the size of the terminal is known
+        editor.side_by_side = Some(diff);
+        Ok(editor)
+    }
+
+    fn new(view: View, mode: Mode) -> Self {
+        Self {
+            view: arc_mutex!(view),
+            mode: arc_mutex!(mode),
+            message: arc_mutex!(None),
+            repo_status: arc_mutex!(None),
+            git_events: None,
+            explorer: None,
+            history: None,
+            branches: None,
+            stashes: None,
+            working_view: None,
+            side_by_side: None,
+            before_side_by_side: None,
+            has_conflicts: false,
+            before_commit: None,
+            pending: None,
+            popup_open: false,
+            recorder: None,
+        }
+    }
+
+    /// Record the session (the keys and the resize events) with the given recorder
+    pub fn record(&mut self, recorder: Recorder) {
+        self.recorder = Some(arc_mutex!(recorder));
+    }
+
+    /// Save the current file
+    fn save(&self) {
+        let mut view = self.view.lock().unwrap();
+        let file_path = view.file_path();
+        let content = view.dump_file();
+
+        let result = std::fs::write(file_path.clone() + ".tmp", content)
+            .and_then(|_| std::fs::rename(file_path.clone() + ".tmp", file_path));
+        match result {
+            Ok(()) => view.mark_saved(),
+            Err(e) => {
+                drop(view);
+                self.set_message(&format!("Could not save: {}", e));
+            }
+        }
+    }
+
+    /// Display a message in the status bar
+    fn set_message(&self, message: &str) {
+        *self.message.lock().unwrap() = Some(message.into());
+    }
+
+    /// Absolute line of the cursor in the current view
+    fn cursor_line(&self) -> usize {
+        let view = self.view.lock().unwrap();
+        view.cursor.1 + view.start_line
+    }
+
+    /// Replace the current view with another one, keeping the dimensions of the screen
+    fn replace_view(&self, mut new_view: View) {
+        let mut view = self.view.lock().unwrap();
+        new_view.height = view.height;
+        new_view.width = view.width;
+        *view = new_view;
+    }
+
+    /// Take the current view out (to put it back later), leaving an empty one
+    fn take_view(&self) -> View {
+        let mut view = self.view.lock().unwrap();
+        let mut placeholder = View::scratch("", "");
+        placeholder.height = view.height;
+        placeholder.width = view.width;
+        std::mem::replace(&mut *view, placeholder)
+    }
+
+    /// Move the cursor to the next (or previous) hunk of the diff
+    fn goto_hunk(&mut self, forward: bool) -> RefreshOrder {
+        // Hunks are reached on their marker in the gutter
+        let starts = match &self.side_by_side {
+            Some(diff) => diff.change_starts(),
+            None => {
+                let view = self.view.lock().unwrap();
+                view.hunks().iter().map(Hunk::marker_line).collect()
+            }
+        };
+        self.goto_next_line(&starts, forward, "No more hunks")
+    }
+
+    /// Move the cursor to the next (or previous) of the given lines
+    fn goto_next_line(&mut self, lines: &[usize], forward: bool, none: &str) -> RefreshOrder {
+        let mut view = self.view.lock().unwrap();
+        let line = view.cursor.1 + view.start_line;
+        let lines = lines.iter().copied();
+        let target = if forward {
+            lines.filter(|start| *start > line).min()
+        } else {
+            lines.filter(|start| *start < line).max()
+        };
+
+        match target {
+            Some(target) => {
+                if view.navigate(0, target as isize - line as isize) {
+                    RefreshOrder::AllLines
+                } else {
+                    RefreshOrder::CursorPos
+                }
+            }
+            None => {
+                drop(view);
+                self.set_message(none);
+                RefreshOrder::StatusBar
+            }
+        }
+    }
+
+    /// Get the hunk under the cursor (unstaged hunks take precedence)
+    fn hunk_at_cursor(&self) -> Option<Hunk> {
+        let view = self.view.lock().unwrap();
+        let line = view.cursor.1 + view.start_line;
+        view.hunks()
+            .into_iter()
+            .rev()
+            .find(|hunk| hunk.contains(line))
+    }
+
+    /// Show the original text of the hunk under the cursor in a popup
+    fn preview_hunk(&mut self) -> RefreshOrder {
+        let Some(hunk) = self.hunk_at_cursor() else {
+            self.set_message("No hunk under the cursor");
+            return RefreshOrder::StatusBar;
+        };
+        let base = match hunk.staged {
+            true => DiffBase::Head,
+            false => self.view.lock().unwrap().diff_base().unwrap_or_default(),
+        };
+        let lines = match hunk.old_lines.is_empty() {
+            true => vec!["(added lines)".to_string()],
+            false => hunk
+                .old_lines
+                .iter()
+                .map(|line| format!("-{}", line.trim_end_matches('\n')))
+                .collect(),
+        };
+
+        self.popup_open = true;
+        RefreshOrder::Popup(Popup {
+            title: base.to_string(),
+            lines,
+        })
+    }
+
+    /// Revert the changes under the cursor back to HEAD, whatever the diff base is
+    /// (can be undone). The gutter is updated by the git thread.
+    fn revert_hunk(&mut self) -> RefreshOrder {
+        let line = self.cursor_line();
+        let mut view = self.view.lock().unwrap();
+        let head = match view.revision_content("HEAD") {
+            Ok(Some(head)) => head,
+            Ok(None) => {
+                drop(view);
+                self.set_message("The file is not in HEAD");
+                return RefreshOrder::StatusBar;
+            }
+            Err(e) => {
+                drop(view);
+                self.set_message(&format!("Could not revert: {}", e));
+                return RefreshOrder::StatusBar;
+            }
+        };
+        let hunk = git::diff_buffers(&head, &view.dump_file())
+            .unwrap_or_default()
+            .into_iter()
+            .find(|hunk| hunk.contains(line));
+        let Some(hunk) = hunk else {
+            drop(view);
+            self.set_message("Nothing to revert");
+            return RefreshOrder::StatusBar;
+        };
+        view.revert_hunk(&hunk);
+        RefreshOrder::AllLines
+    }
+
+    /// Find the merge conflicts of the buffer and highlight them. Returns the conflicts.
+    fn refresh_conflicts(&mut self) -> Vec<Conflict> {
+        let mut view = self.view.lock().unwrap();
+        let content = view.dump_file();
+        let lines = content.lines().collect::<Vec<_>>();
+        let conflicts = conflict::find_conflicts(&lines);
+        view.highlights = conflict::highlights(&conflicts);
+        self.has_conflicts = !conflicts.is_empty();
+        conflicts
+    }
+
+    /// Highlight the structure of a file opened by git (a commit message, a rebase todo
+    /// list...) and draw its rulers. Returns whether the highlights changed.
+    fn refresh_git_file(&self) -> bool {
+        let mut view = self.view.lock().unwrap();
+        let Some(git_file) = GitFile::detect(&view.file_name()) else {
+            return false;
+        };
+        let content = view.dump_file();
+        let highlights = git_file.highlights(&content.lines().collect::<Vec<_>>());
+        view.rulers = git_file.rulers();
+        let changed = view.highlights != highlights;
+        view.highlights = highlights;
+        changed
+    }
+
+    /// Replace the action of the line under the cursor in a rebase todo list with the
+    /// next (or previous) one: pick, reword, edit, squash, fixup, drop (can be undone)
+    fn cycle_rebase_action(&mut self, forward: bool) -> RefreshOrder {
+        let line = self.cursor_line();
+        let mut view = self.view.lock().unwrap();
+        if GitFile::detect(&view.file_name()) != Some(GitFile::RebaseTodo) {
+            drop(view);
+            self.set_message("Not a rebase todo list");
+            return RefreshOrder::StatusBar;
+        }
+        let content = view.dump_file();
+        let current = content.lines().nth(line).unwrap_or_default();
+        let Some(text) = git_file::cycle_action(current, forward) else {
+            drop(view);
+            self.set_message("No commit on this line");
+            return RefreshOrder::StatusBar;
+        };
+        view.replace_lines(line, 1, &format!("{}\n", text));
+        drop(view);
+        self.refresh_git_file();
+        RefreshOrder::AllLines
+    }
+
+    /// Move the cursor to the next (or previous) merge conflict
+    fn goto_conflict(&mut self, forward: bool) -> RefreshOrder {
+        let starts = self
+            .refresh_conflicts()
+            .iter()
+            .map(|conflict| conflict.start)
+            .collect::<Vec<_>>();
+        self.goto_next_line(&starts, forward, "No more conflicts")
+    }
+
+    /// Replace the merge conflict under the cursor with the chosen side(s) (can be undone)
+    fn resolve_conflict(&mut self, resolution: Resolution) -> RefreshOrder {
+        let line = self.cursor_line();
+        let conflicts = self.refresh_conflicts();
+        let Some(conflict) = conflicts.iter().find(|conflict| conflict.contains(line)) else {
+            self.set_message("No conflict under the cursor");
+            return RefreshOrder::StatusBar;
+        };
+
+        let mut view = self.view.lock().unwrap();
+        let content = view.dump_file();
+        let lines = content.lines().collect::<Vec<_>>();
+        let text = conflict.resolve(&lines, resolution);
+        view.replace_lines(conflict.start, conflict.len(), &text);
+        drop(view);
+
+        let left = self.refresh_conflicts().len();
+        if left == 0 {
+            self.set_message("All conflicts resolved (save, then git add the file)");
+        } else {
+            self.set_message(&format!("{} conflict(s) left", left));
+        }
+        RefreshOrder::AllLines
+    }
+
+    /// Start selecting lines from the cursor, or stop selecting
+    fn toggle_visual(&mut self) -> RefreshOrder {
+        // The view is always locked before the mode (like in the tui thread)
+        let mut view = self.view.lock().unwrap();
+        let mut mode = self.mode.lock().unwrap();
+        *mode = match mode.deref() {
+            Mode::Visual => {
+                view.selection_anchor = None;
+                Mode::Normal
+            }
+            _ => {
+                view.selection_anchor = Some(view.cursor.1 + view.start_line);
+                Mode::Visual
+            }
+        };
+        RefreshOrder::AllLines
+    }
+
+    /// Stage (or unstage) the selected lines, or the unstaged (or staged) hunk
+    /// under the cursor, and update the gutter right away
+    fn stage_lines(&mut self, stage: bool) -> RefreshOrder {
+        let mut view = self.view.lock().unwrap();
+        let line = view.cursor.1 + view.start_line;
+        let lines = match view.selection() {
+            Some(selection) => Some(selection),
+            // An untracked file has no hunks, it is staged entirely (like `git add`)
+            None if stage && view.file_status() == Some(FileStatus::Untracked) => {
+                let count = view.dump_file().lines().count();
+                Some(0..=count.saturating_sub(1))
+            }
+            None => view
+                .hunks()
+                .into_iter()
+                .find(|hunk| hunk.staged != stage && hunk.contains(line))
+                .map(|hunk| hunk.lines()),
+        };
+        // Leave visual mode
+        view.selection_anchor = None;
+        let mut mode = self.mode.lock().unwrap();
+        if matches!(*mode, Mode::Visual) {
+            *mode = Mode::Normal;
+        }
+        drop(mode);
+
+        let message = match lines {
+            None if stage => "No unstaged hunk under the cursor".to_string(),
+            None => "No staged hunk under the cursor".to_string(),
+            Some(lines) => {
+                let result = match stage {
+                    true => view.stage_lines(lines),
+                    false => view.unstage_lines(lines),
+                };
+                match (result, stage) {
+                    (Ok(()), true) => "Staged".to_string(),
+                    (Ok(()), false) => "Unstaged".to_string(),
+                    (Err(e), true) => format!("Could not stage: {}", e),
+                    (Err(e), false) => format!("Could not unstage: {}", e),
+                }
+            }
+        };
+        drop(view);
+        self.set_message(&message);
+        RefreshOrder::AllLines
+    }
+
+    /// Show the commit that last changed the line under the cursor in a popup
+    fn show_commit(&mut self) -> RefreshOrder {
+        let mut view = self.view.lock().unwrap();
+        let line = view.cursor.1 + view.start_line;
+        // Blame the file just for this line if the annotations are hidden
+        let hidden = view.blame().is_none();
+        if hidden {
+            if let Err(e) = view.toggle_blame() {
+                drop(view);
+                self.set_message(&format!("Could not blame: {}", e));
+                return RefreshOrder::StatusBar;
+            }
+        }
+        let blame = view.blame().and_then(|blame| blame.get(line).cloned());
+        if hidden {
+            let _ = view.toggle_blame();
+        }
+
+        let popup = match blame {
+            Some(Some(blame)) => match view.commit_message(blame.commit) {
+                Ok(lines) => Popup {
+                    title: blame.commit.to_string()[..7].to_string(),
+                    lines,
+                },
+                Err(e) => {
+                    drop(view);
+                    self.set_message(&format!("Could not read the commit: {}", e));
+                    return RefreshOrder::StatusBar;
+                }
+            },
+            Some(None) => Popup {
+                title: "blame".into(),
+                lines: vec!["Not committed yet".into()],
+            },
+            None => {
+                drop(view);
+                self.set_message("No commit for this line");
+                return RefreshOrder::StatusBar;
+            }
+        };
+        self.popup_open = true;
+        RefreshOrder::Popup(popup)
+    }
+
+    /// Browse the history of the current file (the buffer is kept aside meanwhile)
+    fn open_history(&mut self) -> RefreshOrder {
+        let view = self.view.lock().unwrap();
+        let revisions = match view.history() {
+            Ok(revisions) if revisions.is_empty() => Err("no commit".into()),
+            result => result,
+        };
+        let revisions = match revisions {
+            Ok(revisions) => revisions,
+            Err(e) => {
+                drop(view);
+                self.set_message(&format!("No history: {}", e));
+                return RefreshOrder::StatusBar;
+            }
+        };
+
+        self.history = Some(History::new(&view.file_name(), revisions));
+        drop(view);
+        self.working_view = Some(self.take_view());
+        self.show_history()
+    }
+
+    /// Display the list of revisions or the selected revision (read-only)
+    fn show_history(&mut self) -> RefreshOrder {
+        let (Some(history), Some(working_view)) = (&self.history, &self.working_view) else {
+            return RefreshOrder::None;
+        };
+
+        if !history.showing_revision() {
+            self.replace_view(View::scratch(&history.render(), &history.title()));
+            self.view
+                .lock()
+                .unwrap()
+                .navigate(0, history.selected_index() as isize);
+        } else if let Some(revision) = history.selected() {
+            let content = match working_view.revision_content(&revision.commit.to_string()) {
+                Ok(Some(content)) => content,
+                Ok(None) => String::new(),
+                Err(e) => {
+                    self.set_message(&format!("Could not read the revision: {}", e));
+                    return RefreshOrder::StatusBar;
+                }
+            };
+            // Stay on the same line when stepping through the revisions
+            let line = self.cursor_line();
+            self.replace_view(View::scratch(&content, &history.title()));
+            self.view.lock().unwrap().navigate(0, line as isize);
+        }
+        *self.mode.lock().unwrap() = Mode::History;
+        RefreshOrder::AllLines
+    }
+
+    /// Open the revision under the cursor in the list of revisions
+    fn open_revision(&mut self) -> RefreshOrder {
+        let line = self.cursor_line();
+        let Some(history) = &mut self.history else {
+            return RefreshOrder::None;
+        };
+        if history.showing_revision() || !history.select(line) {
+            return RefreshOrder::None;
+        }
+        history.set_showing_revision(true);
+        self.show_history()
+    }
+
+    /// Open the revision before (older) or after (newer) the displayed one
+    fn step_history(&mut self, older: bool) -> RefreshOrder {
+        let Some(history) = &mut self.history else {
+            return RefreshOrder::None;
+        };
+        if !history.step(older) {
+            let message = match older {
+                true => "No older revision",
+                false => "No newer revision",
+            };
+            self.set_message(message);
+            return RefreshOrder::StatusBar;
+        }
+        history.set_showing_revision(true);
+        self.show_history()
+    }
+
+    /// Go back to the working buffer, comparing it to the selected revision in the gutter
+    fn diff_history(&mut self) -> RefreshOrder {
+        let line = self.cursor_line();
+        let Some(history) = &mut self.history else {
+            return RefreshOrder::None;
+        };
+        if !history.showing_revision() {
+            history.select(line);
+        }
+        let Some(revision) = history.selected() else {
+            return RefreshOrder::None;
+        };
+        let commit = revision.commit.to_string();
+        self.close_history_browser();
+        self.set_diff_base(&commit)
+    }
+
+    /// Go back to the list of revisions, or to the working buffer from the list
+    fn close_history(&mut self) -> RefreshOrder {
+        match &mut self.history {
+            Some(history) if history.showing_revision() => {
+                history.set_showing_revision(false);
+                self.show_history()
+            }
+            _ => {
+                self.close_history_browser();
+                RefreshOrder::AllLines
+            }
+        }
+    }
+
+    /// Stop browsing the history and restore the working buffer
+    fn close_history_browser(&mut self) {
+        self.history = None;
+        if let Some(view) = self.working_view.take() {
+            self.replace_view(view);
+        }
+        *self.mode.lock().unwrap() = Mode::Normal;
+    }
+
+    /// List the branches and the tags of the repository to check one out (the buffer
+    /// is kept aside meanwhile)
+    fn open_branches(&mut self) -> RefreshOrder {
+        let refs = self.view.lock().unwrap().refs();
+        let refs = match refs {
+            Ok(refs) => refs,
+            Err(e) => {
+                self.set_message(&format!("No branches: {}", e));
+                return RefreshOrder::StatusBar;
+            }
+        };
+        let branches = Branches::new(refs);
+        let view = View::scratch(&branches.render(), "branches");
+        self.working_view = Some(self.take_view());
+        self.replace_view(view);
+        self.view
+            .lock()
+            .unwrap()
+            .navigate(0, branches.current_index() as isize);
+        self.branches = Some(branches);
+        *self.mode.lock().unwrap() = Mode::Branches;
+        RefreshOrder::AllLines
+    }
+
+    /// Check out the branch or the tag under the cursor, then go back to the buffer and
+    /// reload it. The unsaved changes of the buffer have to be saved first.
+    fn checkout_branch(&mut self) -> RefreshOrder {
+        let line = self.cursor_line();
+        let (Some(branches), Some(working_view)) = (&self.branches, &mut self.working_view) else {
+            return RefreshOrder::None;
+        };
+        let Some(git_ref) = branches.entry(line).cloned() else {
+            return RefreshOrder::None;
+        };
+        // Leave the switcher open if the checkout is refused
+        let result = match working_view.is_modified() {
+            true => Err("the buffer has unsaved changes, save them (w) first".into()),
+            false => working_view.checkout(&git_ref),
+        };
+        if let Err(e) = result {
+            self.set_message(&format!("Could not check out {}: {}", git_ref.name, e));
+            return RefreshOrder::StatusBar;
+        }
+        self.close_branches();
+        self.set_message(&format!("Switched to {}", git_ref.name));
+        self.reload_view();
+        RefreshOrder::AllLines
+    }
+
+    /// Create a branch at HEAD and switch to it, then go back to the buffer
+    fn create_branch(&mut self, name: &str) -> RefreshOrder {
+        let Some(working_view) = &mut self.working_view else {
+            return RefreshOrder::None;
+        };
+        match working_view.create_branch(name.trim()) {
+            Ok(()) => {
+                self.close_branches();
+                self.set_message(&format!("Switched to a new branch {}", name.trim()));
+                RefreshOrder::AllLines
+            }
+            Err(e) => {
+                self.set_message(&format!("Could not create the branch: {}", e));
+                RefreshOrder::StatusBar
+            }
+        }
+    }
+
+    /// Close the branch switcher and restore the working buffer
+    fn close_branches(&mut self) {
+        self.branches = None;
+        if let Some(view) = self.working_view.take() {
+            self.replace_view(view);
+        }
+        *self.mode.lock().unwrap() = Mode::Normal;
+    }
+
+    /// Stash the changes of the working tree and of the index, then reload the buffer.
+    /// The unsaved changes of the buffer have to be saved first.
+    fn stash(&mut self) -> RefreshOrder {
+        let mut view = self.view.lock().unwrap();
+        if view.is_modified() {
+            drop(view);
+            self.set_message("The buffer has unsaved changes, save them (w) to stash them");
+            return RefreshOrder::StatusBar;
+        }
+        let result = view.stash_save();
+        drop(view);
+        match result {
+            Ok(()) => {
+                self.set_message("Saved working directory and index state");
+                self.reload_view();
+                RefreshOrder::AllLines
+            }
+            Err(e) => {
+                self.set_message(&format!("Could not stash: {}", e));
+                RefreshOrder::StatusBar
+            }
+        }
+    }
+
+    /// Apply a stash and remove it from the list, then go back to the buffer and
+    /// reload it. The unsaved changes of the buffer have to be saved first.
+    fn pop_stash(&mut self, index: usize) -> RefreshOrder {
+        let result = {
+            let mut view = self.view.lock().unwrap();
+            let view = self.working_view.as_mut().unwrap_or(&mut view);
+            match view.is_modified() {
+                true => Err("the buffer has unsaved changes, save them (w) first".into()),
+                false => view.stash_pop(index),
+            }
+        };
+        if let Err(e) = result {
+            self.set_message(&format!("Could not pop stash@{{{}}}: {}", index, e));
+            return RefreshOrder::StatusBar;
+        }
+        if self.stashes.is_some() {
+            self.close_stashes_viewer();
+        }
+        self.set_message(&format!("Applied and dropped stash@{{{}}}", index));
+        self.reload_view();
+        RefreshOrder::AllLines
+    }
+
+    /// Browse the stash list (the buffer is kept aside meanwhile)
+    fn open_stashes(&mut self) -> RefreshOrder {
+        let stashes = self.view.lock().unwrap().stashes();
+        let stashes = match stashes {
+            Ok(stashes) if stashes.is_empty() => Err("no stash entries".into()),
+            result => result,
+        };
+        match stashes {
+            Ok(stashes) => {
+                self.stashes = Some(Stashes::new(stashes));
+                self.working_view = Some(self.take_view());
+                self.show_stashes()
+            }
+            Err(e) => {
+                self.set_message(&format!("No stashes: {}", e));
+                RefreshOrder::StatusBar
+            }
+        }
+    }
+
+    /// Display the stash list or the diff of the selected stash
+    fn show_stashes(&mut self) -> RefreshOrder {
+        let (Some(stashes), Some(working_view)) = (&self.stashes, &mut self.working_view) else {
+            return RefreshOrder::None;
+        };
+        if !stashes.showing_diff() {
+            self.replace_view(View::scratch(&stashes.render(), &stashes.title()));
+            self.view
+                .lock()
+                .unwrap()
+                .navigate(0, stashes.selected_index() as isize);
+        } else {
+            let lines = match working_view.stash_diff(stashes.selected_index()) {
+                Ok(lines) => lines,
+                Err(e) => {
+                    self.set_message(&format!("Could not read the stash: {}", e));
+                    return RefreshOrder::StatusBar;
+                }
+            };
+            let mut view = View::scratch(&lines.join("\n"), &stashes.title());
+            view.highlights = stashes::diff_highlights(&lines);
+            self.replace_view(view);
+        }
+        *self.mode.lock().unwrap() = Mode::Stashes;
+        RefreshOrder::AllLines
+    }
+
+    /// Display the diff of the stash under the cursor in the list
+    fn open_stash(&mut self) -> RefreshOrder {
+        let line = self.cursor_line();
+        let Some(stashes) = &mut self.stashes else {
+            return RefreshOrder::None;
+        };
+        if stashes.showing_diff() || !stashes.select(line) {
+            return RefreshOrder::None;
+        }
+        stashes.set_showing_diff(true);
+        self.show_stashes()
+    }
+
+    /// Go back to the stash list, or to the working buffer from the list
+    fn close_stashes(&mut self) -> RefreshOrder {
+        match &mut self.stashes {
+            Some(stashes) if stashes.showing_diff() => {
+                stashes.set_showing_diff(false);
+                self.show_stashes()
+            }
+            _ => {
+                self.close_stashes_viewer();
+                RefreshOrder::AllLines
+            }
+        }
+    }
+
+    /// Stop browsing the stashes and restore the working buffer
+    fn close_stashes_viewer(&mut self) {
+        self.stashes = None;
+        if let Some(view) = self.working_view.take() {
+            self.replace_view(view);
+        }
+        *self.mode.lock().unwrap() = Mode::Normal;
+    }
+
+    /// Reload the buffer if its file changed on disk (after a checkout or a stash), unless it has
+    /// unsaved changes. The cursor stays on the same line.
+    fn reload_view(&mut self) {
+        let view = self.view.lock().unwrap();
+        let path = view.file_path();
+        // A file removed by the checkout stays open (it can be saved again)
+        let Ok(content) = std::fs::read_to_string(&path) else {
+            return;
+        };
+        if file::File::expand_tabs(&content) == view.dump_file() {
+            return;
+        }
+        if view.is_modified() {
+            drop(view);
+            self.set_message(&format!("{} changed on disk, unsaved changes kept", path));
+            return;
+        }
+        drop(view);
+        let line = self.cursor_line();
+        self.replace_view(View::new(&path));
+        self.view.lock().unwrap().navigate(0, line as isize);
+    }
+
+    /// Compare the file to HEAD side by side, or in the history, the selected
+    /// revision to the previous one
+    fn side_by_side(&mut self) -> RefreshOrder {
+        let diff = match *self.mode.lock().unwrap() {
+            Mode::History => None,
+            _ => Some(self.head_side_by_side()),
+        };
+        let diff = diff.unwrap_or_else(|| self.history_side_by_side());
+        self.open_side_by_side(diff)
+    }
+
+    /// Display a side-by-side diff, keeping the current view to go back to
+    fn open_side_by_side(&mut self, diff: Result<SideBySide, Box<dyn Error>>) -> RefreshOrder {
+        match diff {
+            Ok(diff) => {
+                let mode = self.mode.lock().unwrap().clone();
+                self.before_side_by_side = Some((self.take_view(), mode));
+                self.show_side_by_side(diff)
+            }
+            Err(e) => {
+                self.set_message(&format!("Could not compare: {}", e));
+                RefreshOrder::StatusBar
+            }
+        }
+    }
+
+    /// Compare the file in HEAD to the buffer
+    fn head_side_by_side(&self) -> Result<SideBySide, Box<dyn Error>> {
+        let view = self.view.lock().unwrap();
+        let head = view.revision_content("HEAD")?.unwrap_or_default();
+        let name = view.file_name();
+        let title = format!("{}@HEAD", name);
+        Ok(SideBySide::new(&head, &view.dump_file(), (&title, &name))?)
+    }
+
+    /// Compare the file saved on disk to the buffer (the unsaved changes)
+    fn saved_side_by_side(&self) -> Result<SideBySide, Box<dyn Error>> {
+        let view = self.view.lock().unwrap();
+        let name = view.file_name();
+        let saved = saved::saved_content(&view.file_dir(), &name);
+        let title = format!("{} (saved)", name);
+        Ok(SideBySide::new(&saved, &view.dump_file(), (&title, &name))?)
+    }
+
+    /// Compare our, the common ancestor's and their version of a conflicted file
+    fn three_way_side_by_side(&self) -> Result<SideBySide, Box<dyn Error>> {
+        let view = self.view.lock().unwrap();
+        let Some(versions) = view.conflict_versions()? else {
+            return Err("the file is not conflicted in the index".into());
+        };
+        let content = |version: Option<String>| version.unwrap_or_default();
+        Ok(SideBySide::three_way(
+            &content(versions.base),
+            &content(versions.ours),
+            &content(versions.theirs),
+            ("ours", "base", "theirs"),
+        )?)
+    }
+
+    /// Compare the revision selected in the history to the previous one
+    fn history_side_by_side(&mut self) -> Result<SideBySide, Box<dyn Error>> {
+        let line = self.cursor_line();
+        let (Some(history), Some(working_view)) = (&mut self.history, &self.working_view) else {
+            return Err("No history".into());
+        };
+        if !history.showing_revision() {
+            history.select(line);
+        }
+        let index = history.selected_index();
+        let content = |revision: Option<&Revision>| match revision {
+            Some(revision) => working_view.revision_content(&revision.commit.to_string()),
+            None => Ok(None),
+        };
+        let title = |revision: Option<&Revision>| match revision {
+            Some(revision) => format!("{}@{}", working_view.file_name(), revision.short_id()),
+            None => "(none)".to_string(),
+        };
+        let (old, new) = (history.revision(index + 1), history.revision(index));
+        Ok(SideBySide::new(
+            &content(old)?.unwrap_or_default(),
+            &content(new)?.unwrap_or_default(),
+            (&title(old), &title(new)),
+        )?)
+    }
+
+    /// Display a side-by-side diff in the current view
+    fn show_side_by_side(&mut self, diff: SideBySide) -> RefreshOrder {
+        let width = self.view.lock().unwrap().width;
+        self.replace_view(Self::render_side_by_side(&diff, width));
+        self.side_by_side = Some(diff);
+        *self.mode.lock().unwrap() = Mode::Diff;
+        RefreshOrder::AllLines
+    }
+
+    /// Render the columns of a side-by-side diff in a scratch view of the given width
+    fn render_side_by_side(diff: &SideBySide, width: usize) -> View {
+        let (text, highlights) = diff.render(width);
+        let mut view = View::scratch(&text, &diff.title());
+        view.highlights = highlights;
+        view
+    }
+
+    /// Go back to where the side-by-side diff was opened from (or quit if it was
+    /// opened from the command line)
+    fn close_side_by_side(&mut self) -> RefreshOrder {
+        self.side_by_side = None;
+        match self.before_side_by_side.take() {
+            Some((view, mode)) => {
+                self.replace_view(view);
+                *self.mode.lock().unwrap() = mode;
+                RefreshOrder::AllLines
+            }
+            None => RefreshOrder::Terminate,
+        }
+    }
+
+    /// Ask the user what the buffer should be compared to in the gutter
+    fn prompt_diff_base(&mut self) -> RefreshOrder {
+        let Some(base) = self.view.lock().unwrap().diff_base() else {
+            self.set_message("Not in a git repository");
+            return RefreshOrder::StatusBar;
+        };
+        let message = "Diff against (index, HEAD, saved or a revision): ";
+        let prompt = Prompt::new(PromptKind::DiffBase, message, &base.to_string());
+        *self.mode.lock().unwrap() = Mode::Prompt(prompt);
+        RefreshOrder::StatusBar
+    }
+
+    /// Change what the buffer is compared to in the gutter and recompute the diff
+    fn set_diff_base(&mut self, base: &str) -> RefreshOrder {
+        let base = DiffBase::parse(base);
+        let mut view = self.view.lock().unwrap();
+        let message = match view.set_diff_base(base.clone()) {
+            Ok(()) => {
+                let _ = view.refresh_diff();
+                format!("Diffing against {}", base)
+            }
+            Err(e) => format!("Invalid diff base: {}", e),
+        };
+        drop(view);
+        self.set_message(&message);
+        RefreshOrder::AllLines
+    }
+
+    /// Open the explorer on the directory of the current file
+    fn open_explorer(&mut self) -> RefreshOrder {
+        let (file_dir, file_path) = {
+            let view = self.view.lock().unwrap();
+            if view.is_modified() {
+                drop(view);
+                self.set_message("No write since last change");
+                return RefreshOrder::StatusBar;
+            }
+            (view.file_dir(), view.file_path())
+        };
+
+        let explorer = self
+            .explorer
+            .get_or_insert_with(|| Explorer::new(&file_dir));
+        explorer.refresh();
+        let line = explorer.position(Path::new(&file_path)).unwrap_or(0);
+        self.show_explorer(line)
+    }
+
+    /// Display the explorer in the current view, with the cursor on the given line
+    fn show_explorer(&mut self, line: usize) -> RefreshOrder {
+        let Some(explorer) = &self.explorer else {
+            return RefreshOrder::None;
+        };
+        let root = explorer.root().to_string_lossy();
+        self.replace_view(View::scratch(&explorer.render(), &root));
+        self.view.lock().unwrap().navigate(0, line as isize);
+        *self.mode.lock().unwrap() = Mode::Explorer;
+        RefreshOrder::AllLines
+    }
+
+    /// Open the file or toggle the directory under the cursor in the explorer
+    fn explorer_open(&mut self) -> RefreshOrder {
+        let line = self.cursor_line();
+        let Some(explorer) = &mut self.explorer else {
+            return RefreshOrder::None;
+        };
+        let Some(entry) = explorer.entry(line) else {
+            return RefreshOrder::None;
+        };
+
+        if entry.is_dir {
+            explorer.toggle(line);
+            self.show_explorer(line)
+        } else {
+            let path = entry.path.to_string_lossy().to_string();
+            self.replace_view(View::new(&path));
+            self.refresh_git_file();
+            *self.mode.lock().unwrap() = Mode::Normal;
+            RefreshOrder::AllLines
+        }
+    }
+
+    /// Ask the user for the argument of an explorer file operation
+    fn explorer_prompt(&mut self, cmd: Command) -> RefreshOrder {
+        let line = self.cursor_line();
+        let Some(explorer) = &self.explorer else {
+            return RefreshOrder::None;
+        };
+        let entry = explorer.entry(line);
+        let name = entry
+            .and_then(|entry| entry.path.file_name())
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        let prompt = match (cmd, entry) {
+            (Command::ExplorerCreate, _) => {
+                Prompt::new(PromptKind::ExplorerCreate(line), "New file: ", "")
+            }
+            (Command::ExplorerRename, Some(_)) => {
+                Prompt::new(PromptKind::ExplorerRename(line), "Rename to: ", &name)
+            }
+            (Command::ExplorerMove, Some(entry)) => {
+                let dir = entry
+                    .path
+                    .parent()
+                    .and_then(|dir| dir.strip_prefix(explorer.root()).ok())
+                    .map(|dir| dir.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                Prompt::new(PromptKind::ExplorerMove(line), "Move to: ", &dir)
+            }
+            (Command::ExplorerDelete, Some(_)) => Prompt::new(
+                PromptKind::ExplorerDelete(line),
+                &format!("Delete {}? (y/n) ", name),
+                "",
+            ),
+            _ => return RefreshOrder::None,
+        };
+        *self.mode.lock().unwrap() = Mode::Prompt(prompt);
+        RefreshOrder::StatusBar
+    }
+
+    /// Execute the action associated to the prompt with the input of the user
+    fn submit_prompt(&mut self) -> RefreshOrder {
+        let Mode::Prompt(prompt) = self.mode.lock().unwrap().clone() else {
+            return RefreshOrder::None;
         };
-        write!(f, "{}", mode)
+        *self.mode.lock().unwrap() = prompt.kind.mode();
+        let input = prompt.input.as_str();
+
+        match prompt.kind {
+            PromptKind::Rename => self.rename(input, false),
+            PromptKind::RenameOverwrite(path) if input == "y" => self.rename(&path, true),
+            PromptKind::RenameOverwrite(_) => RefreshOrder::StatusBar,
+            PromptKind::DiffBase => self.set_diff_base(input),
+            PromptKind::Command => self.run_command_line(input),
+            PromptKind::NewBranch => self.create_branch(input),
+            kind => self.submit_explorer_prompt(kind, input),
+        }
     }
-}
 
-pub enum RefreshOrder {
-    /// Terminate the editor
-    Terminate,
-    /// No need to refresh the screen
-    None,
-    /// Refresh the cursor position
-    CursorPos,
-    /// Refresh the given lines
-    Lines(HashSet<usize>),
-    /// Refresh the status bar
-    StatusBar,
-    /// Refresh the git indicators
-    GitIndicators,
-    /// Refresh the whole screen
-    AllLines,
-    /// Refresh the screen after a resize event
-    Resize,
-}
+    /// Execute an explorer file operation with the input of the user
+    fn submit_explorer_prompt(&mut self, kind: PromptKind, input: &str) -> RefreshOrder {
+        let Some(explorer) = &mut self.explorer else {
+            return RefreshOrder::None;
+        };
 
-impl Editor {
-    /// Open a file in the editor
-    pub fn open(path: &str) -> Self {
-        Self {
-            view: arc_mutex!(View::new(path)),
-            mode: arc_mutex!(Mode::Normal),
+        let result = match kind {
+            PromptKind::ExplorerCreate(line) => explorer.create(line, input),
+            PromptKind::ExplorerRename(line) => explorer.rename(line, input),
+            PromptKind::ExplorerMove(line) => explorer.move_to(line, input),
+            PromptKind::ExplorerDelete(line) if input == "y" => {
+                explorer.delete(line).map(|_| explorer.root().to_path_buf())
+            }
+            PromptKind::ExplorerDelete(line) => {
+                return self.show_explorer(line);
+            }
+            _ => return RefreshOrder::None,
+        };
+
+        match result {
+            Ok(path) => {
+                let line = explorer.position(&path).unwrap_or(self.cursor_line());
+                self.show_explorer(line)
+            }
+            Err(e) => {
+                self.set_message(&e.to_string());
+                RefreshOrder::StatusBar
+            }
         }
     }
 
-    /// Save the current file
-    fn save(&self) {
-        let view = self.view.lock().unwrap();
-        let file_path = view.file_path();
+    /// Run a command typed in the command line
+    fn run_command_line(&mut self, input: &str) -> RefreshOrder {
+        if input.trim().is_empty() {
+            return RefreshOrder::StatusBar;
+        }
+        match Command::parse_command_line(input) {
+            Ok(cmd) => self.execute(cmd),
+            Err(e) => {
+                self.set_message(&format!("{}: {}", e, input.trim()));
+                RefreshOrder::StatusBar
+            }
+        }
+    }
+
+    /// Replace a string on the line of the cursor, or on every line of the buffer
+    fn substitute(&mut self, substitution: &Substitution) -> RefreshOrder {
+        let cursor_line = self.cursor_line();
+        let mut view = self.view.lock().unwrap();
         let content = view.dump_file();
+        let mut lines = content
+            .split_inclusive('\n')
+            .map(String::from)
+            .collect::<Vec<_>>();
+        let range = match substitution.whole_file {
+            true => 0..lines.len(),
+            false => cursor_line..(cursor_line + 1).min(lines.len()),
+        };
+        let mut changed = vec![];
+        for index in range {
+            let line = &lines[index];
+            let (text, end) = match line.strip_suffix('\n') {
+                Some(text) => (text, "\n"),
+                None => (line.as_str(), ""),
+            };
+            if let Some(text) = substitution.apply(text) {
+                lines[index] = text + end;
+                changed.push(index);
+            }
+        }
+        let (Some(&first), Some(&last)) = (changed.first(), changed.last()) else {
+            drop(view);
+            self.set_message(&format!("Pattern not found: {}", substitution.pattern()));
+            return RefreshOrder::StatusBar;
+        };
+        view.replace_lines(first, last - first + 1, &lines[first..=last].concat());
+        drop(view);
+        self.set_message(&format!("{} line(s) changed", changed.len()));
+        RefreshOrder::AllLines
+    }
 
-        std::fs::write(file_path.clone() + ".tmp", content).unwrap_or_default();
-        std::fs::rename(file_path.clone() + ".tmp", file_path).unwrap_or_default();
+    /// Open the commit message buffer, pre-filled with the summary of the staged
+    /// changes. The commit is created when the buffer is closed (see `finish_commit`).
+    fn start_commit(&mut self) -> RefreshOrder {
+        if self.before_commit.is_some() {
+            self.set_message("Already writing a commit message");
+            return RefreshOrder::StatusBar;
+        }
+        let path = self.view.lock().unwrap().prepare_commit();
+        match path {
+            Ok(path) => {
+                self.before_commit = Some(self.take_view());
+                self.replace_view(View::new(&path));
+                self.refresh_git_file();
+                self.set_message("Write the commit message, save (w) and quit (q) to commit");
+                RefreshOrder::AllLines
+            }
+            Err(e) => {
+                self.set_message(&format!("Could not commit: {}", e));
+                RefreshOrder::StatusBar
+            }
+        }
     }
 
-    /// Rename the current file
-    fn rename(&mut self, c: Option<char>) {
+    /// Close the commit message buffer and commit with the saved message (an empty
+    /// message aborts the commit), then go back to the working buffer
+    fn finish_commit(&mut self) -> RefreshOrder {
+        let Some(working_view) = self.before_commit.take() else {
+            return RefreshOrder::None;
+        };
+        let path = self.view.lock().unwrap().file_path();
+        let saved = std::fs::read_to_string(path).unwrap_or_default();
+        let message = git::cleanup_message(&saved);
+        self.replace_view(working_view);
+
+        if message.is_empty() {
+            self.set_message("Aborting commit due to empty commit message");
+            return RefreshOrder::AllLines;
+        }
         let mut view = self.view.lock().unwrap();
-        let mut file_name = view.file_name();
-        match c {
-            None => {
-                file_name.pop();
-                view.set_file_name(file_name);
-            }
-            Some(c) => {
-                file_name.push(c);
-                view.set_file_name(file_name);
+        let result = view.commit(&message);
+        let _ = view.refresh_diff();
+        let git_ref = view.git_ref().unwrap_or_default();
+        drop(view);
+        match result {
+            Ok(oid) => {
+                let summary = message.lines().next().unwrap_or_default();
+                let short_id = &oid.to_string()[..7];
+                self.set_message(&format!("[{} {}] {}", git_ref, short_id, summary));
             }
+            Err(e) => self.set_message(&format!("Could not commit: {}", e)),
+        }
+        RefreshOrder::AllLines
+    }
+
+    /// Close the commit message buffer without committing (`:cq`), and go back to the
+    /// working buffer
+    fn abort_commit(&mut self) -> RefreshOrder {
+        let Some(working_view) = self.before_commit.take() else {
+            return RefreshOrder::None;
+        };
+        self.replace_view(working_view);
+        self.set_message("Commit aborted");
+        RefreshOrder::AllLines
+    }
+
+    /// Leave the prompt without executing its action
+    fn cancel_prompt(&mut self) -> RefreshOrder {
+        let mut mode = self.mode.lock().unwrap();
+        if let Mode::Prompt(prompt) = mode.clone() {
+            *mode = prompt.kind.mode();
+        }
+        RefreshOrder::StatusBar
+    }
+
+    /// Rename (or move) the current file on disk. Unless `overwrite` is set, the
+    /// user is asked for a confirmation before overwriting an existing file.
+    fn rename(&mut self, file_path: &str, overwrite: bool) -> RefreshOrder {
+        let mut view = self.view.lock().unwrap();
+        if file_path.is_empty() || file_path.ends_with('/') {
+            drop(view);
+            self.set_message("Invalid file name");
+            return RefreshOrder::StatusBar;
+        }
+        if file_path == view.file_path() {
+            return RefreshOrder::StatusBar;
         }
+        if !overwrite && Path::new(file_path).exists() {
+            let message = format!("{} exists, overwrite? (y/n) ", file_path);
+            let kind = PromptKind::RenameOverwrite(file_path.into());
+            *self.mode.lock().unwrap() = Mode::Prompt(Prompt::new(kind, &message, ""));
+            return RefreshOrder::StatusBar;
+        }
+
+        if let Err(e) = view.rename(file_path) {
+            drop(view);
+            self.set_message(&format!("Could not rename: {}", e));
+        }
+        RefreshOrder::StatusBar
     }
 
     /// Execute an editor command
     /// - Quit: exit the program
+    /// - Suspend: stop the program until it is resumed
     /// - Move: move the cursor
     /// - Save: save the file
-    /// - Rename: rename the file
+    /// - ToggleRename: start renaming the file
     /// - ToggleMode: toogle editor mode
     /// - Insert: insert a character
     /// - Delete: delete a character
+    /// - Explorer*: browse and modify the file tree
+    /// - Prompt*: answer a prompt in the status bar
     fn execute(&mut self, cmd: Command) -> RefreshOrder {
         match cmd {
+            Command::Quit if self.before_commit.is_some() => self.finish_commit(),
             Command::Quit => {
                 // Doesn't matter as self.terminate() never returns
                 RefreshOrder::Terminate
             }
+            Command::Abort if self.before_commit.is_some() => self.abort_commit(),
+            Command::Abort => RefreshOrder::Abort,
+            Command::Suspend => RefreshOrder::Suspend,
             Command::Move(x, y) => {
                 let scroll = self.view.lock().unwrap().navigate(x, y);
-                if scroll {
+                // The highlighted selection follows the cursor
+                if scroll || matches!(*self.mode.lock().unwrap(), Mode::Visual) {
                     RefreshOrder::AllLines
                 } else {
                     RefreshOrder::CursorPos
@@ -205,10 +1525,6 @@ impl Editor {
                 self.save();
                 RefreshOrder::StatusBar
             }
-            Command::Rename(c) => {
-                self.rename(c);
-                RefreshOrder::StatusBar
-            }
             Command::ToggleMode => {
                 self.toggle_mode();
                 RefreshOrder::StatusBar
@@ -253,6 +1569,8 @@ impl Editor {
                 cmds.into_iter().fold(RefreshOrder::None, |refr, cmd| {
                     use RefreshOrder::*;
                     match (refr, self.execute(cmd)) {
+                        // Quitting takes precedence over the other refresh orders
+                        (r @ (Terminate | Abort), _) | (_, r @ (Terminate | Abort)) => r,
                         (None, r) | (r, None) => r,
                         (Lines(mut s1), Lines(s2)) => {
                             s1.extend(s2);
@@ -264,84 +1582,292 @@ impl Editor {
             }
             Command::DeleteLine => {
                 let mut view = self.view.lock().unwrap();
+                view.checkpoint();
                 view.delete_line();
                 RefreshOrder::AllLines
             }
+            Command::OpenExplorer => self.open_explorer(),
+            Command::ChangeDiffBase => self.prompt_diff_base(),
+            Command::Pending(c) => {
+                self.pending = Some(c);
+                RefreshOrder::None
+            }
+            Command::NextHunk => self.goto_hunk(true),
+            Command::PreviousHunk => self.goto_hunk(false),
+            Command::PreviewHunk => self.preview_hunk(),
+            Command::RevertHunk => self.revert_hunk(),
+            Command::StageHunk => self.stage_lines(true),
+            Command::UnstageHunk => self.stage_lines(false),
+            Command::ToggleVisual => self.toggle_visual(),
+            Command::ToggleBlame => {
+                let result = self.view.lock().unwrap().toggle_blame();
+                if let Err(e) = result {
+                    self.set_message(&format!("Could not blame: {}", e));
+                }
+                RefreshOrder::AllLines
+            }
+            Command::ShowCommit => self.show_commit(),
+            Command::OpenHistory => self.open_history(),
+            Command::HistoryOpen => self.open_revision(),
+            Command::HistoryOlder => self.step_history(true),
+            Command::HistoryNewer => self.step_history(false),
+            Command::HistoryDiff => self.diff_history(),
+            Command::HistoryClose => self.close_history(),
+            Command::OpenBranches => self.open_branches(),
+            Command::BranchCheckout => self.checkout_branch(),
+            Command::BranchCreate => {
+                let prompt = Prompt::new(PromptKind::NewBranch, "New branch from HEAD: ", "");
+                *self.mode.lock().unwrap() = Mode::Prompt(prompt);
+                RefreshOrder::StatusBar
+            }
+            Command::BranchesClose => {
+                self.close_branches();
+                RefreshOrder::AllLines
+            }
+            Command::StashOpen => self.open_stash(),
+            Command::StashPop => {
+                let line = self.cursor_line();
+                let index = match &self.stashes {
+                    Some(stashes) if stashes.showing_diff() => stashes.selected_index(),
+                    _ => line,
+                };
+                self.pop_stash(index)
+            }
+            Command::StashesClose => self.close_stashes(),
+            Command::StashSave => self.stash(),
+            Command::StashPopLatest => self.pop_stash(0),
+            Command::OpenStashes => self.open_stashes(),
+            Command::StartCommit => self.start_commit(),
+            Command::DiffOrig => {
+                let diff = self.saved_side_by_side();
+                self.open_side_by_side(diff)
+            }
+            Command::Substitute(substitution) => self.substitute(&substitution),
+            Command::SideBySide => self.side_by_side(),
+            Command::CloseSideBySide => self.close_side_by_side(),
+            Command::NextConflict => self.goto_conflict(true),
+            Command::PreviousConflict => self.goto_conflict(false),
+            Command::ResolveConflict(resolution) => self.resolve_conflict(resolution),
+            Command::ThreeWay => {
+                let diff = self.three_way_side_by_side();
+                self.open_side_by_side(diff)
+            }
+            Command::Undo => {
+                let mut view = self.view.lock().unwrap();
+                if view.undo() {
+                    let _ = view.refresh_diff();
+                    RefreshOrder::AllLines
+                } else {
+                    drop(view);
+                    self.set_message("Already at oldest change");
+                    RefreshOrder::StatusBar
+                }
+            }
+            Command::ExplorerOpen => self.explorer_open(),
+            Command::ExplorerCollapse => {
+                let line = self.cursor_line();
+                match self.explorer.as_mut().and_then(|e| e.collapse(line)) {
+                    Some(line) => self.show_explorer(line),
+                    None => RefreshOrder::None,
+                }
+            }
+            Command::ExplorerCreate
+            | Command::ExplorerRename
+            | Command::ExplorerMove
+            | Command::ExplorerDelete => self.explorer_prompt(cmd),
+            Command::PromptInput(c) => {
+                if let Mode::Prompt(prompt) = self.mode.lock().unwrap().deref_mut() {
+                    prompt.edit(c);
+                }
+                RefreshOrder::StatusBar
+            }
+            Command::PromptComplete => {
+                if let Mode::Prompt(prompt) = self.mode.lock().unwrap().deref_mut() {
+                    prompt.complete();
+                }
+                RefreshOrder::StatusBar
+            }
+            Command::NextRebaseAction => self.cycle_rebase_action(true),
+            Command::PreviousRebaseAction => self.cycle_rebase_action(false),
+            Command::OpenCommandLine => {
+                let prompt = Prompt::new(PromptKind::Command, ":", "");
+                *self.mode.lock().unwrap() = Mode::Prompt(prompt);
+                RefreshOrder::StatusBar
+            }
+            Command::PromptSubmit => self.submit_prompt(),
+            Command::PromptCancel => self.cancel_prompt(),
         }
     }
 
     /// Toggle the mode of the editor between normal and insert
     fn toggle_mode(&mut self) {
+        let mut view = self.view.lock().unwrap();
         let mut mode = self.mode.lock().unwrap();
         *mode = match mode.clone() {
-            Mode::Normal => Mode::Insert,
-            Mode::Insert => Mode::Normal,
-            Mode::Rename => Mode::Normal,
+            Mode::Normal => {
+                // The whole insertion can be undone at once
+                view.checkpoint();
+                Mode::Insert
+            }
+            Mode::Insert | Mode::Visual => Mode::Normal,
+            Mode::Explorer => Mode::Explorer,
+            Mode::History => Mode::History,
+            Mode::Branches => Mode::Branches,
+            Mode::Stashes => Mode::Stashes,
+            Mode::Diff => Mode::Diff,
+            Mode::Prompt(prompt) => prompt.kind.mode(),
         }
     }
 
+    /// Start editing the path of the current file in the status bar
     fn toggle_rename(&mut self) {
-        let mut mode = self.mode.lock().unwrap();
-        *mode = match mode.clone() {
-            Mode::Normal => Mode::Rename,
-            Mode::Rename => Mode::Normal,
-            _ => Mode::Normal, // Could not be in insert mode
-        }
+        let file_path = self.view.lock().unwrap().file_path();
+        let prompt = Prompt::new(PromptKind::Rename, "Rename to: ", &file_path);
+        *self.mode.lock().unwrap() = Mode::Prompt(prompt);
     }
 
     /// Initialize git operations
     fn init_git_thread(&mut self, sender: Sender<RefreshOrder>) {
         // Spawn a thread to compute the diff in background
+        // (the view may be replaced by a file in a git repository at any time)
+        let (events, receiver) = mpsc::channel();
+        self.git_events = Some(events.clone());
         let view = self.view.clone();
-        let locked_view = view.lock().unwrap();
-        if matches!(locked_view.git_ref(), None) {
-            // If we are not in a git repository, we don't need to compute the diff
-            return;
-        };
-        drop(locked_view);
+        let repo_status = self.repo_status.clone();
 
-        thread::spawn({
-            move || loop {
-                let mut view = view.lock().unwrap();
-                let _ = view.refresh_diff();
+        thread::spawn(move || {
+            // The thread opens its own instance of the repository of the current file
+            // (or compares it to the saved file outside of a repository)
+            let mut vcs: Option<(String, Box<dyn Vcs + Send>)> = None;
+            let mut watched = HashSet::new();
+            // Revision, diff base and blame of the last diff computed
+            let mut last = None;
+            // The first diff is computed right away
+            let mut repository_changed = true;
+            loop {
+                // The view is only locked while the snapshot is taken (the rope is shared)
+                let snapshot = view.lock().unwrap().snapshot();
+                match snapshot {
+                    Some(snapshot) => {
+                        let key = (snapshot.revision, snapshot.base.clone(), snapshot.blame);
+                        let reopened = vcs.as_ref().map(|(dir, _)| dir) != Some(&snapshot.file_dir);
+                        if reopened {
+                            let dir = snapshot.file_dir.clone();
+                            vcs = Some((dir, file::open_vcs(&snapshot.file_dir)));
+                        }
+                        if let Some((_, vcs)) = &mut vcs {
+                            let git_dir = vcs.as_git().and_then(Git::git_dir);
+                            if let Some(git_dir) = git_dir {
+                                if watched.insert(git_dir.to_path_buf()) {
+                                    watch::watch_git_dir(git_dir, events.clone());
+                                }
+                            }
+                            if reopened || repository_changed {
+                                // There is no repository status outside of a repository
+                                let status = vcs.as_git_mut().and_then(|git| {
+                                    let _ = git.refresh_status();
+                                    git.status()
+                                });
+                                *repo_status.lock().unwrap() = status;
+                                let _ = sender.send(RefreshOrder::StatusBar);
+                            }
+                            if reopened || repository_changed || last.as_ref() != Some(&key) {
+                                if let Some(result) = Self::diff_snapshot(vcs.as_mut(), &snapshot) {
+                                    let applied =
+                                        view.lock().unwrap().apply_diff(snapshot.revision, result);
+                                    // Otherwise the buffer was edited meanwhile, the diff
+                                    // is computed again after the next event
+                                    if applied {
+                                        last = Some(key);
+                                        let _ = sender.send(RefreshOrder::GitIndicators);
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    None => {
+                        last = None;
+                        if repo_status.lock().unwrap().take().is_some() {
+                            let _ = sender.send(RefreshOrder::StatusBar);
+                        }
+                    }
+                }
 
-                if let Err(SendError(_)) = sender.send(RefreshOrder::GitIndicators) {
+                // Wait for an event, then for the events to settle
+                let Ok(event) = receiver.recv() else {
                     break;
+                };
+                repository_changed = event == GitEvent::RepositoryChanged;
+                loop {
+                    match receiver.recv_timeout(GIT_DEBOUNCE) {
+                        Ok(event) => repository_changed |= event == GitEvent::RepositoryChanged,
+                        Err(RecvTimeoutError::Timeout) => break,
+                        Err(RecvTimeoutError::Disconnected) => return,
+                    }
                 }
-
-                // Drop the lock before sleeping
-                drop(view);
-                thread::sleep(Duration::from_millis(250));
             }
         });
     }
 
+    /// Compute the diff of a snapshot of the buffer (without locking the view)
+    fn diff_snapshot(vcs: &mut dyn Vcs, snapshot: &Snapshot) -> Option<DiffResult> {
+        vcs.set_diff_base(snapshot.base.clone()).ok()?;
+        let content = snapshot.content.to_string();
+        vcs.diff_result(
+            &snapshot.file_dir,
+            &snapshot.file_name,
+            &content,
+            snapshot.blame,
+        )
+        .ok()
+    }
+
+    /// Notify the git thread (if it is running)
+    fn notify_git(&self, event: GitEvent) {
+        if let Some(events) = &self.git_events {
+            let _ = events.send(event);
+        }
+    }
+
     /// Get the status bar infos
     fn get_status_bar_infos(
         mode: &Arc<Mutex<Mode>>,
-        file_name: &str,
-        ref_name: Option<String>,
+        message: &Arc<Mutex<Option<String>>>,
+        view: &View,
+        repo_status: &Arc<Mutex<Option<RepoStatus>>>,
     ) -> StatusBarInfos {
         let mode = mode.lock().unwrap();
+        // An active prompt takes precedence over the other messages
+        let message = match mode.deref() {
+            Mode::Prompt(prompt) => Some(prompt.text()),
+            _ => message.lock().unwrap().clone(),
+        };
 
         StatusBarInfos {
-            file_name: file_name.into(),
+            file_name: view.file_name(),
+            file_status: view.file_status(),
             mode: mode.clone(),
-            ref_name,
+            repo_status: repo_status.lock().unwrap().clone(),
+            message,
         }
     }
 
-    /// Refresh the TUI
-    fn refresh_tui(
-        tui: &mut TermionTerminalDrawer,
+    /// Refresh the TUI. Returns the exit status of the editor once it is terminated.
+    fn refresh_tui<D: TerminalDrawer>(
+        tui: &mut D,
         view: &mut View,
         status_bar_infos: &StatusBarInfos,
         refresh_order: RefreshOrder,
-    ) {
+    ) -> Option<i32> {
         match refresh_order {
-            RefreshOrder::Terminate => {
+            RefreshOrder::Terminate | RefreshOrder::Abort => {
+                // The command line that quit is not left on the screen
+                tui.draw_status_bar(status_bar_infos);
                 tui.terminate();
-                exit(0);
+                return Some(match refresh_order {
+                    RefreshOrder::Abort => 1,
+                    _ => 0,
+                });
             }
             RefreshOrder::None => (),
             RefreshOrder::CursorPos => tui.move_cursor(view.cursor),
@@ -353,34 +1879,43 @@ impl Editor {
                 if let Some(diff) = view.diff() {
                     tui.draw_diff_markers(diff, view);
                 }
+                // The blame annotations follow the diff
+                if view.blame().is_some() {
+                    tui.draw_lines(view, HashSet::from_iter(0..view.height));
+                }
             }
             RefreshOrder::Lines(lines) => tui.draw_lines(view, lines),
             RefreshOrder::AllLines => {
                 tui.draw(view, status_bar_infos);
-                if let Some(diff) = view.diff() {
-                    tui.draw_diff_markers(diff, view);
-                }
+                // Also clears the markers of the previous view if it was replaced
+                tui.draw_diff_markers(view.diff().unwrap_or_default(), view);
+            }
+            RefreshOrder::Popup(popup) => tui.draw_popup(&popup, view),
+            RefreshOrder::Suspend => tui.suspend(),
+            RefreshOrder::Resume => {
+                tui.resume();
+                Self::refresh_tui(tui, view, status_bar_infos, RefreshOrder::AllLines);
             }
             RefreshOrder::Resize => {
-                let (width, height) = tui.get_term_size();
-
-                view.width = width;
-                view.height = height;
-
+                // The view was resized by the main thread
+                tui.set_term_size((view.width, view.height));
                 Self::refresh_tui(tui, view, status_bar_infos, RefreshOrder::AllLines);
             }
         }
+        None
     }
 
     /// # Initialize the tui drawing thread
-    /// This thread listens from three channels:
-    /// - `cmd_rx`: commands refresh orders from the main thread
-    /// - `diff_changed`: a channel that is notified when the diff has changed
-    /// - `resize_rx`: a channel that is notified when the terminal has been resized
-    /// It then draws the TUI accordingly.
-    fn init_tui_thread(&mut self, refresh_receiver: Receiver<RefreshOrder>) {
-        let mut tui = TermionTerminalDrawer::new();
-
+    /// This thread listens to the refresh orders sent by the main thread (after the
+    /// commands and the resize events) and by the git thread (when the diff has changed).
+    ///
+    /// It then draws the TUI accordingly, until the editor is terminated (the thread then
+    /// returns the exit status).
+    fn init_tui_thread<D: TerminalDrawer + Send + 'static>(
+        &mut self,
+        mut tui: D,
+        refresh_receiver: Receiver<RefreshOrder>,
+    ) -> JoinHandle<i32> {
         // Get the terminal size and initialize the view
         let (width, height) = tui.get_term_size();
         let mut locked_view = self.view.lock().unwrap();
@@ -388,10 +1923,11 @@ impl Editor {
         // Resize the view
         locked_view.height = height;
         locked_view.width = width;
+        self.record_event(&Event::Resize(width, height));
 
         // Get the initial status bar infos
         let status_bar_infos =
-            Self::get_status_bar_infos(&self.mode, &locked_view.file_name(), locked_view.git_ref());
+            Self::get_status_bar_infos(&self.mode, &self.message, &locked_view, &self.repo_status);
 
         // Draw the initial TUI
         tui.draw(&locked_view, &status_bar_infos);
@@ -399,55 +1935,559 @@ impl Editor {
         // Spawn a thread to draw the TUI in background
         let view = self.view.clone();
         let mode = self.mode.clone();
+        let message = self.message.clone();
+        let repo_status = self.repo_status.clone();
         thread::spawn({
-            move || loop {
-                if let Ok(refresh_order) = refresh_receiver.recv() {
+            move || {
+                while let Ok(refresh_order) = refresh_receiver.recv() {
                     let mut locked_view = view.lock().unwrap();
-                    let status_bar_infos = Self::get_status_bar_infos(
-                        &mode,
-                        &locked_view.file_name(),
-                        locked_view.git_ref(),
-                    );
+                    let status_bar_infos =
+                        Self::get_status_bar_infos(&mode, &message, &locked_view, &repo_status);
 
-                    Self::refresh_tui(
+                    let status = Self::refresh_tui(
                         &mut tui,
                         locked_view.deref_mut(),
                         &status_bar_infos,
                         refresh_order,
                     );
-                } else {
-                    break;
+                    if let Some(status) = status {
+                        return status;
+                    }
                 }
+                0
             }
-        });
+        })
+    }
+
+    /// Log an event if the session is recorded
+    fn record_event(&self, event: &Event) {
+        if let Some(recorder) = &self.recorder {
+            recorder.lock().unwrap().record(event);
+        }
     }
 
-    /// Run the editor loop
+    /// Run the editor in the terminal, until the user quits
     pub fn run(&mut self) {
+        self.replay(vec![]);
+    }
+
+    /// Replay the events of a recorded session in the terminal, then let the user take
+    /// over until they quit
+    pub fn replay(&mut self, events: Vec<Event>) {
+        // Give the terminal back to the shell even if the editor crashes or is killed
+        restore::install_panic_hook();
+        signal::init_fatal_signal_handlers();
+
+        // The keys typed and the resize events of the terminal, in the order they happen
+        // (`None` once stdin is closed, which terminates the editor). The signals are
+        // listened to before any other thread is spawned.
+        let (sender, receiver) = mpsc::channel();
+        signal::init_terminal_listener(sender.clone(), TermionTerminalDrawer::text_area_size);
+        thread::spawn(move || {
+            for key in io::stdin().keys().flatten() {
+                if sender.send(Some(Event::Key(key))).is_err() {
+                    return;
+                }
+            }
+            let _ = sender.send(None);
+        });
+        let terminal_events = receiver.into_iter().map_while(|event| event);
+
+        let status = self.run_with(
+            TermionTerminalDrawer::new(),
+            events.into_iter().chain(terminal_events),
+        );
+        exit(status);
+    }
+
+    /// Run the editor loop: execute the commands typed with the keys of the given events
+    /// and draw the editor with the given drawer. Returns the exit status of the editor
+    /// once it is terminated (the events running out terminate it as well).
+    pub fn run_with<D: TerminalDrawer + Send + 'static>(
+        &mut self,
+        tui: D,
+        events: impl IntoIterator<Item = Event>,
+    ) -> i32 {
         let (refresh_sender, refresh_receiver) = mpsc::channel::<RefreshOrder>();
 
         // Initialize git operations if needed
         self.init_git_thread(refresh_sender.clone());
 
-        // Initialize the resize signal handler
-        signal::init_resize_listener(refresh_sender.clone());
-
         // Initialize the TUI thread
-        self.init_tui_thread(refresh_receiver);
+        let tui_thread = self.init_tui_thread(tui, refresh_receiver);
 
-        // Main loop of the editor (waiting for key events)
-        for key in io::stdin().keys().flatten() {
-            let mode = self.mode.lock().unwrap().clone();
-            // Parse the key
-            if let Ok(cmd) = Command::parse(key, &mode) {
-                // Execute the command
-                let refresh_order = self.execute(cmd);
+        // A side-by-side diff opened from the command line needs the size of the terminal
+        if let Some(diff) = self.side_by_side.take() {
+            let refresh_order = self.show_side_by_side(diff);
+            refresh_sender.send(refresh_order).unwrap_or_default();
+        }
+
+        // Main loop of the editor (waiting for key events). The events are recorded in
+        // the order they are handled, and a resize applies to the next keys.
+        let mut terminated = false;
+        for event in events {
+            self.record_event(&event);
+            let key = match event {
+                Event::Key(key) => key,
+                Event::Resize(width, height) => {
+                    let refresh_order = self.resize(width, height);
+                    if let Err(SendError(_)) = refresh_sender.send(refresh_order) {
+                        break;
+                    }
+                    continue;
+                }
+                Event::Resume => {
+                    if let Err(SendError(_)) = refresh_sender.send(RefreshOrder::Resume) {
+                        break;
+                    }
+                    continue;
+                }
+            };
+            // Any key closes the popup
+            if self.popup_open {
+                self.popup_open = false;
+                if let Err(SendError(_)) = refresh_sender.send(RefreshOrder::AllLines) {
+                    break;
+                }
+                continue;
+            }
 
+            if let Ok(refresh_order) = self.handle_key(key) {
                 // Send the refresh order to the TUI
+                terminated = matches!(refresh_order, RefreshOrder::Terminate | RefreshOrder::Abort);
                 if let Err(SendError(_)) = refresh_sender.send(refresh_order) {
                     break;
                 }
+                if terminated {
+                    break;
+                }
+            }
+        }
+        if !terminated {
+            refresh_sender
+                .send(RefreshOrder::Terminate)
+                .unwrap_or_default();
+        }
+        tui_thread.join().unwrap_or(1)
+    }
+
+    /// Resize the view to the text area of the screen
+    fn resize(&mut self, width: usize, height: usize) -> RefreshOrder {
+        let mut view = self.view.lock().unwrap();
+        // The columns of a side-by-side diff are rendered again to fit the new width
+        // (they have the same lines, the cursor stays where it was)
+        if let (Mode::Diff, Some(diff)) = (&*self.mode.lock().unwrap(), &self.side_by_side) {
+            let mut rendered = Self::render_side_by_side(diff, width);
+            rendered.start_line = view.start_line;
+            rendered.start_col = view.start_col;
+            rendered.cursor = view.cursor;
+            *view = rendered;
+        }
+        view.width = width;
+        view.height = height;
+        RefreshOrder::Resize
+    }
+
+    /// Parse a key and execute the command (it may be the second key of a sequence)
+    fn handle_key(&mut self, key: Key) -> Result<RefreshOrder, &'static str> {
+        let mode = self.mode.lock().unwrap().clone();
+        // Messages only last until the next key
+        *self.message.lock().unwrap() = None;
+        let cmd = match self.pending.take() {
+            Some(first) => Command::parse_sequence(first, key),
+            None => Command::parse(key, &mode),
+        }?;
+        Ok(self.run_command(cmd))
+    }
+
+    /// Execute a command, then update what depends on the content of the buffer
+    fn run_command(&mut self, cmd: Command) -> RefreshOrder {
+        // Saving changes the status of the working tree
+        let event = match cmd {
+            Command::Save => GitEvent::RepositoryChanged,
+            _ => GitEvent::BufferChanged,
+        };
+        // Execute the command
+        let mut refresh_order = self.execute(cmd);
+        self.notify_git(event);
+        // Keep the highlighted conflicts in sync with the edits
+        let mode = self.mode.lock().unwrap().clone();
+        if self.has_conflicts && matches!(mode, Mode::Normal | Mode::Insert) {
+            let highlights = self.view.lock().unwrap().highlights.clone();
+            self.refresh_conflicts();
+            if self.view.lock().unwrap().highlights != highlights {
+                refresh_order = RefreshOrder::AllLines;
+            }
+        }
+        // Same for the structure of a file opened by git
+        if matches!(mode, Mode::Normal | Mode::Insert) && self.refresh_git_file() {
+            refresh_order = RefreshOrder::AllLines;
+        }
+        refresh_order
+    }
+
+    /// Run the lines of a script without a terminal (see the `script` module), the
+    /// messages are printed on stderr. Returns the exit status of the editor.
+    pub fn run_script(&mut self, script: &[String]) -> i32 {
+        // The commands behave as in a terminal of the default size
+        {
+            let mut view = self.view.lock().unwrap();
+            view.width = 80;
+            view.height = 24;
+        }
+        for (index, line) in script.iter().enumerate() {
+            let refresh_order = match script::parse_line(line) {
+                None => continue,
+                Some(Step::CommandLine(input)) => {
+                    Command::parse_command_line(&input).map(|cmd| self.run_command(cmd))
+                }
+                Some(Step::Keys(keys)) => self.type_keys(keys),
+            };
+            self.print_message();
+            match refresh_order {
+                Ok(RefreshOrder::Terminate) => return self.discard_changes(),
+                Ok(RefreshOrder::Abort) => return script::EXIT_FAILURE,
+                Ok(_) => (),
+                Err(e) => {
+                    eprintln!("giga: line {}: {}: {}", index + 1, e, line.trim());
+                    return script::EXIT_INVALID;
+                }
+            }
+        }
+        self.discard_changes()
+    }
+
+    /// Type the keys of a line of a script, until one of them quits the editor
+    fn type_keys(&mut self, keys: Vec<Key>) -> Result<RefreshOrder, &'static str> {
+        let mut refresh_order = RefreshOrder::None;
+        for key in keys {
+            // There is no popup to close
+            self.popup_open = false;
+            refresh_order = self.handle_key(key)?;
+            self.print_message();
+            if matches!(refresh_order, RefreshOrder::Terminate | RefreshOrder::Abort) {
+                break;
+            }
+        }
+        Ok(refresh_order)
+    }
+
+    /// Print the message of the status bar on stderr (for the scripts)
+    fn print_message(&self) {
+        if let Some(message) = self.message.lock().unwrap().take() {
+            eprintln!("giga: {}", message);
+        }
+    }
+
+    /// Exit status of a script quitting the editor: a failure if the buffer has unsaved
+    /// changes (they are lost)
+    fn discard_changes(&self) -> i32 {
+        let view = self.view.lock().unwrap();
+        if view.is_modified() {
+            eprintln!("giga: {}: unsaved changes discarded", view.file_path());
+            return script::EXIT_FAILURE;
+        }
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use terminal::screen::{ScreenTerminalDrawer, Style};
+    use test_utils::temp_dir;
+
+    /// The keys typed to enter some text (`\x1b` stands for the escape key)
+    fn keys(text: &str) -> Vec<Key> {
+        text.chars()
+            .map(|c| match c {
+                '\x1b' => Key::Esc,
+                c => Key::Char(c),
+            })
+            .collect()
+    }
+
+    /// Run the editor on a screen of 40x8 cells with the given keys, returns the exit
+    /// status and the screen
+    fn run(path: &Path, input: &str) -> (i32, ScreenTerminalDrawer) {
+        let drawer = ScreenTerminalDrawer::new(40, 8);
+        let mut editor = Editor::open(path.to_str().unwrap());
+        let events = keys(input).into_iter().map(Event::Key);
+        let status = editor.run_with(drawer.clone(), events);
+        (status, drawer)
+    }
+
+    #[test]
+    fn edit_and_save() {
+        let dir = temp_dir("editor-edit");
+        let path = dir.join("file.txt");
+        fs::write(&path, "first\nsecond\nthird\n").unwrap();
+
+        let (status, drawer) = run(&path, "jdix\x1bw:q\n");
+        assert_eq!(status, 0);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "first\nxthird\n");
+        drawer.screen(|screen| {
+            assert!(screen.terminated);
+            assert_eq!(screen.text()[..3], ["first", "xthird", ""]);
+            assert_eq!(screen.line(0), "  1  first");
+            assert_eq!(screen.cell(0, 0).style, Style::LineNumber);
+            // The command line is closed once the editor quit
+            assert!(screen.status_bar().starts_with("NORMAL"));
+            // The cursor is after the inserted character
+            assert_eq!(screen.cursor, (6, 1));
+        });
+    }
+
+    #[test]
+    fn modes_and_messages() {
+        let dir = temp_dir("editor-modes");
+        let path = dir.join("file.txt");
+        fs::write(&path, "text\n").unwrap();
+
+        // The keys running out terminate the editor
+        let (status, drawer) = run(&path, "Ai!");
+        assert_eq!(status, 0);
+        drawer.screen(|screen| {
+            assert_eq!(screen.text()[0], "texti!");
+            assert!(screen.status_bar().starts_with("INSERT"));
+            assert_eq!(screen.cursor, (11, 0));
+        });
+        // Nothing was saved
+        assert_eq!(fs::read_to_string(&path).unwrap(), "text\n");
+
+        let (_, drawer) = run(&path, ":nope\n");
+        drawer.screen(|screen| {
+            assert!(screen.status_bar().contains("Not an editor command: nope"));
+            assert_eq!(screen.cell(0, 7).style, Style::StatusBar);
+        });
+
+        // `:cq` exits with an error status
+        let (status, _) = run(&path, ":cq\n");
+        assert_eq!(status, 1);
+    }
+
+    #[test]
+    fn run_scripts() {
+        let dir = temp_dir("editor-script");
+        let path = dir.join("file.txt");
+        let script = |lines: &[&str]| {
+            fs::write(&path, "a x a\nb a\nc\n").unwrap();
+            let mut editor = Editor::open(path.to_str().unwrap());
+            let script = lines
+                .iter()
+                .map(|line| line.to_string())
+                .collect::<Vec<_>>();
+            let status = editor.run_script(&script);
+            (status, fs::read_to_string(&path).unwrap())
+        };
+
+        assert_eq!(
+            script(&[":%s/a/b/g", "# comment", "", ":wq"]),
+            (0, "b x b\nb b\nc\n".into())
+        );
+        // Keys, a substitution on the line of the cursor, the script ends after `:x`
+        assert_eq!(
+            script(&["jd", "Oz<Esc>", ":s/z/y/", ":x", "dd"]),
+            (0, "a x a\ny\nc\n".into())
+        );
+        // Unsaved changes are discarded
+        assert_eq!(script(&["d"]), (1, "a x a\nb a\nc\n".into()));
+        assert_eq!(script(&["d", ":cq"]).0, 1);
+        // Invalid commands stop the script
+        assert_eq!(script(&["d", ":nope", ":wq"]).0, 2);
+        assert_eq!(script(&[":s/a", ":wq"]).0, 2);
+        assert_eq!(script(&["Z", ":wq"]).0, 2);
+    }
+
+    #[test]
+    fn suspend_and_resume() {
+        let dir = temp_dir("editor-suspend");
+        let path = dir.join("file.txt");
+        fs::write(&path, "text\n").unwrap();
+
+        // Ctrl-Z gives the terminal back, the editor keeps its state
+        let mut drawer = ScreenTerminalDrawer::new(40, 8);
+        let mut editor = Editor::open(path.to_str().unwrap());
+        let events = script::parse_keys("A!<C-z>").into_iter().map(Event::Key);
+        editor.run_with(drawer.clone(), events);
+        drawer.screen(|screen| assert!(screen.suspended));
+
+        // Resuming redraws the whole editor
+        drawer.clear();
+        let mut view = editor.view.lock().unwrap();
+        let infos =
+            Editor::get_status_bar_infos(&editor.mode, &editor.message, &view, &editor.repo_status);
+        Editor::refresh_tui(&mut drawer, &mut view, &infos, RefreshOrder::Resume);
+        drawer.screen(|screen| {
+            assert!(!screen.suspended);
+            assert_eq!(screen.text()[0], "text!");
+            assert!(screen.status_bar().starts_with("INSERT"));
+        });
+    }
+
+    #[test]
+    fn record_and_replay() {
+        let dir = temp_dir("editor-record");
+        let path = dir.join("file.txt");
+        let log = dir.join("session.log");
+        let log = log.to_str().unwrap();
+        fs::write(&path, "first\nsecond\n").unwrap();
+
+        let drawer = ScreenTerminalDrawer::new(40, 8);
+        let mut editor = Editor::open(path.to_str().unwrap());
+        editor.record(Recorder::create(log).unwrap());
+        let keys = |keys| script::parse_keys(keys).into_iter().map(Event::Key);
+        let session: Vec<_> = keys("jA!<Esc>:nope<CR>")
+            .chain([Event::Resize(20, 4), Event::Resume])
+            .chain(keys("k"))
+            .collect();
+        editor.run_with(drawer.clone(), session.clone());
+        let recorded = drawer.screen(|screen| (screen.text(), screen.status_bar()));
+        assert_eq!(recorded.0, ["first", "second!", "", ""]);
+
+        // The size of the screen when the session started, then the events in the order
+        // they were handled
+        let events = record::read_log(log).unwrap();
+        assert_eq!(events[0], Event::Resize(35, 7));
+        assert_eq!(events[1..], session);
+
+        // The replay starts from the same file, on a screen of another size
+        fs::write(&path, "first\nsecond\n").unwrap();
+        let drawer = ScreenTerminalDrawer::new(60, 20);
+        let mut editor = Editor::open(path.to_str().unwrap());
+        editor.run_with(drawer.clone(), events);
+        let replayed = drawer.screen(|screen| (screen.text(), screen.status_bar()));
+        assert_eq!(replayed, recorded);
+    }
+
+    #[test]
+    fn browse_directory() {
+        let dir = temp_dir("editor-explorer");
+        fs::create_dir(dir.join("sub")).unwrap();
+        fs::write(dir.join("b.txt"), "").unwrap();
+        fs::write(dir.join("a.txt"), "content of a\n").unwrap();
+
+        let (_, drawer) = run(&dir, "");
+        drawer.screen(|screen| {
+            assert_eq!(screen.text()[..3], ["  ▸ sub/", "    a.txt", "    b.txt"]);
+            assert!(screen.status_bar().starts_with("EXPLORER"));
+        });
+
+        // Open the file under the cursor
+        let (_, drawer) = run(&dir, "j\n");
+        drawer.screen(|screen| {
+            assert_eq!(screen.text()[0], "content of a");
+            assert!(screen.status_bar().starts_with("NORMAL"));
+        });
+    }
+
+    #[test]
+    fn revert_to_head() {
+        let dir = temp_dir("editor-revert");
+        let repo = git2::Repository::init(&dir).unwrap();
+        let path = dir.join("file.txt");
+        let mut index = repo.index().unwrap();
+        // Committed, then the change of the second line is staged
+        for (content, commit) in [("one\ntwo\n", true), ("one\nTWO\n", false)] {
+            fs::write(&path, content).unwrap();
+            index.add_path(Path::new("file.txt")).unwrap();
+            index.write().unwrap();
+            if commit {
+                let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+                let signature = git2::Signature::now("giga", "giga@example.com").unwrap();
+                repo.commit(Some("HEAD"), &signature, &signature, "commit", &tree, &[])
+                    .unwrap();
             }
         }
+        fs::write(&path, "one\nTWO!\n").unwrap();
+
+        // The line is reverted to HEAD, not to the index
+        run(&path, "jgrw");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "one\ntwo\n");
+        // The first line did not change
+        let (_, drawer) = run(&path, "gr");
+        drawer.screen(|screen| assert!(screen.status_bar().contains("Nothing to revert")));
+    }
+
+    #[test]
+    fn checkout_with_unsaved_changes() {
+        let dir = temp_dir("editor-checkout");
+        let repo = git2::Repository::init(&*dir).unwrap();
+        let path = dir.join("file.txt");
+        fs::write(&path, "one\n").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("file.txt")).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = git2::Signature::now("giga", "giga@example.com").unwrap();
+        let commit = repo
+            .commit(Some("HEAD"), &signature, &signature, "commit", &tree, &[])
+            .unwrap();
+        repo.branch("aaa", &repo.find_commit(commit).unwrap(), false)
+            .unwrap();
+
+        // The checkout is refused, the buffer keeps its changes
+        let (_, drawer) = run(&path, "A!\x1bgBk\n");
+        drawer.screen(|screen| {
+            assert!(screen.status_bar().contains("Could not check out aaa"));
+        });
+        let (_, drawer) = run(&path, "A!\x1bgBk\nq");
+        drawer.screen(|screen| assert_eq!(screen.text()[0], "one!"));
+        assert_ne!(repo.head().unwrap().shorthand(), Some("aaa"));
+    }
+
+    #[test]
+    fn abort_commit() {
+        let dir = temp_dir("editor-commit");
+        let repo = git2::Repository::init(&dir).unwrap();
+        let path = dir.join("file.txt");
+        fs::write(&path, "one\n").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("file.txt")).unwrap();
+        index.write().unwrap();
+
+        // `:cq` closes the commit message buffer, the working buffer keeps its changes
+        let (status, drawer) = run(&path, "A!\x1b:Gcommit\niMessage\x1b:w\n:cq\n");
+        assert_eq!(status, 0);
+        drawer.screen(|screen| {
+            assert_eq!(screen.text()[0], "one!");
+            assert!(screen.status_bar().contains("Commit aborted"));
+        });
+        assert!(repo.head().is_err());
+    }
+
+    #[test]
+    fn compare_files() {
+        let dir = temp_dir("editor-compare");
+        let (left, right) = (dir.join("left.txt"), dir.join("right.txt"));
+        fs::write(&left, "same\nold\n").unwrap();
+        fs::write(&right, "same\nnew\n").unwrap();
+        let (left, right) = (left.to_str().unwrap(), right.to_str().unwrap());
+
+        let mut editor = Editor::open_diff(left, right).unwrap();
+        let drawer = ScreenTerminalDrawer::new(40, 8);
+        editor.run_with(drawer.clone(), []);
+        drawer.screen(|screen| {
+            assert!(screen.text()[1].contains("old") && screen.text()[1].contains("new"));
+        });
+
+        // The columns fit the width of the screen after a resize
+        let mut editor = Editor::open_diff(left, right).unwrap();
+        let drawer = ScreenTerminalDrawer::new(40, 8);
+        editor.run_with(drawer.clone(), [Event::Resize(15, 7)]);
+        drawer.screen(|screen| {
+            let line = &screen.text()[1];
+            assert!(line.contains("old") && line.contains("new"));
+            assert!(line.chars().count() <= 15);
+        });
+
+        // A file that cannot be read is an error, not an empty file
+        let missing = dir.join("missing.txt");
+        let error = Editor::open_diff(missing.to_str().unwrap(), right)
+            .err()
+            .unwrap();
+        assert!(error.to_string().starts_with(missing.to_str().unwrap()));
     }
 }
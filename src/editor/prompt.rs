@@ -0,0 +1,163 @@
+//! # Prompts in the status bar
+//!
+//! Some commands need an argument typed by the user (a file name, a confirmation...).
+//! While a prompt is active, the editor is in `Mode::Prompt` and the status bar
+//! displays the prompt message followed by the user input. The `PromptKind` tells
+//! the editor what to do with the input once it is submitted.
+//!
+//! Prompts asking for a path support tab completion (see `Prompt::complete`).
+
+use std::{fs, path::Path};
+
+use super::Mode;
+
+/// What the input of a prompt will be used for
+#[derive(Clone, Debug, PartialEq)]
+pub enum PromptKind {
+    /// Rename (or move) the current file
+    Rename,
+    /// Confirm that the given file can be overwritten by the rename
+    RenameOverwrite(String),
+    /// Choose what the buffer is compared to in the gutter
+    DiffBase,
+    /// Create a file next to the explorer entry at the given line
+    ExplorerCreate(usize),
+    /// Rename the explorer entry at the given line
+    ExplorerRename(usize),
+    /// Move the explorer entry at the given line to another directory
+    ExplorerMove(usize),
+    /// Confirm the deletion of the explorer entry at the given line
+    ExplorerDelete(usize),
+    /// Run an editor command typed after `:`
+    Command,
+    /// Create a branch at HEAD with the given name
+    NewBranch,
+}
+
+impl PromptKind {
+    /// The mode the editor goes back to once the prompt is closed
+    pub fn mode(&self) -> Mode {
+        match self {
+            PromptKind::ExplorerCreate(_)
+            | PromptKind::ExplorerRename(_)
+            | PromptKind::ExplorerMove(_)
+            | PromptKind::ExplorerDelete(_) => Mode::Explorer,
+            PromptKind::NewBranch => Mode::Branches,
+            _ => Mode::Normal,
+        }
+    }
+}
+
+/// A prompt waiting for user input
+#[derive(Clone, Debug, PartialEq)]
+pub struct Prompt {
+    /// What the input will be used for
+    pub kind: PromptKind,
+    /// The message displayed before the input
+    pub message: String,
+    /// The text typed by the user
+    pub input: String,
+}
+
+impl Prompt {
+    /// Create a new prompt with a pre-filled input
+    pub fn new(kind: PromptKind, message: &str, input: &str) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+            input: input.into(),
+        }
+    }
+
+    /// Edit the input: append a character or remove the last one
+    pub fn edit(&mut self, c: Option<char>) {
+        match c {
+            Some(c) => self.input.push(c),
+            None => {
+                self.input.pop();
+            }
+        }
+    }
+
+    /// Complete the path being typed with the entries of its directory:
+    /// the input is extended with the longest prefix common to all the matching
+    /// entries, and a `/` is appended if a single directory matches.
+    pub fn complete(&mut self) {
+        if self.kind != PromptKind::Rename {
+            return;
+        }
+        let (dir, partial) = match self.input.rfind('/') {
+            Some(index) => self.input.split_at(index + 1),
+            None => ("", self.input.as_str()),
+        };
+        let Ok(entries) = fs::read_dir(if dir.is_empty() { "." } else { dir }) else {
+            return;
+        };
+
+        let matches = entries
+            .flatten()
+            .map(|entry| entry.file_name().to_string_lossy().to_string())
+            .filter(|name| name.starts_with(partial))
+            .collect::<Vec<_>>();
+        let Some(first) = matches.first() else {
+            return;
+        };
+        let common = matches.iter().fold(first.clone(), |common, name| {
+            common
+                .chars()
+                .zip(name.chars())
+                .take_while(|(a, b)| a == b)
+                .map(|(a, _)| a)
+                .collect()
+        });
+
+        let mut completed = format!("{}{}", dir, common);
+        if matches.len() == 1 && Path::new(&completed).is_dir() {
+            completed.push('/');
+        }
+        self.input = completed;
+    }
+
+    /// Text displayed in the status bar
+    pub fn text(&self) -> String {
+        format!("{}{}", self.message, self.input)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prompt_edit() {
+        let mut prompt = Prompt::new(PromptKind::ExplorerCreate(0), "New file: ", "sr");
+        prompt.edit(Some('c'));
+        assert_eq!(prompt.text(), "New file: src");
+        prompt.edit(None);
+        prompt.edit(None);
+        prompt.edit(None);
+        prompt.edit(None);
+        assert_eq!(prompt.text(), "New file: ");
+    }
+
+    #[test]
+    fn prompt_complete_path() {
+        let mut prompt = Prompt::new(PromptKind::Rename, "Rename to: ", "tes");
+        prompt.complete();
+        assert_eq!(prompt.input, "tests/");
+        prompt.edit(Some('s'));
+        prompt.complete();
+        assert_eq!(prompt.input, "tests/sample.txt");
+        // No match: nothing happens
+        prompt.input = "tests/nothing".into();
+        prompt.complete();
+        assert_eq!(prompt.input, "tests/nothing");
+    }
+
+    #[test]
+    fn prompt_complete_only_paths() {
+        let mut prompt = Prompt::new(PromptKind::ExplorerDelete(0), "Delete? ", "tes");
+        prompt.complete();
+        assert_eq!(prompt.input, "tes");
+    }
+}
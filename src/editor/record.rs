@@ -0,0 +1,154 @@
+//! # Recording and replaying sessions
+//!
+//! `giga --record session.log file` logs the keys typed in the editor and the size of
+//! the terminal (when it starts and each time it is resized), one event per line after
+//! the time elapsed since the start of the session, in milliseconds:
+//! ```text
+//! 0 resize 75x23
+//! 1250 key i
+//! 1410 key <Esc>
+//! ```
+//! The keys are written like in the scripts (see `script::key_name`), the sizes are the
+//! ones of the text area. `resume` is logged when the editor is resumed after a Ctrl-Z.
+//!
+//! `giga --replay session.log file` feeds the events back to the editor in the same order,
+//! then the user takes over. The timestamps are only informational (they tell how the
+//! session went when reading a bug report): the replay does not wait between the events,
+//! so that it does not depend on the timing of the session. The resize events only change
+//! the size of an in-memory screen (the tests replay sessions on it): a terminal keeps the
+//! size of its window.
+
+use std::{
+    fmt::Display,
+    fs::File,
+    io::{self, Write},
+    str::FromStr,
+    time::Instant,
+};
+
+use termion::event::Key;
+
+use super::script;
+
+/// An input of the editor
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Event {
+    /// A key typed by the user
+    Key(Key),
+    /// The size of the text area changed (width, height)
+    Resize(usize, usize),
+    /// The editor was resumed after being suspended (the screen is drawn again)
+    Resume,
+}
+
+impl Display for Event {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Event::Key(key) => write!(f, "key {}", script::key_name(*key)),
+            Event::Resize(width, height) => write!(f, "resize {}x{}", width, height),
+            Event::Resume => write!(f, "resume"),
+        }
+    }
+}
+
+impl FromStr for Event {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "resume" {
+            return Ok(Event::Resume);
+        }
+        match s.split_once(' ') {
+            Some(("key", name)) => match script::parse_keys(name)[..] {
+                [key] => Ok(Event::Key(key)),
+                _ => Err("Invalid key"),
+            },
+            Some(("resize", size)) => {
+                let (width, height) = size.split_once('x').ok_or("Invalid size")?;
+                match (width.parse(), height.parse()) {
+                    (Ok(width), Ok(height)) => Ok(Event::Resize(width, height)),
+                    _ => Err("Invalid size"),
+                }
+            }
+            _ => Err("Unknown event"),
+        }
+    }
+}
+
+/// Writes the events of a session to a log
+pub struct Recorder {
+    log: File,
+    /// Start of the session
+    start: Instant,
+}
+
+impl Recorder {
+    pub fn create(path: &str) -> io::Result<Self> {
+        Ok(Self {
+            log: File::create(path)?,
+            start: Instant::now(),
+        })
+    }
+
+    /// Log an event (as soon as it happens, so that the log is complete even if the
+    /// editor crashes)
+    pub fn record(&mut self, event: &Event) {
+        let elapsed = self.start.elapsed().as_millis();
+        writeln!(self.log, "{} {}", elapsed, event).unwrap_or_default();
+    }
+}
+
+/// Read the events of a recorded session
+pub fn read_log(path: &str) -> io::Result<Vec<Event>> {
+    let invalid = |index: usize, e: &str| {
+        let message = format!("{}: line {}: {}", path, index + 1, e);
+        io::Error::new(io::ErrorKind::InvalidData, message)
+    };
+    let content = std::fs::read_to_string(path)?;
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.is_empty())
+        .map(|(index, line)| {
+            // The time of the event is only informational
+            let (_, event) = line
+                .split_once(' ')
+                .ok_or_else(|| invalid(index, "Missing event"))?;
+            event.parse().map_err(|e| invalid(index, e))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::editor::test_utils::temp_dir;
+
+    #[test]
+    fn record_and_read_log() {
+        let dir = temp_dir("record");
+        let path = dir.join("session.log");
+        let path = path.to_str().unwrap();
+        let events = [
+            Event::Resize(75, 23),
+            Event::Key(Key::Char('i')),
+            Event::Key(Key::Char(' ')),
+            Event::Key(Key::Esc),
+            Event::Resume,
+        ];
+        let mut recorder = Recorder::create(path).unwrap();
+        for event in &events {
+            recorder.record(event);
+        }
+        let log = std::fs::read_to_string(path).unwrap();
+        let lines = log.lines().collect::<Vec<_>>();
+        assert!(lines[0].ends_with(" resize 75x23"));
+        assert!(lines[2].ends_with(" key <Space>"));
+        assert_eq!(read_log(path).unwrap(), events);
+
+        std::fs::write(path, "0 resize 75x23\n10 key <C-a>\n20 resize 80\n").unwrap();
+        let error = read_log(path).unwrap_err();
+        assert!(error.to_string().ends_with("line 3: Invalid size"));
+        assert_eq!("key ab".parse::<Event>(), Err("Invalid key"));
+    }
+}
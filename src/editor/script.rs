@@ -0,0 +1,183 @@
+//! # Headless scripted editing
+//!
+//! `giga --script cmds.txt file` (or `giga -c ':%s/a/b/g' -c ':wq' file`) edits the file
+//! without a terminal: each line of the script is run in order, then the editor exits.
+//! - A line starting with `:` is a command line (`:w`, `:q`, `:%s/old/new/g`...).
+//! - Any other line is a sequence of keys, typed as in the editor (`jdd`, `ihello<Esc>`).
+//!   The special keys are written between `<` and `>`: `<Esc>`, `<CR>`, `<BS>`, `<Tab>`,
+//!   `<Up>`, `<F1>`, `<C-x>`, `<A-x>`, `<lt>` for `<`... (see `key_name`).
+//! - The blank lines and the lines starting with `#` are ignored.
+//!
+//! The messages of the editor are printed on stderr. The exit status is 0 once the
+//! script is done, 1 if it aborted (`:cq`) or if unsaved changes were discarded, and 2
+//! if a line of the script is not a valid command (or if the script has no file to edit).
+
+use termion::event::Key;
+
+/// Exit status when the script quits with `:cq` or discards unsaved changes
+pub const EXIT_FAILURE: i32 = 1;
+/// Exit status when the script is invalid (or has no file to edit)
+pub const EXIT_INVALID: i32 = 2;
+
+/// A line of a script
+#[derive(Debug, PartialEq)]
+pub enum Step {
+    /// Input of the command line (without the `:`)
+    CommandLine(String),
+    /// Keys to type
+    Keys(Vec<Key>),
+}
+
+/// Parse a line of a script (`None` for the blank lines and the comments)
+pub fn parse_line(line: &str) -> Option<Step> {
+    let line = line.trim_end_matches(['\r', '\n']);
+    if line.trim().is_empty() || line.starts_with('#') {
+        return None;
+    }
+    Some(match line.strip_prefix(':') {
+        Some(input) => Step::CommandLine(input.to_string()),
+        None => Step::Keys(parse_keys(line)),
+    })
+}
+
+/// Parse a sequence of keys, with the special keys written between `<` and `>`
+pub fn parse_keys(text: &str) -> Vec<Key> {
+    let mut keys = vec![];
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        let special = rest
+            .find('>')
+            .filter(|_| c == '<')
+            .and_then(|end| special_key(&rest[1..end]).map(|key| (key, end + 1)));
+        match special {
+            Some((key, len)) => {
+                keys.push(key);
+                rest = &rest[len..];
+            }
+            None => {
+                keys.push(Key::Char(c));
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+    keys
+}
+
+/// Names of the special keys (written between `<` and `>`)
+const SPECIAL_KEYS: [(&str, Key); 17] = [
+    ("Esc", Key::Esc),
+    ("CR", Key::Char('\n')),
+    ("BS", Key::Backspace),
+    ("Tab", Key::Char('\t')),
+    ("Space", Key::Char(' ')),
+    ("lt", Key::Char('<')),
+    ("Left", Key::Left),
+    ("Right", Key::Right),
+    ("Up", Key::Up),
+    ("Down", Key::Down),
+    ("Home", Key::Home),
+    ("End", Key::End),
+    ("PageUp", Key::PageUp),
+    ("PageDown", Key::PageDown),
+    ("S-Tab", Key::BackTab),
+    ("Del", Key::Delete),
+    ("Insert", Key::Insert),
+];
+
+/// The key written `<name>`
+fn special_key(name: &str) -> Option<Key> {
+    if let Some((_, key)) = SPECIAL_KEYS.iter().find(|(special, _)| *special == name) {
+        return Some(*key);
+    }
+    let single = |c: &str| {
+        let mut chars = c.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Some(c),
+            _ => None,
+        }
+    };
+    match name {
+        "Enter" => Some(Key::Char('\n')),
+        "Nul" => Some(Key::Null),
+        _ if name.starts_with("C-") => single(&name[2..]).map(Key::Ctrl),
+        _ if name.starts_with("A-") => single(&name[2..]).map(Key::Alt),
+        _ => name.strip_prefix('F')?.parse().ok().map(Key::F),
+    }
+}
+
+/// Write a key as in the scripts: a character, or the name of a special key
+pub fn key_name(key: Key) -> String {
+    if let Some((name, _)) = SPECIAL_KEYS.iter().find(|(_, special)| *special == key) {
+        return format!("<{}>", name);
+    }
+    match key {
+        Key::Char(c) => c.to_string(),
+        Key::Ctrl(c) => format!("<C-{}>", c),
+        Key::Alt(c) => format!("<A-{}>", c),
+        Key::F(n) => format!("<F{}>", n),
+        _ => "<Nul>".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_script_lines() {
+        assert_eq!(
+            parse_line(":%s/a/b/g\n"),
+            Some(Step::CommandLine("%s/a/b/g".into()))
+        );
+        assert_eq!(
+            parse_line("dd"),
+            Some(Step::Keys(vec![Key::Char('d'), Key::Char('d')]))
+        );
+        assert_eq!(parse_line("  "), None);
+        assert_eq!(parse_line("# comment"), None);
+    }
+
+    #[test]
+    fn parse_special_keys() {
+        assert_eq!(
+            parse_keys("ia<Esc><C-a><lt>b>"),
+            vec![
+                Key::Char('i'),
+                Key::Char('a'),
+                Key::Esc,
+                Key::Ctrl('a'),
+                Key::Char('<'),
+                Key::Char('b'),
+                Key::Char('>'),
+            ]
+        );
+        // Not a special key
+        assert_eq!(
+            parse_keys("<x>"),
+            vec![Key::Char('<'), Key::Char('x'), Key::Char('>')]
+        );
+    }
+
+    #[test]
+    fn write_keys() {
+        let keys = [
+            Key::Char('a'),
+            Key::Char(' '),
+            Key::Char('<'),
+            Key::Char('\n'),
+            Key::Up,
+            Key::BackTab,
+            Key::F(5),
+            Key::Ctrl('z'),
+            Key::Alt('x'),
+            Key::Null,
+        ];
+        let names = keys.map(key_name);
+        assert_eq!(
+            names.concat(),
+            "a<Space><lt><CR><Up><S-Tab><F5><C-z><A-x><Nul>"
+        );
+        // The names are parsed back into the same keys
+        assert_eq!(parse_keys(&names.concat()), keys);
+    }
+}
@@ -1,32 +1,138 @@
-//! # Intercepting terminal resize events
+//! # Intercepting signals
 //!
-//! To intercept terminal resize events, we need to register a channel to listen to the SIGWINCH.
-//! To do so, we use the libc crate to register a C function as a signal handler.
+//! The signals of the terminal (SIGWINCH, SIGTSTP and SIGCONT) are blocked in every thread
+//! of the editor, and a thread waits for them with `sigwait`: it can then do anything a
+//! thread does (a signal handler can only call async-signal-safe functions, which sending
+//! through a channel is not). The new size of the terminal is sent as an event, along with
+//! the keys typed, so that the main thread handles it (and records it) between the same
+//! keys as it happened.
 //!
-//! This is a bit hacky but it works. It is the only part of the code which is `unsafe`.
+//! The fatal signals (SIGTERM, SIGHUP...) are intercepted by handlers, to give the terminal
+//! back to the shell (see `terminal::restore`) before the editor is killed.
+//!
+//! In raw mode, Ctrl-Z does not send SIGTSTP: the editor sends it to itself (see `suspend`)
+//! once the terminal is given back. The listener thread gives the terminal back as well when
+//! SIGTSTP comes from elsewhere (`kill -TSTP`), then stops the editor with the default
+//! action of the signal. The drawer takes the terminal again and redraws the editor when it
+//! is continued (`fg`), at the size of the terminal then.
+//!
+//! This is a bit hacky but it works. Along with the `watch` and `terminal::restore` modules,
+//! it is the only part of the code which is `unsafe`.
+
+use std::{
+    ptr,
+    sync::{mpsc::Sender, Condvar, Mutex},
+    thread,
+};
+
+use super::{record::Event, terminal::restore};
 
-use std::sync::mpsc::Sender;
+/// Signals of the terminal, waited for by the listener thread
+const TERMINAL_SIGNALS: [libc::c_int; 3] = [libc::SIGWINCH, libc::SIGTSTP, libc::SIGCONT];
 
-use super::RefreshOrder;
+/// Number of times the editor was continued, to wait for it in `suspend`
+static RESUMED: (Mutex<u64>, Condvar) = (Mutex::new(0), Condvar::new());
 
-// We have to use a global variable because the signal handler has to be a C function
-static mut TX: Option<Sender<RefreshOrder>> = None;
+// Send the resize events of the terminal (with the size given by `size`) through the
+// channel of the events, and give the terminal back when the editor is stopped.
+// This has to be called before any other thread is spawned: the threads inherit the
+// signals blocked.
+pub fn init_terminal_listener(tx: Sender<Option<Event>>, size: fn() -> (usize, usize)) {
+    let signals = unsafe {
+        let mut signals = std::mem::zeroed();
+        libc::sigemptyset(&mut signals);
+        for signal in TERMINAL_SIGNALS {
+            libc::sigaddset(&mut signals, signal);
+        }
+        libc::pthread_sigmask(libc::SIG_BLOCK, &signals, ptr::null_mut());
+        signals
+    };
 
-// Using libc spawn a thread to intercept the SIGWINCH signal and send it through a channel
-// given in parameter to the function.
-pub fn init_resize_listener(tx: Sender<RefreshOrder>) {
+    thread::spawn(move || loop {
+        let mut signal = 0;
+        if unsafe { libc::sigwait(&signals, &mut signal) } != 0 {
+            return;
+        }
+        let events = match signal {
+            libc::SIGTSTP => {
+                restore::leave();
+                stop();
+                continue;
+            }
+            // The terminal may have been resized in the meantime
+            libc::SIGCONT => {
+                *RESUMED.0.lock().unwrap() += 1;
+                RESUMED.1.notify_all();
+                vec![Event::Resume, resize(size)]
+            }
+            _ => vec![resize(size)],
+        };
+        // The send() function fails once the main loop of the editor has stopped
+        for event in events {
+            if tx.send(Some(event)).is_err() {
+                return;
+            }
+        }
+    });
+}
+
+// Stop the editor with the default action of SIGTSTP, in the listener thread (returns once
+// the editor is continued)
+fn stop() {
     unsafe {
-        TX = Some(tx);
+        let mut signals = std::mem::zeroed();
+        libc::sigemptyset(&mut signals);
+        libc::sigaddset(&mut signals, libc::SIGTSTP);
+        libc::signal(libc::SIGTSTP, libc::SIG_DFL);
+        libc::pthread_sigmask(libc::SIG_UNBLOCK, &signals, ptr::null_mut());
+        libc::raise(libc::SIGTSTP);
+        // Continued: SIGTSTP is waited for again (SIGCONT is still blocked, and pending)
+        libc::pthread_sigmask(libc::SIG_BLOCK, &signals, ptr::null_mut());
+    }
+}
+
+// The resize event of the current size of the terminal
+fn resize(size: fn() -> (usize, usize)) -> Event {
+    let (width, height) = size();
+    Event::Resize(width, height)
+}
+
+/// Signals that kill the editor, after which the terminal has to be restored (SIGSEGV and
+/// SIGBUS are left to the Rust runtime, which reports the stack overflows)
+const FATAL_SIGNALS: [libc::c_int; 5] = [
+    libc::SIGTERM,
+    libc::SIGHUP,
+    libc::SIGINT,
+    libc::SIGQUIT,
+    libc::SIGABRT,
+];
+
+// Restore the terminal when the editor is killed by a fatal signal
+pub fn init_fatal_signal_handlers() {
+    for signal in FATAL_SIGNALS {
+        unsafe {
+            libc::signal(signal, fatal_handler as *const () as libc::sighandler_t);
+        }
     }
+}
+
+// This function is called when a fatal signal is intercepted
+extern "C" fn fatal_handler(signal: libc::c_int) {
+    restore::leave();
+    // Raise the signal again with its default action, so that the editor is killed by it
     unsafe {
-        libc::signal(libc::SIGWINCH, resize_handler as libc::sighandler_t);
+        libc::signal(signal, libc::SIG_DFL);
+        libc::raise(signal);
     }
 }
 
-// This function is called when the SIGWINCH signal is intercepted
-unsafe extern "C" fn resize_handler(_: libc::c_int) {
-    // Send the signal through the channel
-    // Note: the send() function will fail if the receiver is not listening anymore
-    //       (the receiver is the terminal drawer)
-    let _ = TX.as_ref().unwrap().send(RefreshOrder::Resize);
+/// Stop the editor, like Ctrl-Z does in a shell (returns once the editor is continued)
+pub fn suspend() {
+    let resumed = RESUMED.0.lock().unwrap();
+    let count = *resumed;
+    // The listener thread receives the signal sent to the process
+    unsafe {
+        libc::kill(libc::getpid(), libc::SIGTSTP);
+    }
+    let _resumed = RESUMED.1.wait_while(resumed, |resumed| *resumed == count);
 }
@@ -0,0 +1,139 @@
+//! # Stash list viewer
+//!
+//! The stash list of the repository is displayed in a scratch buffer, one stash per
+//! line. From there the user can look at the changes recorded by a stash (a unified
+//! diff, highlighted like a patch) and pop it. Like with the history browser, the editor
+//! keeps the working buffer aside in the meantime, and reloads it once a stash changed
+//! its file on disk.
+
+use super::view::{file::git::Stash, Highlight, Highlights};
+
+/// State of the stash list viewer
+pub struct Stashes {
+    /// The stash list, most recent first
+    stashes: Vec<Stash>,
+    /// Index of the selected stash
+    selected: usize,
+    /// Whether the diff of the selected stash is displayed (instead of the list)
+    showing_diff: bool,
+}
+
+impl Stashes {
+    pub fn new(stashes: Vec<Stash>) -> Self {
+        Self {
+            stashes,
+            selected: 0,
+            showing_diff: false,
+        }
+    }
+
+    /// The selected stash
+    pub fn selected(&self) -> Option<&Stash> {
+        self.stashes.get(self.selected)
+    }
+
+    /// Index of the selected stash (its line in the list)
+    pub fn selected_index(&self) -> usize {
+        self.selected
+    }
+
+    /// Select the stash at the given line of the list
+    pub fn select(&mut self, index: usize) -> bool {
+        if index >= self.stashes.len() {
+            return false;
+        }
+        self.selected = index;
+        true
+    }
+
+    /// Whether the diff of the selected stash is displayed (instead of the list)
+    pub fn showing_diff(&self) -> bool {
+        self.showing_diff
+    }
+
+    pub fn set_showing_diff(&mut self, showing_diff: bool) {
+        self.showing_diff = showing_diff;
+    }
+
+    /// Name of the buffer displaying the list or the diff of the selected stash
+    pub fn title(&self) -> String {
+        match (self.showing_diff, self.selected()) {
+            (true, Some(stash)) => format!("stash@{{{}}}", stash.index),
+            _ => "stashes".to_string(),
+        }
+    }
+
+    /// Render the stash list as the content of a buffer (one stash per line)
+    pub fn render(&self) -> String {
+        self.stashes
+            .iter()
+            .map(Stash::to_string)
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// Highlight the added and the removed lines of a unified diff
+pub fn diff_highlights(lines: &[String]) -> Highlights {
+    let mut highlights = Highlights::new();
+    for (index, line) in lines.iter().enumerate() {
+        let highlight = if line.starts_with("+++") || line.starts_with("---") {
+            continue;
+        } else if line.starts_with('+') {
+            Highlight::Added
+        } else if line.starts_with('-') {
+            Highlight::Deleted
+        } else if line.starts_with("@@") {
+            Highlight::Comment
+        } else {
+            continue;
+        };
+        highlights.insert(index, vec![(0..usize::MAX, highlight)]);
+    }
+    highlights
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stash(index: usize, message: &str) -> Stash {
+        Stash {
+            index,
+            message: message.into(),
+        }
+    }
+
+    #[test]
+    fn stashes_select() {
+        let mut stashes = Stashes::new(vec![stash(0, "WIP on main: new"), stash(1, "old")]);
+        assert_eq!(
+            stashes.render(),
+            "stash@{0}: WIP on main: new\nstash@{1}: old"
+        );
+        assert_eq!(stashes.title(), "stashes");
+        assert!(stashes.select(1));
+        assert!(!stashes.select(2));
+        stashes.set_showing_diff(true);
+        assert_eq!(stashes.title(), "stash@{1}");
+    }
+
+    #[test]
+    fn stash_diff_highlights() {
+        let lines = [
+            "--- a/a.txt",
+            "+++ b/a.txt",
+            "@@ -1 +1 @@",
+            "-a",
+            "+b",
+            " c",
+        ]
+        .map(String::from);
+        let highlights = diff_highlights(&lines);
+        assert_eq!(highlights.get(&0), None);
+        assert_eq!(highlights[&2][0].1, Highlight::Comment);
+        assert_eq!(highlights[&3][0].1, Highlight::Deleted);
+        assert_eq!(highlights[&4][0].1, Highlight::Added);
+        assert_eq!(highlights.get(&5), None);
+    }
+}
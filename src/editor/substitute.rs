@@ -0,0 +1,118 @@
+//! # Substitutions
+//!
+//! The `:s/old/new/` command line replaces the first occurrence of `old` with `new` on
+//! the line of the cursor, `:%s/old/new/` on every line of the buffer. With the `g` flag
+//! (`:%s/old/new/g`) every occurrence of the lines is replaced. The pattern is a literal
+//! string (not a regular expression), a `/` is escaped as `\/` in both parts.
+
+/// A substitution typed in the command line
+#[derive(Clone, Debug, PartialEq)]
+pub struct Substitution {
+    pattern: String,
+    replacement: String,
+    /// Replace every occurrence of each line (instead of the first one)
+    global: bool,
+    /// Apply to every line of the buffer (instead of the line of the cursor)
+    pub whole_file: bool,
+}
+
+impl Substitution {
+    /// Parse `s/old/new/`, `%s/old/new/g`... (the last `/` is optional)
+    pub fn parse(input: &str) -> Result<Self, &'static str> {
+        let (whole_file, input) = match input.strip_prefix('%') {
+            Some(input) => (true, input),
+            None => (false, input),
+        };
+        let input = input.strip_prefix("s/").ok_or("Not a substitution")?;
+        let mut parts = split_unescaped(input).into_iter();
+        let pattern = parts.next().unwrap_or_default();
+        let replacement = parts.next().ok_or("Missing replacement")?;
+        let global = match parts.next().as_deref() {
+            None | Some("") => false,
+            Some("g") => true,
+            Some(_) => return Err("Invalid substitution flags"),
+        };
+        if pattern.is_empty() {
+            return Err("Empty pattern");
+        }
+        Ok(Self {
+            pattern,
+            replacement,
+            global,
+            whole_file,
+        })
+    }
+
+    /// The line with the substitution applied, `None` if the pattern is not in the line
+    pub fn apply(&self, line: &str) -> Option<String> {
+        if !line.contains(&self.pattern) {
+            return None;
+        }
+        Some(match self.global {
+            true => line.replace(&self.pattern, &self.replacement),
+            false => line.replacen(&self.pattern, &self.replacement, 1),
+        })
+    }
+
+    /// The pattern (for the messages)
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+}
+
+/// Split the parts of a substitution on the `/` that are not escaped
+fn split_unescaped(input: &str) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut chars = input.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('/') => parts.last_mut().unwrap().push('/'),
+                Some(c) => parts.last_mut().unwrap().extend(['\\', c]),
+                None => parts.last_mut().unwrap().push('\\'),
+            },
+            '/' => parts.push(String::new()),
+            c => parts.last_mut().unwrap().push(c),
+        }
+    }
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_substitution() {
+        let substitution = Substitution::parse("%s/a\\/b/c/g").unwrap();
+        assert_eq!(
+            substitution,
+            Substitution {
+                pattern: "a/b".into(),
+                replacement: "c".into(),
+                global: true,
+                whole_file: true,
+            }
+        );
+        let substitution = Substitution::parse("s/a/").unwrap();
+        assert_eq!(substitution.replacement, "");
+        assert!(!substitution.global && !substitution.whole_file);
+
+        assert_eq!(Substitution::parse("s/a"), Err("Missing replacement"));
+        assert_eq!(Substitution::parse("s//b/"), Err("Empty pattern"));
+        assert_eq!(
+            Substitution::parse("s/a/b/x"),
+            Err("Invalid substitution flags")
+        );
+        assert_eq!(Substitution::parse("wq"), Err("Not a substitution"));
+    }
+
+    #[test]
+    fn apply_substitution() {
+        let first = Substitution::parse("s/a/b/").unwrap();
+        assert_eq!(first.apply("a a"), Some("b a".into()));
+        assert_eq!(first.apply("c"), None);
+        let global = Substitution::parse("s/a/b/g").unwrap();
+        assert_eq!(global.apply("a a"), Some("b b".into()));
+    }
+}
@@ -2,21 +2,41 @@
 //!
 //! The terminal drawing logic is abstracted the `TerminalDrawer` trait and
 //! implemented for the termion crate using the `TermionTerminalDrawer` struct
-//! in the `termion` module.
+//! in the `termion` module. The `screen` module implements it in memory, to run the
+//! editor in the tests. The `restore` module gives the terminal back to the shell.
+//!
+//! Both implementations share the layout of the screen:
+//! - The line numbers on the left of the screen, followed by the git diff markers
+//! - The actual editor on the rest of the screen
+//! - The status bar at the bottom of the screen
 
+pub mod restore;
+#[cfg(test)]
+pub mod screen;
 pub mod termion;
 
 use crate::editor::{view::View, Mode};
 use std::collections::{HashMap, HashSet};
 
-use super::view::file::git::PatchType;
+use super::view::file::git::{FileStatus, Marker, RepoStatus};
+
+/// Width of the line numbers
+const LINE_NUMBER_WIDTH: u16 = 3;
+/// Height of the status bar
+const STATUS_BAR_HEIGHT: u16 = 1;
 
 /// A TerminalDrawer instance is responsible for drawing the editor on the terminal
 pub trait TerminalDrawer {
     /// Terminate the TerminalDrawer instance (potentially cleanup the terminal)
     fn terminate(&mut self);
+    /// Give the terminal back and stop the editor (Ctrl-Z), until it is resumed
+    fn suspend(&mut self);
+    /// Take the terminal again after the editor was resumed (it is then redrawn)
+    fn resume(&mut self);
     /// Get the terminal dimensions
     fn get_term_size(&self) -> (usize, usize);
+    /// Force the terminal dimensions (when a resize event is replayed)
+    fn set_term_size(&mut self, size: (usize, usize));
     /// Clear the terminal
     fn clear(&mut self);
     /// (Re)Draw the whole editor
@@ -28,12 +48,111 @@ pub trait TerminalDrawer {
     /// (Re)Draw the status bar
     fn draw_status_bar(&mut self, status_bar_infos: &StatusBarInfos);
     /// (Re)Draw the diff markers on the left of the editor
-    fn draw_diff_markers(&mut self, diff: HashMap<usize, PatchType>, view: &View);
+    fn draw_diff_markers(&mut self, diff: HashMap<usize, Marker>, view: &View);
+    /// Draw a popup next to the cursor, on top of the editor
+    fn draw_popup(&mut self, popup: &Popup, view: &View);
 }
 
 /// Information that go in the status bar
 pub struct StatusBarInfos {
     pub file_name: String,
+    /// Status of the file in the repository (untracked, ignored...)
+    pub file_status: Option<FileStatus>,
     pub mode: Mode,
-    pub ref_name: Option<String>,
+    /// State of the git repository (branch, changes, operation in progress...)
+    pub repo_status: Option<RepoStatus>,
+    /// Message displayed instead of the file name (prompts, errors...)
+    pub message: Option<String>,
+}
+
+impl StatusBarInfos {
+    /// Text of the status bar for a terminal of the given width:
+    /// - The current mode (NORMAL/INSERT/RENAME/...) (left)
+    /// - The current file name, or a message/prompt if any (in the middle)
+    /// - The current git branch (if we are in a git) (right)
+    pub fn text(&self, width: usize) -> String {
+        let mode = format!(" {}", self.mode);
+        // Files that are not committed yet are flagged after their name
+        let file_name = match self.file_status {
+            Some(status @ (FileStatus::Added | FileStatus::Untracked | FileStatus::Ignored)) => {
+                format!("{} [{}]", self.file_name, status)
+            }
+            _ => self.file_name.clone(),
+        };
+        let center = self.message.as_ref().unwrap_or(&file_name);
+        let git_branch = match &self.repo_status {
+            Some(status) => format!("{} ", status),
+            None => " ".to_string(),
+        };
+
+        // The file name is in the middle of the status bar, the state of the repository
+        // (or spaces to fill the status bar) at the right
+        let center_len = center.chars().count();
+        let offset = (width.saturating_sub(center_len) / 2).saturating_sub(mode.len());
+        let right_offset = width
+            .saturating_sub(mode.len() + offset + center_len)
+            .saturating_sub(git_branch.chars().count());
+        format!(
+            "{}{}{}{}{}",
+            mode,
+            " ".repeat(offset),
+            center,
+            " ".repeat(right_offset),
+            git_branch
+        )
+    }
+}
+
+/// Text displayed in a box on top of the editor until the next key
+pub struct Popup {
+    pub title: String,
+    pub lines: Vec<String>,
+}
+
+impl Popup {
+    /// Lines of the box around the popup, drawn over the text of a terminal of the given
+    /// size, and the line of the screen where the box starts (below the cursor, or above
+    /// if there is not enough room)
+    /// ```text
+    /// ┌title───┐
+    /// │line 1  │
+    /// │line 2  │
+    /// └────────┘
+    /// ```
+    pub fn boxed(&self, cursor_y: usize, (width, height): (u16, u16)) -> (usize, Vec<String>) {
+        let left = LINE_NUMBER_WIDTH + 2;
+        let max_width = width.saturating_sub(left + 2) as usize;
+        let max_height = height.saturating_sub(STATUS_BAR_HEIGHT + 2) as usize;
+
+        let title_len = self.title.chars().count();
+        let inner_width = self
+            .lines
+            .iter()
+            .map(|line| line.chars().count())
+            .chain([title_len])
+            .max()
+            .unwrap_or_default()
+            .min(max_width);
+        let inner_height = self.lines.len().min(max_height);
+
+        let top = if cursor_y + 1 + inner_height + 2 <= max_height + 2 {
+            cursor_y + 1
+        } else {
+            cursor_y.saturating_sub(inner_height + 2)
+        };
+
+        let title = self.title.chars().take(inner_width).collect::<String>();
+        let mut lines = vec![format!(
+            "┌{}{}┐",
+            title,
+            "─".repeat(inner_width - title.chars().count())
+        )];
+        for line in self.lines.iter().take(inner_height) {
+            let line = line.chars().take(inner_width).collect::<String>();
+            let padding = " ".repeat(inner_width - line.chars().count());
+            lines.push(format!("│{}{}│", line, padding));
+        }
+        lines.push(format!("└{}┘", "─".repeat(inner_width)));
+        (top, lines)
+    }
 }
@@ -0,0 +1,74 @@
+//! # Giving the terminal back
+//!
+//! The editor draws on the alternate screen of the terminal, in raw mode. When it quits,
+//! leaving the alternate screen brings back the content of the terminal as it was
+//! (the shell history), and the attributes saved when it started restore the modes of the
+//! terminal. The editor does not change the shape of the cursor, only its visibility.
+//!
+//! The terminal has to be given back however the editor stops: this is also done by a
+//! panic hook, and by the handlers of the fatal signals (see the `signal` module). The
+//! restoration only writes escape codes and sets the terminal attributes, which is safe
+//! in a signal handler, and it is done once even if several of them happen.
+
+use std::{
+    io,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        OnceLock,
+    },
+};
+
+/// Attributes of the terminal before the editor started
+static ORIGINAL: OnceLock<libc::termios> = OnceLock::new();
+/// Whether the terminal is in the state of the editor (raw mode, alternate screen)
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Switch to the alternate screen
+const ENTER: &[u8] = b"\x1b[?1049h";
+/// Reset the colors, show the cursor and leave the alternate screen
+const LEAVE: &[u8] = b"\x1b[0m\x1b[?25h\x1b[?1049l";
+
+/// Put the terminal in the state of the editor: raw mode and alternate screen
+pub fn enter() -> io::Result<()> {
+    let mut termios = unsafe { std::mem::zeroed() };
+    if unsafe { libc::tcgetattr(libc::STDOUT_FILENO, &mut termios) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // The attributes are only saved the first time (the editor may enter again after
+    // being suspended)
+    let original = *ORIGINAL.get_or_init(|| termios);
+    let mut raw = original;
+    unsafe { libc::cfmakeraw(&mut raw) };
+    if unsafe { libc::tcsetattr(libc::STDOUT_FILENO, libc::TCSANOW, &raw) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    write(ENTER);
+    ACTIVE.store(true, Ordering::SeqCst);
+    Ok(())
+}
+
+/// Give the terminal back as it was before `enter` (does nothing if it already was)
+pub fn leave() {
+    if !ACTIVE.swap(false, Ordering::SeqCst) {
+        return;
+    }
+    write(LEAVE);
+    if let Some(original) = ORIGINAL.get() {
+        unsafe { libc::tcsetattr(libc::STDOUT_FILENO, libc::TCSANOW, original) };
+    }
+}
+
+/// Give the terminal back before printing the panic message (otherwise it would be
+/// printed on the alternate screen, and then erased)
+pub fn install_panic_hook() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        leave();
+        default_hook(info);
+    }));
+}
+
+/// Write bytes to the terminal without buffering them
+fn write(bytes: &[u8]) {
+    unsafe { libc::write(libc::STDOUT_FILENO, bytes.as_ptr().cast(), bytes.len()) };
+}
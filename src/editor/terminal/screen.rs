@@ -0,0 +1,383 @@
+//! # In-memory terminal
+//!
+//! `ScreenTerminalDrawer` draws the editor in a grid of cells kept in memory instead of
+//! writing escape codes to the terminal. It follows the layout of the termion drawer, but
+//! the cells are styled after what they display (a line number, a diff marker, a
+//! highlighted character...) rather than with colors, so that the tests can run the
+//! editor on a sequence of keys and check the content of the screen, its styles and the
+//! position of the cursor.
+
+use std::{
+    collections::{HashMap, HashSet},
+    ops::Range,
+    sync::{Arc, Mutex},
+};
+
+use crate::editor::view::{
+    file::git::{Marker, PatchType},
+    FileView, Highlight, View,
+};
+
+use super::{Popup, StatusBarInfos, TerminalDrawer, LINE_NUMBER_WIDTH, STATUS_BAR_HEIGHT};
+
+/// Column where the content of the lines starts (after the line numbers and the markers)
+const TEXT_LEFT: usize = LINE_NUMBER_WIDTH as usize + 2;
+
+/// What a cell of the screen displays
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Style {
+    /// Text without style (or an empty cell)
+    #[default]
+    Default,
+    LineNumber,
+    /// Line number of a selected line
+    SelectedLineNumber,
+    /// Git diff marker in the gutter
+    Marker(Marker),
+    /// Character of a highlighted range
+    Highlight(Highlight),
+    /// Blame annotation after the content of a line
+    Blame,
+    Ruler,
+    StatusBar,
+    Popup,
+}
+
+/// A character of the screen and its style
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Cell {
+    pub c: char,
+    pub style: Style,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Self {
+            c: ' ',
+            style: Style::Default,
+        }
+    }
+}
+
+/// Content of the in-memory terminal
+pub struct Screen {
+    width: usize,
+    height: usize,
+    cells: Vec<Vec<Cell>>,
+    /// Position of the cursor on the screen (column, line)
+    pub cursor: (usize, usize),
+    /// Whether the editor terminated the drawer (the content is kept)
+    pub terminated: bool,
+    /// Whether the editor is suspended (Ctrl-Z)
+    pub suspended: bool,
+}
+
+impl Screen {
+    fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            cells: vec![vec![Cell::default(); width]; height],
+            cursor: (0, 0),
+            terminated: false,
+            suspended: false,
+        }
+    }
+
+    /// Text of a line of the screen (without the trailing spaces)
+    pub fn line(&self, y: usize) -> String {
+        let line = self.cells[y].iter().map(|cell| cell.c).collect::<String>();
+        line.trim_end().to_string()
+    }
+
+    /// Text of the lines of the editor, without the line numbers and the markers
+    pub fn text(&self) -> Vec<String> {
+        (0..self.height - STATUS_BAR_HEIGHT as usize)
+            .map(|y| self.line(y).chars().skip(TEXT_LEFT).collect())
+            .collect()
+    }
+
+    /// Text of the status bar (without the padding)
+    pub fn status_bar(&self) -> String {
+        self.line(self.height - 1).trim_start().to_string()
+    }
+
+    pub fn cell(&self, x: usize, y: usize) -> Cell {
+        self.cells[y][x]
+    }
+
+    /// Write some text from a position of the screen, truncated at its right edge
+    fn put(&mut self, x: usize, y: usize, text: &str, style: Style) {
+        let Some(line) = self.cells.get_mut(y) else {
+            return;
+        };
+        for (cell, c) in line.iter_mut().skip(x).zip(text.chars()) {
+            *cell = Cell { c, style };
+        }
+    }
+
+    /// Clear a line from a column to the right edge of the screen
+    fn clear_from(&mut self, x: usize, y: usize) {
+        if let Some(line) = self.cells.get_mut(y) {
+            line.iter_mut()
+                .skip(x)
+                .for_each(|cell| *cell = Cell::default());
+        }
+    }
+}
+
+/// Implementation of the TerminalDrawer trait in memory. The drawer is moved to the
+/// thread drawing the editor, its clones give access to the screen.
+#[derive(Clone)]
+pub struct ScreenTerminalDrawer {
+    screen: Arc<Mutex<Screen>>,
+}
+
+impl ScreenTerminalDrawer {
+    /// A screen of the given size (the status bar included)
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            screen: Arc::new(Mutex::new(Screen::new(width, height))),
+        }
+    }
+
+    /// Read the content of the screen
+    pub fn screen<T>(&self, read: impl FnOnce(&Screen) -> T) -> T {
+        read(&self.screen.lock().unwrap())
+    }
+
+    /// Write a line with styled ranges of characters (the last range containing a
+    /// character wins, like in the termion drawer)
+    fn put_highlighted(
+        screen: &mut Screen,
+        y: usize,
+        content: &str,
+        start_col: usize,
+        spans: &[(Range<usize>, Highlight)],
+    ) {
+        for (col, c) in content.chars().enumerate() {
+            let style = spans
+                .iter()
+                .rev()
+                .find(|(range, _)| range.contains(&(col + start_col)))
+                .map_or(Style::Default, |(_, highlight)| {
+                    Style::Highlight(*highlight)
+                });
+            screen.put(TEXT_LEFT + col, y, &c.to_string(), style);
+        }
+    }
+}
+
+impl TerminalDrawer for ScreenTerminalDrawer {
+    fn terminate(&mut self) {
+        self.screen.lock().unwrap().terminated = true;
+    }
+
+    fn suspend(&mut self) {
+        self.screen.lock().unwrap().suspended = true;
+    }
+
+    fn resume(&mut self) {
+        self.screen.lock().unwrap().suspended = false;
+        self.clear();
+    }
+
+    fn get_term_size(&self) -> (usize, usize) {
+        let screen = self.screen.lock().unwrap();
+        (
+            screen.width - TEXT_LEFT,
+            screen.height - STATUS_BAR_HEIGHT as usize,
+        )
+    }
+
+    fn set_term_size(&mut self, (width, height): (usize, usize)) {
+        let mut screen = self.screen.lock().unwrap();
+        *screen = Screen::new(width + TEXT_LEFT, height + STATUS_BAR_HEIGHT as usize);
+    }
+
+    fn clear(&mut self) {
+        let mut screen = self.screen.lock().unwrap();
+        let (width, height) = (screen.width, screen.height);
+        screen.cells = vec![vec![Cell::default(); width]; height];
+    }
+
+    fn draw(&mut self, view: &View, status_bar_infos: &StatusBarInfos) {
+        self.draw_status_bar(status_bar_infos);
+        self.draw_lines(view, HashSet::from_iter(0..view.height));
+    }
+
+    fn move_cursor(&mut self, pos: (usize, usize)) {
+        self.screen.lock().unwrap().cursor = (pos.0 + TEXT_LEFT, pos.1);
+    }
+
+    fn draw_lines(&mut self, view: &View, lines: HashSet<usize>) {
+        let selection = view.selection();
+        let mut screen = self.screen.lock().unwrap();
+        for line in lines {
+            // The line number, then one column for the git diff markers
+            let selected = selection
+                .as_ref()
+                .is_some_and(|selection| selection.contains(&(line + view.start_line)));
+            let style = match selected {
+                true => Style::SelectedLineNumber,
+                false => Style::LineNumber,
+            };
+            let number = format!("{:3} ", line + view.start_line + 1);
+            screen.put(0, line, &number, style);
+            // The content of the line
+            let content = view.line(line);
+            let len = content.chars().count();
+            let spans = view.highlights.get(&(line + view.start_line));
+            let spans = spans.map(Vec::as_slice).unwrap_or_default();
+            Self::put_highlighted(&mut screen, line, &content, view.start_col, spans);
+            screen.clear_from(TEXT_LEFT + len, line);
+            // The blame annotation (if there is room for it)
+            let room = view.width.saturating_sub(len + 4);
+            let blame = view
+                .blame()
+                .and_then(|blame| blame.get(line + view.start_line));
+            if let Some(blame) = blame.filter(|_| room > 0) {
+                let annotation = match blame {
+                    Some(blame) => blame.to_string(),
+                    None => "Not committed yet".to_string(),
+                };
+                let annotation = annotation.chars().take(room).collect::<String>();
+                screen.put(TEXT_LEFT + len, line, "    ", Style::Default);
+                screen.put(TEXT_LEFT + len + 4, line, &annotation, Style::Blame);
+            }
+            // The rulers after the end of the line
+            for ruler in &view.rulers {
+                let Some(col) = ruler.checked_sub(view.start_col) else {
+                    continue;
+                };
+                if col >= len && col < view.width {
+                    screen.put(TEXT_LEFT + col, line, "│", Style::Ruler);
+                }
+            }
+        }
+        screen.cursor = (view.cursor.0 + TEXT_LEFT, view.cursor.1);
+    }
+
+    fn draw_status_bar(&mut self, status_bar_infos: &StatusBarInfos) {
+        let mut screen = self.screen.lock().unwrap();
+        let (width, height) = (screen.width, screen.height);
+        let text = status_bar_infos.text(width);
+        screen.clear_from(0, height - 1);
+        screen.put(0, height - 1, &text, Style::StatusBar);
+    }
+
+    fn draw_diff_markers(&mut self, diff: HashMap<usize, Marker>, view: &View) {
+        let mut screen = self.screen.lock().unwrap();
+        let x = LINE_NUMBER_WIDTH as usize;
+        for line in 0..view.height {
+            match diff.get(&(line + view.start_line)) {
+                Some(marker) => {
+                    let c = match (marker.patch_type, marker.staged) {
+                        (PatchType::Deleted, false) => "╺",
+                        (_, false) => "▐",
+                        (PatchType::Deleted, true) => "╶",
+                        (_, true) => "▕",
+                    };
+                    screen.put(x, line, c, Style::Marker(*marker));
+                }
+                None => {
+                    screen.put(x, line, " ", Style::Default);
+                }
+            }
+        }
+    }
+
+    fn draw_popup(&mut self, popup: &Popup, view: &View) {
+        let mut screen = self.screen.lock().unwrap();
+        let size = (screen.width as u16, screen.height as u16);
+        let (top, lines) = popup.boxed(view.cursor.1, size);
+        for (i, line) in lines.iter().enumerate() {
+            screen.put(TEXT_LEFT, top + i, line, Style::Popup);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::editor::Mode;
+
+    fn status_bar_infos(message: Option<&str>) -> StatusBarInfos {
+        StatusBarInfos {
+            file_name: "file.txt".into(),
+            file_status: None,
+            mode: Mode::Normal,
+            repo_status: None,
+            message: message.map(String::from),
+        }
+    }
+
+    #[test]
+    fn screen_draw() {
+        let mut view = View::scratch("first\nsecond line\nthird", "file.txt");
+        let mut drawer = ScreenTerminalDrawer::new(30, 6);
+        (view.width, view.height) = drawer.get_term_size();
+        assert_eq!((view.width, view.height), (25, 5));
+        view.highlights.insert(
+            1,
+            vec![(0..6, Highlight::Added), (2..3, Highlight::Deleted)],
+        );
+        view.navigate(2, 1);
+        drawer.draw(&view, &status_bar_infos(None));
+
+        drawer.screen(|screen| {
+            assert_eq!(screen.line(0), "  1  first");
+            assert_eq!(screen.text(), vec!["first", "second line", "third", "", ""]);
+            assert_eq!(screen.status_bar(), "NORMAL    file.txt");
+            assert_eq!(screen.cursor, (7, 1));
+            assert_eq!(screen.cell(0, 1).style, Style::LineNumber);
+            assert_eq!(screen.cell(5, 1).style, Style::Highlight(Highlight::Added));
+            assert_eq!(
+                screen.cell(7, 1).style,
+                Style::Highlight(Highlight::Deleted)
+            );
+            assert_eq!(screen.cell(11, 1).style, Style::Default);
+        });
+
+        // Only the given lines are drawn again
+        view.insert('x');
+        drawer.draw_lines(&view, HashSet::from([0]));
+        drawer.screen(|screen| assert_eq!(screen.text()[1], "second line"));
+        drawer.draw_lines(&view, HashSet::from([1]));
+        drawer.screen(|screen| assert_eq!(screen.text()[1], "sexcond line"));
+
+        let marker = Marker {
+            patch_type: PatchType::Added,
+            staged: false,
+        };
+        drawer.draw_diff_markers(HashMap::from([(2, marker)]), &view);
+        drawer.screen(|screen| {
+            assert_eq!(
+                screen.cell(3, 2),
+                Cell {
+                    c: '▐',
+                    style: Style::Marker(marker)
+                }
+            );
+            assert_eq!(screen.cell(3, 1).c, ' ');
+        });
+    }
+
+    #[test]
+    fn screen_popup() {
+        let view = View::scratch("text", "file.txt");
+        let mut drawer = ScreenTerminalDrawer::new(20, 6);
+        let popup = Popup {
+            title: "title".into(),
+            lines: vec!["a".into(), "long line".into()],
+        };
+        drawer.draw_popup(&popup, &view);
+        drawer.screen(|screen| {
+            assert_eq!(screen.line(1), "     ┌title────┐");
+            assert_eq!(screen.line(2), "     │a        │");
+            assert_eq!(screen.line(3), "     │long line│");
+            assert_eq!(screen.line(4), "     └─────────┘");
+            assert_eq!(screen.cell(5, 1).style, Style::Popup);
+        });
+    }
+}
@@ -1,20 +1,18 @@
 use std::{
     collections::{HashMap, HashSet},
     io::{Stdout, Write},
+    ops::Range,
 };
 
-use termion::{
-    clear, color, cursor,
-    raw::{IntoRawMode, RawTerminal},
-};
-
-use crate::editor::view::{file::git::PatchType, FileView, View};
+use termion::{clear, color, cursor};
 
-use super::{StatusBarInfos, TerminalDrawer};
+use crate::editor::signal;
+use crate::editor::view::{
+    file::git::{Marker, PatchType},
+    FileView, Highlight, View,
+};
 
-/// Macro for line number width
-const LINE_NUMBER_WIDTH: u16 = 3;
-const STATUS_BAR_HEIGHT: u16 = 1;
+use super::{restore, Popup, StatusBarInfos, TerminalDrawer, LINE_NUMBER_WIDTH, STATUS_BAR_HEIGHT};
 
 /// Macro for writing to the terminal
 macro_rules! print {
@@ -28,45 +26,43 @@ macro_rules! print {
 /// - The status bar at the top of the screen
 /// - The line numbers on the left of the screen
 /// - The actual editor on the rest of the screen
-/// To exploit the full potential of the termion crate, the TermionTerminalDrawer acts as a
-/// wrapper around the `RawTerminal<Stdout>` struct provided by termion.
+///
+/// The editor is drawn on the alternate screen of the terminal, in raw mode (see the
+/// `restore` module): the content of the terminal is left untouched.
 pub struct TermionTerminalDrawer {
-    /// The raw terminal output we can write to using termion
-    stdout: RawTerminal<Stdout>,
+    /// The terminal output we can write to using termion
+    stdout: Stdout,
 }
 
 impl TerminalDrawer for TermionTerminalDrawer {
     fn terminate(&mut self) {
-        // Clear the screen with the "\x1B[3J" escape code (clear screen and scrollback buffer)
-        print!(self.stdout, clear::All);
-        print!(self.stdout, "\x1B[3J");
-        // Move the cursor to the top left
-        print!(self.stdout, cursor::Goto(1, 1));
-        // Reset the terminal colors
-        print!(self.stdout, color::Fg(color::Reset));
-        print!(self.stdout, color::Bg(color::Reset));
-        // Disable raw mode
-        self.stdout.suspend_raw_mode().unwrap_or_default();
-        // Show the terminal cursor
-        print!(self.stdout, cursor::Show);
+        // Flush what is left to draw, then leave the alternate screen and the raw mode
+        self.flush();
+        restore::leave();
+    }
 
-        // Flush the stdout buffer
-        self.stdout.flush().unwrap_or_default();
+    fn suspend(&mut self) {
+        self.flush();
+        restore::leave();
+        signal::suspend();
+    }
+
+    fn resume(&mut self) {
+        restore::enter().unwrap_or_default();
+        self.clear();
     }
 
     fn clear(&mut self) {
-        // Clear the screen
+        // Clear the screen (the alternate screen has no scrollback buffer)
         print!(self.stdout, clear::All);
-        // Clear the scrollback buffer
-        print!(self.stdout, "\x1B[3J");
     }
 
     fn get_term_size(&self) -> (usize, usize) {
-        let (x, y) = termion::terminal_size().unwrap_or_default();
-        (
-            (x - LINE_NUMBER_WIDTH - 2) as usize,
-            (y - STATUS_BAR_HEIGHT) as usize,
-        )
+        Self::text_area_size()
+    }
+
+    fn set_term_size(&mut self, _size: (usize, usize)) {
+        // A terminal keeps the size of its window
     }
 
     fn draw(&mut self, view: &View, status_bar_infos: &StatusBarInfos) {
@@ -94,27 +90,53 @@ impl TerminalDrawer for TermionTerminalDrawer {
     }
 
     fn draw_lines(&mut self, view: &View, lines: HashSet<usize>) {
+        let selection = view.selection();
         // Draw each line that has changed
         for line in lines {
             // Move the cursor to the beginning of the line
             print!(self.stdout, cursor::Goto(1, line as u16 + 1));
-            // Print the line number
-            self.draw_line_number(line + view.start_line + 1);
+            // Print the line number (highlighted if the line is selected)
+            let selected = selection
+                .as_ref()
+                .is_some_and(|selection| selection.contains(&(line + view.start_line)));
+            self.draw_line_number(line + view.start_line + 1, selected);
             // Leave one space for git diff markers
             print!(self.stdout, cursor::Right(1));
             // Print the line content
-            print!(self.stdout, view.line(line));
+            let content = view.line(line);
+            match view.highlights.get(&(line + view.start_line)) {
+                Some(spans) => self.draw_highlighted(&content, view.start_col, spans),
+                None => {
+                    print!(self.stdout, content);
+                }
+            }
+            // Print the blame annotation after the content (if there is room for it)
+            let room = view.width.saturating_sub(content.chars().count() + 4);
+            let blame = view
+                .blame()
+                .and_then(|blame| blame.get(line + view.start_line));
+            if let Some(blame) = blame.filter(|_| room > 0) {
+                let annotation = match blame {
+                    Some(blame) => blame.to_string(),
+                    None => "Not committed yet".to_string(),
+                };
+                print!(self.stdout, color::Fg(color::LightBlack));
+                print!(
+                    self.stdout,
+                    format!("    {}", annotation.chars().take(room).collect::<String>())
+                );
+                print!(self.stdout, color::Fg(color::Reset));
+            }
             // Clear the rest of the line
             print!(self.stdout, clear::UntilNewline);
+            self.draw_rulers(view, line, content.chars().count());
         }
         // Move the cursor to its actual position
         self.move_cursor(view.cursor);
     }
 
-    // The status bar is at the bottom of the screen and displays the following information:
-    // - The current mode (NORMAL/INSERT/RENAME) (left)
-    // - The current file name (in the middle)
-    // - The current git branch (if we are in a git) (right)
+    // The status bar is at the bottom of the screen and displays the mode, the file name
+    // (or a message) and the state of the repository (see `StatusBarInfos::text`)
     fn draw_status_bar(&mut self, status_bar_infos: &StatusBarInfos) {
         let (width, height) = termion::terminal_size().unwrap_or_default();
 
@@ -124,33 +146,7 @@ impl TerminalDrawer for TermionTerminalDrawer {
         print!(self.stdout, color::Bg(color::White));
         // Set the status bar foreground color to black
         print!(self.stdout, color::Fg(color::Black));
-        // Print the mode (NORMAL or INSERT)
-        print!(self.stdout, " ");
-        print!(self.stdout, status_bar_infos.mode);
-        // Print the file name in the middle of the status bar
-        let offset = (width as usize - status_bar_infos.file_name.len()) / 2 - " NORMAL".len();
-        print!(self.stdout, " ".repeat(offset));
-        print!(self.stdout, status_bar_infos.file_name);
-        // Print the git branch if we are in a git repository at the right of the status bar
-        if let Some(git_branch) = &status_bar_infos.ref_name {
-            let offset = width as usize
-                - "NORMAL".len() // All modes have the same length
-                - status_bar_infos.file_name.len()
-                - offset
-                - 2
-                - git_branch.len();
-            print!(self.stdout, " ".repeat(offset));
-            print!(self.stdout, git_branch);
-        } else {
-            // If we are not in a git repository, we still need to print spaces to fill the status bar
-            let offset = width as usize
-                - "NORMAL".len() // All modes have the same length
-                - status_bar_infos.file_name.len()
-                - 2
-                - offset;
-            print!(self.stdout, " ".repeat(offset));
-        }
-        print!(self.stdout, " ");
+        print!(self.stdout, status_bar_infos.text(width as usize));
         // Reset the status bar colors
         print!(self.stdout, color::Fg(color::Reset));
         print!(self.stdout, color::Bg(color::Reset));
@@ -163,7 +159,12 @@ impl TerminalDrawer for TermionTerminalDrawer {
     /// - '╺' (red) for removed lines
     /// - '▐' (yellow) for modified lines
     /// - ' ' (default) for unchanged lines
-    fn draw_diff_markers(&mut self, diff: HashMap<usize, PatchType>, view: &View) {
+    ///
+    /// Staged changes use thinner markers in other colors:
+    /// - '▕' (cyan) for added lines
+    /// - '╶' (magenta) for removed lines
+    /// - '▕' (blue) for modified lines
+    fn draw_diff_markers(&mut self, diff: HashMap<usize, Marker>, view: &View) {
         for line in 0..view.height {
             // Go to the beginning of the line
             print!(
@@ -172,34 +173,84 @@ impl TerminalDrawer for TermionTerminalDrawer {
             );
             // Print the diff marker
             match diff.get(&(line + view.start_line)) {
-                Some(PatchType::Added) => {
-                    print!(self.stdout, color::Fg(color::Green));
-                    print!(self.stdout, "▐");
-                }
-                Some(PatchType::Deleted) => {
-                    print!(self.stdout, color::Fg(color::Red));
-                    print!(self.stdout, "╺");
-                }
-                Some(PatchType::Changed) => {
-                    print!(self.stdout, color::Fg(color::Yellow));
-                    print!(self.stdout, "▐");
-                }
+                Some(Marker {
+                    patch_type,
+                    staged: false,
+                }) => match patch_type {
+                    PatchType::Added => {
+                        print!(self.stdout, color::Fg(color::Green));
+                        print!(self.stdout, "▐");
+                    }
+                    PatchType::Deleted => {
+                        print!(self.stdout, color::Fg(color::Red));
+                        print!(self.stdout, "╺");
+                    }
+                    PatchType::Changed => {
+                        print!(self.stdout, color::Fg(color::Yellow));
+                        print!(self.stdout, "▐");
+                    }
+                },
+                Some(Marker {
+                    patch_type,
+                    staged: true,
+                }) => match patch_type {
+                    PatchType::Added => {
+                        print!(self.stdout, color::Fg(color::Cyan));
+                        print!(self.stdout, "▕");
+                    }
+                    PatchType::Deleted => {
+                        print!(self.stdout, color::Fg(color::Magenta));
+                        print!(self.stdout, "╶");
+                    }
+                    PatchType::Changed => {
+                        print!(self.stdout, color::Fg(color::Blue));
+                        print!(self.stdout, "▕");
+                    }
+                },
                 _ => {
                     print!(self.stdout, " ");
                 }
             }
         }
+        print!(self.stdout, color::Fg(color::Reset));
+        self.move_cursor(view.cursor);
+    }
+
+    /// Draw the popup in a box below the cursor (or above if there is not enough room)
+    fn draw_popup(&mut self, popup: &Popup, view: &View) {
+        let size = termion::terminal_size().unwrap_or_default();
+        let (top, lines) = popup.boxed(view.cursor.1, size);
+        // The popup is drawn over the text, not over the line numbers
+        let left = LINE_NUMBER_WIDTH + 2;
+        print!(self.stdout, cursor::Hide);
+        for (i, line) in lines.iter().enumerate() {
+            print!(self.stdout, cursor::Goto(left + 1, (top + i) as u16 + 1));
+            print!(self.stdout, line);
+        }
+        print!(self.stdout, cursor::Show);
         self.move_cursor(view.cursor);
     }
 }
 
 impl TermionTerminalDrawer {
-    pub fn new() -> Box<Self> {
+    pub fn new() -> Self {
+        restore::enter().expect("giga needs to run in a terminal");
         let mut drawer = Self {
-            stdout: std::io::stdout().into_raw_mode().unwrap(),
+            stdout: std::io::stdout(),
         };
         drawer.clear();
-        Box::new(drawer)
+        drawer.flush();
+        drawer
+    }
+
+    /// # Size of the text area of the terminal
+    /// The terminal without the line numbers and the status bar (empty if it is too small).
+    pub fn text_area_size() -> (usize, usize) {
+        let (x, y) = termion::terminal_size().unwrap_or_default();
+        (
+            x.saturating_sub(LINE_NUMBER_WIDTH + 2) as usize,
+            y.saturating_sub(STATUS_BAR_HEIGHT) as usize,
+        )
     }
 
     /// # Helper funtion to flush the stdout buffer
@@ -207,11 +258,106 @@ impl TermionTerminalDrawer {
         self.stdout.flush().unwrap_or_default();
     }
 
+    /// # Draw a line with styled ranges of characters
+    /// The ranges are columns of the line in the file, `start_col` is the column of
+    /// the first character of `content`. The last range containing a character wins.
+    fn draw_highlighted(
+        &mut self,
+        content: &str,
+        start_col: usize,
+        spans: &[(Range<usize>, Highlight)],
+    ) {
+        let mut current = None;
+        for (col, c) in content.chars().enumerate() {
+            let highlight = spans
+                .iter()
+                .rev()
+                .find(|(range, _)| range.contains(&(col + start_col)))
+                .map(|(_, highlight)| *highlight);
+            if highlight != current {
+                print!(self.stdout, color::Fg(color::Reset));
+                print!(self.stdout, color::Bg(color::Reset));
+                match highlight {
+                    Some(Highlight::Added) => {
+                        print!(self.stdout, color::Fg(color::Green));
+                    }
+                    Some(Highlight::Deleted) => {
+                        print!(self.stdout, color::Fg(color::Red));
+                    }
+                    Some(Highlight::Changed) => {
+                        print!(self.stdout, color::Fg(color::Yellow));
+                    }
+                    Some(Highlight::ChangedText) => {
+                        print!(self.stdout, color::Bg(color::Yellow));
+                        print!(self.stdout, color::Fg(color::Black));
+                    }
+                    Some(Highlight::Ours) => {
+                        print!(self.stdout, color::Fg(color::Green));
+                    }
+                    Some(Highlight::Base) => {
+                        print!(self.stdout, color::Fg(color::Magenta));
+                    }
+                    Some(Highlight::Theirs) => {
+                        print!(self.stdout, color::Fg(color::Cyan));
+                    }
+                    Some(Highlight::ConflictMarker) => {
+                        print!(self.stdout, color::Bg(color::Red));
+                        print!(self.stdout, color::Fg(color::White));
+                    }
+                    Some(Highlight::Filler | Highlight::Comment) => {
+                        print!(self.stdout, color::Fg(color::LightBlack));
+                    }
+                    Some(Highlight::Summary) => {
+                        print!(self.stdout, color::Fg(color::Yellow));
+                    }
+                    Some(Highlight::Overflow) => {
+                        print!(self.stdout, color::Bg(color::Red));
+                    }
+                    Some(Highlight::Keyword) => {
+                        print!(self.stdout, color::Fg(color::Magenta));
+                    }
+                    Some(Highlight::CommitId) => {
+                        print!(self.stdout, color::Fg(color::Cyan));
+                    }
+                    None => (),
+                }
+                current = highlight;
+            }
+            print!(self.stdout, c);
+        }
+        print!(self.stdout, color::Fg(color::Reset));
+        print!(self.stdout, color::Bg(color::Reset));
+    }
+
+    /// # Draw the rulers of a line
+    /// The rulers are drawn after the end of the line (the characters beyond a ruler are
+    /// highlighted instead)
+    fn draw_rulers(&mut self, view: &View, line: usize, len: usize) {
+        print!(self.stdout, color::Fg(color::LightBlack));
+        for ruler in &view.rulers {
+            let Some(col) = ruler.checked_sub(view.start_col) else {
+                continue;
+            };
+            if col >= len && col < view.width {
+                let x = col as u16 + LINE_NUMBER_WIDTH + 3;
+                print!(self.stdout, cursor::Goto(x, line as u16 + 1));
+                print!(self.stdout, "│");
+            }
+        }
+        print!(self.stdout, color::Fg(color::Reset));
+    }
+
     /// # Draw the line numbers
-    /// The line numbers are displayed at the left of the screen in blue
-    fn draw_line_number(&mut self, line: usize) {
-        // Set foreground color to blue
-        print!(self.stdout, color::Fg(color::Blue));
+    /// The line numbers are displayed at the left of the screen in blue,
+    /// the line numbers of the selected lines are displayed in reverse video
+    fn draw_line_number(&mut self, line: usize, selected: bool) {
+        if selected {
+            print!(self.stdout, color::Bg(color::Blue));
+            print!(self.stdout, color::Fg(color::Black));
+        } else {
+            // Set foreground color to blue
+            print!(self.stdout, color::Fg(color::Blue));
+        }
         // Print the line number formatted to 3 characters
         print!(self.stdout, format!("{:3} ", line));
         // Reset both foreground and background colors
@@ -1,5 +1,19 @@
-use git2::{DiffOptions, ObjectType, Patch as GitPatch};
-use std::collections::HashMap;
+use git2::{
+    build::CheckoutBuilder, Branch, BranchType, DescribeFormatOptions, DescribeOptions, DiffFormat,
+    DiffOptions, ErrorCode, IndexEntry, IndexTime, ObjectType, Oid, Patch as GitPatch,
+    RepositoryState, Sort, Status, StatusOptions, Tree,
+};
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    fs,
+    ops::{Range, RangeInclusive},
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use super::saved::saved_hunks;
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum PatchType {
@@ -13,238 +27,2395 @@ pub struct Patch {
     start: usize,
     count: usize,
     patch_type: PatchType,
+    /// Whether the change is staged (index vs HEAD) or not (buffer vs index)
+    staged: bool,
+}
+
+/// A hunk of the diff between the diff base and the buffer
+#[derive(Clone, Debug, PartialEq)]
+pub struct Hunk {
+    /// First line of the hunk in the buffer (0-indexed). For pure deletions, it is
+    /// the line below the deleted lines.
+    pub start: usize,
+    /// Number of lines of the hunk in the buffer (0 for pure deletions)
+    pub count: usize,
+    /// Lines of the diff base replaced by the hunk (with their line endings)
+    pub old_lines: Vec<String>,
+    /// Whether the change is staged (index vs HEAD) or not (buffer vs index)
+    pub staged: bool,
+}
+
+impl Hunk {
+    /// Convert a raw hunk (1-indexed) to a hunk of the buffer (0-indexed)
+    fn from_raw(hunk: &RawHunk, staged: bool) -> Self {
+        Self {
+            start: hunk.new_range().start,
+            count: hunk.new_lines,
+            old_lines: hunk.old_text.clone(),
+            staged,
+        }
+    }
+
+    /// Let's perform a guess on the patch type:
+    /// - If the old count is the same as the new count, it's a change
+    /// - If the old count is less than the new count, it's an addition
+    /// - If the old count is more than the new count, it's a deletion
+    pub fn patch_type(&self) -> PatchType {
+        let old_count = self.old_lines.len();
+        if old_count == self.count {
+            PatchType::Changed
+        } else if old_count < self.count {
+            PatchType::Added
+        } else {
+            PatchType::Deleted
+        }
+    }
+
+    /// Line of the (first) marker of the hunk in the gutter
+    pub fn marker_line(&self) -> usize {
+        Patch::from(self).start
+    }
+
+    /// Lines of the buffer covered by the hunk (the line below it for a deletion)
+    pub fn lines(&self) -> RangeInclusive<usize> {
+        self.start..=self.start + self.count.saturating_sub(1)
+    }
+
+    /// Whether the given line is part of the hunk or of its marker in the gutter
+    pub fn contains(&self, line: usize) -> bool {
+        let patch = Patch::from(self);
+        (self.start..self.start + self.count).contains(&line)
+            || (patch.start..patch.start + patch.count).contains(&line)
+    }
+}
+
+impl From<&Hunk> for Patch {
+    fn from(hunk: &Hunk) -> Self {
+        let patch_type = hunk.patch_type();
+
+        /*  If patch is a deletion, new_count will be 0 so we hard code it to 1
+            and start will be marked beneath the deleted line.
+        */
+        Patch {
+            start: if patch_type == PatchType::Deleted && hunk.count > 0 {
+                hunk.start + 1
+            } else {
+                hunk.start
+            },
+            count: if patch_type == PatchType::Deleted {
+                1
+            } else {
+                hunk.count
+            },
+            patch_type,
+            staged: hunk.staged,
+        }
+    }
+}
+
+/// Marker displayed in the gutter for a changed line
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Marker {
+    pub patch_type: PatchType,
+    pub staged: bool,
+}
+
+/// What the buffer is compared to in the gutter
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum DiffBase {
+    /// Unstaged changes (buffer vs index) and staged changes (index vs HEAD)
+    #[default]
+    Index,
+    /// All the changes since the last commit (buffer vs HEAD)
+    Head,
+    /// All the changes since an arbitrary revision (`main`, `HEAD~3`, a hash...)
+    Revision(String),
+    /// The unsaved changes (buffer vs file on disk)
+    Saved,
+}
+
+impl DiffBase {
+    /// Parse a diff base typed by the user
+    pub fn parse(base: &str) -> Self {
+        match base.trim() {
+            "" | "index" => DiffBase::Index,
+            "HEAD" => DiffBase::Head,
+            "saved" => DiffBase::Saved,
+            rev => DiffBase::Revision(rev.into()),
+        }
+    }
+}
+
+impl Display for DiffBase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DiffBase::Index => write!(f, "index"),
+            DiffBase::Head => write!(f, "HEAD"),
+            DiffBase::Revision(rev) => write!(f, "{}", rev),
+            DiffBase::Saved => write!(f, "saved"),
+        }
+    }
+}
+
+/// Last commit that changed a line of the file
+#[derive(Clone, Debug, PartialEq)]
+pub struct BlameLine {
+    pub commit: Oid,
+    pub author: String,
+    /// Time of the commit (seconds since the epoch)
+    pub time: i64,
+}
+
+impl Display for BlameLine {
+    /// Short annotation displayed next to the line: `a1b2c3d Author, 3 days ago`
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let id = self.commit.to_string();
+        write!(
+            f,
+            "{} {}, {}",
+            &id[..7],
+            self.author,
+            relative_date(self.time)
+        )
+    }
+}
+
+/// A commit of the history of a file
+#[derive(Clone, Debug, PartialEq)]
+pub struct Revision {
+    pub commit: Oid,
+    /// First line of the commit message
+    pub summary: String,
+    pub author: String,
+    /// Time of the commit (seconds since the epoch)
+    pub time: i64,
+}
+
+impl Revision {
+    /// Abbreviated id of the commit
+    pub fn short_id(&self) -> String {
+        self.commit.to_string()[..7].to_string()
+    }
+}
+
+impl Display for Revision {
+    /// Line of the history: `a1b2c3d Summary (Author, 3 days ago)`
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} ({}, {})",
+            self.short_id(),
+            self.summary,
+            self.author,
+            relative_date(self.time)
+        )
+    }
+}
+
+/// The versions of a conflicted file recorded in the index during a merge
+/// (`None` if the file does not exist on that side)
+#[derive(Debug, PartialEq)]
+pub struct ConflictVersions {
+    /// The common ancestor
+    pub base: Option<String>,
+    pub ours: Option<String>,
+    pub theirs: Option<String>,
+}
+
+/// Kind of a reference that can be checked out
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RefKind {
+    /// Local branch
+    Local,
+    /// Remote-tracking branch (`origin/main`)
+    Remote,
+    Tag,
+}
+
+/// A branch or a tag of the repository
+#[derive(Clone, Debug, PartialEq)]
+pub struct GitRef {
+    /// Short name of the reference (`main`, `origin/main`, `v1.0`)
+    pub name: String,
+    pub kind: RefKind,
+    /// Whether HEAD points to this branch
+    pub current: bool,
+}
+
+impl Display for GitRef {
+    /// Line of the branch switcher: `* main`, `  origin/main (remote)`, `  v1.0 (tag)`
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let marker = if self.current { '*' } else { ' ' };
+        write!(f, "{} {}", marker, self.name)?;
+        match self.kind {
+            RefKind::Local => Ok(()),
+            RefKind::Remote => write!(f, " (remote)"),
+            RefKind::Tag => write!(f, " (tag)"),
+        }
+    }
+}
+
+/// An entry of the stash list
+#[derive(Clone, Debug, PartialEq)]
+pub struct Stash {
+    /// Position in the stash list (0 is the most recent)
+    pub index: usize,
+    /// Message of the stash (`WIP on main: a1b2c3d Summary` by default)
+    pub message: String,
+}
+
+impl Display for Stash {
+    /// Line of the stash list: `stash@{0}: WIP on main: a1b2c3d Summary`
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "stash@{{{}}}: {}", self.index, self.message)
+    }
+}
+
+/// State of the repository displayed in the status bar
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RepoStatus {
+    /// Current branch, or the tag (or `describe` output) of HEAD when it is detached
+    pub head: String,
+    /// Number of commits ahead of and behind the upstream branch, if there is one
+    pub ahead_behind: Option<(usize, usize)>,
+    /// Whether the worktree has unstaged changes
+    pub unstaged: bool,
+    /// Whether the index has staged changes
+    pub staged: bool,
+    /// Operation in progress: `MERGING`, `REBASE 2/5`...
+    pub operation: Option<String>,
+    /// Where the repository comes from: the superproject of a submodule (`app@main`)
+    /// or the name of a linked worktree (`worktree feature`)
+    pub context: Option<String>,
+}
+
+impl Display for RepoStatus {
+    /// Summary of the state of the repository, like the git prompt of shells:
+    /// `main*+ ↑1↓2 |MERGING` (`*` unstaged changes, `+` staged changes),
+    /// preceded by its context if any: `app@main › lib`
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(context) = &self.context {
+            write!(f, "{} › ", context)?;
+        }
+        write!(f, "{}", self.head)?;
+        if self.unstaged {
+            write!(f, "*")?;
+        }
+        if self.staged {
+            write!(f, "+")?;
+        }
+        match self.ahead_behind {
+            Some((0, 0)) | None => {}
+            Some((ahead, 0)) => write!(f, " ↑{}", ahead)?,
+            Some((0, behind)) => write!(f, " ↓{}", behind)?,
+            Some((ahead, behind)) => write!(f, " ↑{}↓{}", ahead, behind)?,
+        }
+        if let Some(operation) = &self.operation {
+            write!(f, " |{}", operation)?;
+        }
+        Ok(())
+    }
+}
+
+/// Status of the file being edited in the repository
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FileStatus {
+    /// The file is in HEAD
+    Tracked,
+    /// The file is in the index but not in HEAD yet
+    Added,
+    /// The file is neither in HEAD nor in the index
+    Untracked,
+    /// The file is untracked and matches a `.gitignore` pattern
+    Ignored,
 }
 
+impl Display for FileStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let status = match self {
+            FileStatus::Tracked => "tracked",
+            FileStatus::Added => "new file",
+            FileStatus::Untracked => "untracked",
+            FileStatus::Ignored => "ignored",
+        };
+        write!(f, "{}", status)
+    }
+}
+
+/// The diff of a buffer, computed by `Vcs::diff_result` and stored by `Vcs::apply_diff`
+pub struct DiffResult {
+    /// Status of the file in the repository (`None` outside of a repository)
+    pub(super) file_status: Option<FileStatus>,
+    pub(super) hunks: Vec<Hunk>,
+    /// Changes since HEAD, to map the blame of HEAD onto the buffer (if it is enabled)
+    pub(super) head_changes: Option<Vec<RawHunk>>,
+    /// Number of lines of the buffer
+    pub(super) line_count: usize,
+}
+
+/// Hunk of a diff between two buffers, with 1-indexed line numbers like in unified diffs
+pub(super) struct RawHunk {
+    old_start: usize,
+    old_lines: usize,
+    new_start: usize,
+    new_lines: usize,
+    /// Removed lines of the old buffer
+    old_text: Vec<String>,
+}
+
+impl RawHunk {
+    /// 0-indexed lines of the old buffer replaced by the hunk (empty for an insertion,
+    /// positioned before the line following the insertion point)
+    fn old_range(&self) -> Range<usize> {
+        let start = match self.old_lines {
+            0 => self.old_start,
+            _ => self.old_start - 1,
+        };
+        start..start + self.old_lines
+    }
+
+    /// 0-indexed lines of the new buffer inserted by the hunk (empty for a deletion)
+    fn new_range(&self) -> Range<usize> {
+        let start = match self.new_lines {
+            0 => self.new_start,
+            _ => self.new_start - 1,
+        };
+        start..start + self.new_lines
+    }
+}
+
+/// What the buffer of a file is compared to: the diff and the markers of the gutter.
+/// The other operations only make sense in a git repository, see `as_git`.
 pub trait Vcs {
-    fn get_ref(&self) -> String;
-    fn get_file_content(&mut self, file_path: &str, file_name: &str) -> Option<String>;
-    fn compute_diff(
+    fn get_hunks(
         &mut self,
+        content: &str,
         file_path: &str,
         file_name: &str,
-        content: &str,
-    ) -> Result<(), Box<dyn std::error::Error>>;
-    fn get_diff_result(
+    ) -> Result<Vec<Hunk>, Box<dyn std::error::Error>>;
+    fn diff_result(
         &mut self,
+        file_path: &str,
+        file_name: &str,
         content: &str,
+        with_blame: bool,
+    ) -> Result<DiffResult, Box<dyn std::error::Error>>;
+    fn apply_diff(&mut self, result: DiffResult);
+    fn diff(&self) -> Option<HashMap<usize, Marker>>;
+    fn hunks(&self) -> Vec<Hunk>;
+    fn diff_base(&self) -> DiffBase;
+    fn set_diff_base(&mut self, base: DiffBase) -> Result<(), git2::Error>;
+
+    fn compute_diff(
+        &mut self,
         file_path: &str,
         file_name: &str,
-    ) -> Result<Vec<Patch>, Box<dyn std::error::Error>>;
-    fn diff(&self) -> Option<HashMap<usize, PatchType>>;
+        content: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let with_blame = self.as_git().is_some_and(Git::blame_enabled);
+        let result = self.diff_result(file_path, file_name, content, with_blame)?;
+        self.apply_diff(result);
+        Ok(())
+    }
+
+    /// The git repository the buffer is compared to, if any
+    fn as_git(&self) -> Option<&Git> {
+        None
+    }
+
+    fn as_git_mut(&mut self) -> Option<&mut Git> {
+        None
+    }
+}
+
+/// Error of the git operations on a file outside of a repository
+pub(super) fn not_in_repository() -> git2::Error {
+    git2::Error::from_str("Not in a git repository")
 }
 
 pub struct Git {
     repo: git2::Repository,
-    diff: Option<HashMap<usize, PatchType>>,
+    diff: Option<HashMap<usize, Marker>>,
+    hunks: Vec<Hunk>,
+    base: DiffBase,
+    /// Blame of the lines of the file in HEAD, if blame annotations are enabled
+    head_blame: Option<Vec<BlameLine>>,
+    /// Blame of the lines of the buffer (`None` for the lines not committed yet)
+    blame: Option<Vec<Option<BlameLine>>>,
+    /// State of the repository, refreshed in the background
+    status: Option<RepoStatus>,
+    /// Status of the file when the diff was computed
+    file_status: Option<FileStatus>,
+    /// Context of the repository (linked worktree or submodule), computed once
+    context: Option<String>,
 }
 
 impl Git {
+    /// Discover the repository containing the given directory. The directory
+    /// itself may not exist yet (new file in a new directory), in which case the
+    /// discovery starts from its closest existing ancestor.
+    /// Bare repositories are ignored: a file inside one has no working tree to diff against.
+    /// So are the files of the git directory itself (such as the commit message).
     pub fn open(path: &str) -> Option<Self> {
-        if let Ok(repo) = git2::Repository::discover(path) {
-            Some(Self { repo, diff: None })
+        let mut git = Self::discover(path)?;
+        git.context = git.context();
+        Some(git)
+    }
+
+    /// Discover the repository like `open`, without computing its context
+    fn discover(path: &str) -> Option<Self> {
+        let path = Path::new(path);
+        let dir = path.ancestors().find(|dir| dir.is_dir()).unwrap_or(path);
+        let dir = if dir.as_os_str().is_empty() {
+            Path::new(".")
         } else {
-            None
+            dir
+        };
+        let in_git_dir = |repo: &git2::Repository| match (
+            fs::canonicalize(dir),
+            fs::canonicalize(repo.path()),
+        ) {
+            (Ok(dir), Ok(git_dir)) => dir.starts_with(git_dir),
+            _ => false,
+        };
+        match git2::Repository::discover(dir) {
+            Ok(repo) if !repo.is_bare() && !in_git_dir(&repo) => Some(Self {
+                repo,
+                diff: None,
+                hunks: vec![],
+                base: DiffBase::default(),
+                head_blame: None,
+                blame: None,
+                status: None,
+                file_status: None,
+                context: None,
+            }),
+            _ => None,
         }
     }
-}
-impl Vcs for Git {
-    fn get_ref(&self) -> String {
+
+    /// Tree of the commit pointed to by HEAD, `None` if the branch is unborn
+    /// (freshly initialized repository or orphan branch)
+    fn head_tree(&self) -> Result<Option<git2::Tree<'_>>, git2::Error> {
         match self.repo.head() {
-            Ok(head) => {
-                if let Some(name) = head.name() {
-                    // Remove the "refs/heads/" prefix
-                    name.trim_start_matches("refs/heads/").to_string()
-                } else {
-                    if let Some(hash) = head.shorthand() {
-                        // If the head is a commit hash
-                        hash.to_string()
-                    } else {
-                        // If the head is not utf-8 encoded
-                        "".to_string()
-                    }
-                }
-            }
-            Err(_) => panic!("Error while getting the head"),
-        }
-    }
-
-    fn get_file_content(&mut self, file_path: &str, file_name: &str) -> Option<String> {
-        let head_commit = self.repo.head().unwrap().peel_to_commit().unwrap();
-        let tree = head_commit.tree().unwrap();
-
-        let rel_path = file_path.trim_start_matches("./");
-        let binding = std::path::Path::new(rel_path).join(file_name);
-
-        let path = binding.as_path();
-        let entry = tree.get_path(path);
-        match entry {
-            Ok(entry) => {
-                let obj = self
-                    .repo
-                    .find_object(entry.id(), Some(ObjectType::Blob))
-                    .unwrap();
-                if let Some(blob) = obj.as_blob() {
-                    // ensure the content is utf-8 encoded
-                    let content_res = std::str::from_utf8(blob.content());
-                    match content_res {
-                        Ok(content) => return Some(content.to_string()),
-                        Err(_) => return None,
-                    }
-                } else {
-                    // If the object is not a blob
-                    return None;
-                }
+            Ok(head) => Ok(Some(head.peel_to_tree()?)),
+            Err(e) if e.code() == ErrorCode::UnbornBranch => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Content of a blob, `None` if it is not utf-8 encoded
+    fn blob_content(&self, id: Oid) -> Result<Option<String>, git2::Error> {
+        let obj = self.repo.find_object(id, Some(ObjectType::Blob))?;
+        Ok(obj
+            .as_blob()
+            .and_then(|blob| std::str::from_utf8(blob.content()).ok())
+            .map(str::to_string))
+    }
+
+    /// Content of a file in a tree, `None` if the file is not in the tree
+    fn tree_content(&self, tree: &Tree, path: &Path) -> Result<Option<String>, git2::Error> {
+        match tree.get_path(path) {
+            Ok(entry) => self.blob_content(entry.id()),
+            Err(e) if e.code() == ErrorCode::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Index of the repository, reloaded if it was changed by another process
+    fn index(&self) -> Result<git2::Index, git2::Error> {
+        let mut index = self.repo.index()?;
+        index.read(false)?;
+        Ok(index)
+    }
+
+    /// Content of the file in the index, `None` if the file is not in the index
+    fn get_index_content(
+        &self,
+        file_path: &str,
+        file_name: &str,
+    ) -> Result<Option<String>, git2::Error> {
+        let Some(path) = self.relative_path(&Path::new(file_path).join(file_name)) else {
+            return Ok(None);
+        };
+        match self.index()?.get_path(&path, 0) {
+            Some(entry) => self.blob_content(entry.id),
+            None => Ok(None),
+        }
+    }
+
+    /// Replace the content of a file in the index (without touching the working tree),
+    /// or remove it from the index if `content` is `None`
+    fn write_index_content(&self, path: &Path, content: Option<&str>) -> Result<(), git2::Error> {
+        let mut index = self.index()?;
+        match content {
+            Some(content) => {
+                let entry = match index.get_path(path, 0) {
+                    Some(entry) => entry,
+                    // New file in the index
+                    None => IndexEntry {
+                        ctime: IndexTime::new(0, 0),
+                        mtime: IndexTime::new(0, 0),
+                        dev: 0,
+                        ino: 0,
+                        mode: self.worktree_mode(path),
+                        uid: 0,
+                        gid: 0,
+                        file_size: 0,
+                        id: Oid::zero(),
+                        flags: 0,
+                        flags_extended: 0,
+                        path: path.to_string_lossy().as_bytes().to_vec(),
+                    },
+                };
+                index.add_frombuffer(&entry, content.as_bytes())?;
             }
+            None => index.remove_path(path)?,
+        }
+        index.write()
+    }
+
+    /// Mode of a new index entry for a file of the working tree, like `git add` gives it:
+    /// a symbolic link, an executable file (unless `core.fileMode` is false) or a file
+    fn worktree_mode(&self, path: &Path) -> u32 {
+        let metadata = self
+            .repo
+            .workdir()
+            .and_then(|workdir| fs::symlink_metadata(workdir.join(path)).ok());
+        let file_mode = self
+            .repo
+            .config()
+            .and_then(|config| config.get_bool("core.fileMode"))
+            .unwrap_or(true);
+        match metadata {
+            Some(metadata) if metadata.file_type().is_symlink() => 0o120000,
+            Some(metadata) if file_mode && metadata.permissions().mode() & 0o111 != 0 => 0o100755,
+            _ => 0o100644,
+        }
+    }
+
+    /// Name of a detached HEAD: the tag pointing to it, or the output of
+    /// `git describe --tags` (`v1.0-3-ga1b2c3d`), or the abbreviated commit id
+    fn describe_head(&self) -> Result<String, git2::Error> {
+        let mut options = DescribeOptions::new();
+        options.describe_tags();
+        let mut format = DescribeFormatOptions::new();
+        format.abbreviated_size(7);
+        match self
+            .repo
+            .describe(&options)
+            .and_then(|describe| describe.format(Some(&format)))
+        {
+            Ok(name) => Ok(name),
+            // No tag to describe HEAD with
             Err(_) => {
-                return None;
+                let commit = self.repo.head()?.peel_to_commit()?;
+                Ok(commit.id().to_string()[..7].to_string())
+            }
+        }
+    }
+
+    /// Number of commits the current branch is ahead of and behind its upstream,
+    /// `None` if HEAD is not on a branch or the branch has no upstream
+    fn ahead_behind(&self) -> Option<(usize, usize)> {
+        let head = self.repo.head().ok()?;
+        if !head.is_branch() {
+            return None;
+        }
+        let upstream = Branch::wrap(head).upstream().ok()?;
+        let local = self.repo.head().ok()?.target()?;
+        let upstream = upstream.get().target()?;
+        self.repo.graph_ahead_behind(local, upstream).ok()
+    }
+
+    /// Operation in progress in the repository, with its progress for rebases
+    fn operation(&self) -> Option<String> {
+        // Progress of a rebase, from the files git keeps in the git directory
+        let progress = |dir: &str, step: &str, total: &str| {
+            let read = |name| fs::read_to_string(self.repo.path().join(dir).join(name)).ok();
+            match (read(step), read(total)) {
+                (Some(step), Some(total)) => format!(" {}/{}", step.trim(), total.trim()),
+                _ => String::new(),
+            }
+        };
+        let operation = match self.repo.state() {
+            RepositoryState::Clean => return None,
+            RepositoryState::Merge => "MERGING".to_string(),
+            RepositoryState::Revert | RepositoryState::RevertSequence => "REVERTING".to_string(),
+            RepositoryState::CherryPick | RepositoryState::CherryPickSequence => {
+                "CHERRY-PICKING".to_string()
+            }
+            RepositoryState::Bisect => "BISECTING".to_string(),
+            RepositoryState::RebaseInteractive => {
+                format!("REBASE-i{}", progress("rebase-merge", "msgnum", "end"))
+            }
+            RepositoryState::RebaseMerge => {
+                format!("REBASE-m{}", progress("rebase-merge", "msgnum", "end"))
+            }
+            RepositoryState::Rebase => {
+                format!("REBASE{}", progress("rebase-apply", "next", "last"))
+            }
+            RepositoryState::ApplyMailbox => {
+                format!("AM{}", progress("rebase-apply", "next", "last"))
+            }
+            RepositoryState::ApplyMailboxOrRebase => {
+                format!("AM/REBASE{}", progress("rebase-apply", "next", "last"))
             }
         };
+        Some(operation)
+    }
+
+    /// Context of the repository: the name of a linked worktree (`worktree feature`), or
+    /// the superproject of a submodule with its HEAD (`app@main`). A submodule is a
+    /// repository whose working directory is a gitlink in the index of the repository
+    /// containing it. It does not change while the repository is open (`Git::open`).
+    fn context(&self) -> Option<String> {
+        if self.repo.is_worktree() {
+            let worktree = git2::Worktree::open_from_repository(&self.repo).ok()?;
+            return Some(format!("worktree {}", worktree.name()?));
+        }
+        let workdir = fs::canonicalize(self.repo.workdir()?).ok()?;
+        let superproject = Git::discover(workdir.parent()?.to_str()?)?;
+        let path = superproject.relative_path(&workdir)?;
+        let entry = superproject.index().ok()?.get_path(&path, 0)?;
+        if entry.mode != 0o160000 {
+            return None;
+        }
+        let super_workdir = fs::canonicalize(superproject.repo.workdir()?).ok()?;
+        let name = super_workdir.file_name()?.to_string_lossy();
+        Some(format!("{}@{}", name, superproject.get_ref().ok()?))
     }
 
-    /// Get the result of the `diff` command between the current commit and the string given in parameter
-    /// for the given file path. The exact command is:
+    /// Path of a file relative to the working directory of the repository.
+    /// The file itself does not need to exist, only its parent directory.
+    fn relative_path(&self, path: &Path) -> Option<PathBuf> {
+        let workdir = fs::canonicalize(self.repo.workdir()?).ok()?;
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let path = fs::canonicalize(dir).ok()?.join(path.file_name()?);
+        path.strip_prefix(workdir).ok().map(Path::to_path_buf)
+    }
+}
+impl Vcs for Git {
+    /// Get the hunks of the `diff` command between the diff base and the string given in parameter
+    /// for the given file path. With the default base (`DiffBase::Index`), the exact commands are:
     /// ```sh
-    /// diff -u <(git show HEAD:{file_name}) <(echo {content})
+    /// diff -u <(git show :{file_name}) <(echo {content})      # unstaged changes
+    /// diff -u <(git show HEAD:{file_name}) <(git show :{file_name})  # staged changes
     /// ```
-    /// and should be run where the file is located (`file_path`).
-    fn get_diff_result(
+    /// and should be run where the file is located (`file_path`). Staged changes are
+    /// reported at the position of the corresponding lines in the buffer.
+    /// Staged hunks come first and unstaged hunks last.
+    fn get_hunks(
         &mut self,
         content: &str,
         file_path: &str,
         file_name: &str,
-    ) -> Result<Vec<Patch>, Box<dyn std::error::Error>> {
-        // Get the content of the file at the current commit (HEAD)
-        let content_opt = self.get_file_content(file_path, file_name);
+    ) -> Result<Vec<Hunk>, Box<dyn std::error::Error>> {
+        // Every line is added if the file is not in the base
+        let hunks = match &self.base {
+            DiffBase::Head => {
+                let old_content = self.get_file_content(file_path, file_name)?;
+                hunks(
+                    &diff_hunks(&old_content.unwrap_or_default(), content)?,
+                    false,
+                )
+            }
+            DiffBase::Revision(rev) => {
+                let old_content = self.get_revision_content(rev, file_path, file_name)?;
+                hunks(
+                    &diff_hunks(&old_content.unwrap_or_default(), content)?,
+                    false,
+                )
+            }
+            DiffBase::Saved => saved_hunks(content, file_path, file_name)?,
+            DiffBase::Index => {
+                let head_content = self.get_file_content(file_path, file_name)?;
+                // A file missing from the index is compared to HEAD
+                let index_content = match self.get_index_content(file_path, file_name)? {
+                    Some(index_content) => index_content,
+                    None => head_content.clone().unwrap_or_default(),
+                };
+
+                let unstaged = diff_hunks(&index_content, content)?;
+                let staged = diff_hunks(&head_content.unwrap_or_default(), &index_content)?;
+
+                let mut hunks = hunks(&staged, true);
+                for hunk in hunks.iter_mut() {
+                    hunk.start = map_line(hunk.start, &unstaged);
+                }
+                hunks.extend(self::hunks(&unstaged, false));
+                hunks
+            }
+        };
+        Ok(hunks)
+    }
 
-        let old_content = match content_opt {
-            Some(c) => c,
-            None => "".to_string(),
+    /// Compute the diff of the buffer without storing it, so that it can be computed
+    /// by another instance (on a snapshot of the buffer) and applied later
+    fn diff_result(
+        &mut self,
+        file_path: &str,
+        file_name: &str,
+        content: &str,
+        with_blame: bool,
+    ) -> Result<DiffResult, Box<dyn std::error::Error>> {
+        let file_status = self.get_file_status(file_path, file_name)?;
+        // The lines of a file unknown to git are not marked as added (unless the buffer
+        // is compared to a revision, which may contain it, or to the saved file)
+        let unknown = matches!(file_status, FileStatus::Untracked | FileStatus::Ignored);
+        let hunks = match unknown && matches!(self.base, DiffBase::Index | DiffBase::Head) {
+            true => vec![],
+            false => self.get_hunks(content, file_path, file_name)?,
+        };
+        // Lines changed since HEAD are not blamed
+        let head_changes = match with_blame {
+            true => {
+                let head = self
+                    .get_file_content(file_path, file_name)?
+                    .unwrap_or_default();
+                Some(diff_hunks(&head, content)?)
+            }
+            false => None,
         };
+        Ok(DiffResult {
+            file_status: Some(file_status),
+            hunks,
+            head_changes,
+            line_count: content.lines().count(),
+        })
+    }
+
+    /// Store the diff of the buffer and update the markers of the gutter and the
+    /// blame of the lines
+    fn apply_diff(&mut self, result: DiffResult) {
+        self.diff = Some(markers(&result.hunks));
+        self.hunks = result.hunks;
+        self.file_status = result.file_status;
+
+        if let (Some(head_blame), Some(changes)) = (&self.head_blame, &result.head_changes) {
+            self.blame = Some(blame_lines(head_blame, changes, result.line_count));
+        }
+    }
+
+    fn diff(&self) -> Option<HashMap<usize, Marker>> {
+        self.diff.clone()
+    }
 
-        let old_buffer = old_content.as_bytes();
-        let new_buffer = content.as_bytes();
-        let mut options = DiffOptions::default();
-        options.context_lines(0);
+    fn hunks(&self) -> Vec<Hunk> {
+        self.hunks.clone()
+    }
 
-        let patch = GitPatch::from_buffers(old_buffer, None, new_buffer, None, Some(&mut options))?;
+    fn diff_base(&self) -> DiffBase {
+        self.base.clone()
+    }
 
-        let mut patches: Vec<Patch> = vec![];
+    /// Change what the buffer is compared to, the revision must exist
+    fn set_diff_base(&mut self, base: DiffBase) -> Result<(), git2::Error> {
+        if let DiffBase::Revision(rev) = &base {
+            self.repo.revparse_single(rev)?.peel_to_tree()?;
+        }
+        self.base = base;
+        Ok(())
+    }
 
-        for num in 0..patch.num_hunks() {
-            let hunk = patch.hunk(num)?.0;
+    fn as_git(&self) -> Option<&Git> {
+        Some(self)
+    }
 
-            let new_start = hunk.new_start() as usize;
+    fn as_git_mut(&mut self) -> Option<&mut Git> {
+        Some(self)
+    }
+}
 
-            let old_count = hunk.old_lines() as usize;
-            let new_count = hunk.new_lines() as usize;
+/// Operations that only make sense in a git repository
+impl Git {
+    /// Path of the git directory (`.git`) of the repository
+    pub fn git_dir(&self) -> Option<&Path> {
+        Some(self.repo.path())
+    }
 
-            /*
-            Let's perform a guess on the patch type:
-            - If the old count is the same as the new count, it's a change
-            - If the old count is less than the new count, it's an addition
-            - If the old count is more than the new count, it's a deletion
-            */
+    pub fn get_ref(&self) -> Result<String, git2::Error> {
+        let head = match self.repo.head() {
+            Ok(head) => head,
+            // On an unborn branch, HEAD is a symbolic reference to a branch that does not exist yet
+            Err(e) if e.code() == ErrorCode::UnbornBranch => {
+                let head = self.repo.find_reference("HEAD")?;
+                let target = head.symbolic_target().unwrap_or_default();
+                return Ok(target.trim_start_matches("refs/heads/").to_string());
+            }
+            Err(e) => return Err(e),
+        };
 
-            let patch_type = if old_count == new_count {
-                PatchType::Changed
-            } else if old_count < new_count {
-                PatchType::Added
-            } else {
-                PatchType::Deleted
-            };
+        if self.repo.head_detached()? {
+            self.describe_head()
+        } else if let Some(name) = head.name() {
+            // Remove the "refs/heads/" prefix
+            Ok(name.trim_start_matches("refs/heads/").to_string())
+        } else if let Some(hash) = head.shorthand() {
+            // If the head is a commit hash
+            Ok(hash.to_string())
+        } else {
+            // If the head is not utf-8 encoded
+            Ok("".to_string())
+        }
+    }
 
-            /*  If patch is a deletion, new_count will be 0 so we hard code it to 1
-                and start will be marked beneath the deleted line.
-
-                However, if patch is addition or change, line will be 1-indexed and we use 0-indexed line
-                so we need to increment it by 1.
-            */
-            patches.push(Patch {
-                start: if patch_type == PatchType::Deleted || new_start == 0 {
-                    new_start
-                } else {
-                    new_start - 1
-                },
-                count: if patch_type == PatchType::Deleted {
-                    1
-                } else {
-                    new_count
-                },
-                patch_type,
-            });
+    /// Compute the state of the repository displayed in the status bar
+    pub fn refresh_status(&mut self) -> Result<(), git2::Error> {
+        let mut options = StatusOptions::new();
+        options.include_untracked(false);
+        let (mut unstaged, mut staged) = (false, false);
+        for entry in self.repo.statuses(Some(&mut options))?.iter() {
+            let status = entry.status();
+            unstaged |= status.intersects(
+                Status::WT_MODIFIED
+                    | Status::WT_DELETED
+                    | Status::WT_RENAMED
+                    | Status::WT_TYPECHANGE
+                    | Status::CONFLICTED,
+            );
+            staged |= status.intersects(
+                Status::INDEX_NEW
+                    | Status::INDEX_MODIFIED
+                    | Status::INDEX_DELETED
+                    | Status::INDEX_RENAMED
+                    | Status::INDEX_TYPECHANGE,
+            );
         }
-        Ok(patches)
+        self.status = Some(RepoStatus {
+            head: self.get_ref()?,
+            ahead_behind: self.ahead_behind(),
+            unstaged,
+            staged,
+            operation: self.operation(),
+            context: self.context.clone(),
+        });
+        Ok(())
     }
 
-    fn compute_diff(
+    /// State of the repository, only the name of HEAD until it has been refreshed
+    pub fn status(&self) -> Option<RepoStatus> {
+        match &self.status {
+            Some(status) => Some(status.clone()),
+            None => Some(RepoStatus {
+                head: self.get_ref().ok()?,
+                ..Default::default()
+            }),
+        }
+    }
+
+    /// Get the content of the file at HEAD. Returns `None` if the file is not in HEAD
+    /// (new file, unborn branch) or if it is not a utf-8 encoded blob.
+    pub fn get_file_content(
         &mut self,
         file_path: &str,
         file_name: &str,
-        content: &str,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let patches = self.get_diff_result(content, file_path, file_name)?;
+    ) -> Result<Option<String>, git2::Error> {
+        let Some(tree) = self.head_tree()? else {
+            return Ok(None);
+        };
+        match self.relative_path(&Path::new(file_path).join(file_name)) {
+            Some(path) => self.tree_content(&tree, &path),
+            None => Ok(None),
+        }
+    }
 
-        let mut marks = HashMap::new();
+    /// Whether the blame annotations are computed
+    pub fn blame_enabled(&self) -> bool {
+        self.head_blame.is_some()
+    }
 
-        for patch in patches {
-            match patch.patch_type {
-                PatchType::Added => {
-                    for i in 0..patch.count {
-                        marks.insert(patch.start + i, PatchType::Added);
-                    }
-                }
-                PatchType::Deleted => {
-                    for i in 0..patch.count {
-                        marks.insert(patch.start + i, PatchType::Deleted);
-                    }
-                }
-                PatchType::Changed => {
-                    for i in 0..patch.count {
-                        marks.insert(patch.start + i, PatchType::Changed);
-                    }
-                }
-            }
+    /// Whether the file is in HEAD, only in the index, untracked or ignored
+    pub fn get_file_status(
+        &self,
+        file_path: &str,
+        file_name: &str,
+    ) -> Result<FileStatus, git2::Error> {
+        let Some(path) = self.relative_path(&Path::new(file_path).join(file_name)) else {
+            return Ok(FileStatus::Untracked);
+        };
+        let in_head = match self.head_tree()? {
+            Some(tree) => tree.get_path(&path).is_ok(),
+            None => false,
+        };
+        if in_head {
+            Ok(FileStatus::Tracked)
+        } else if self.index()?.get_path(&path, 0).is_some() {
+            Ok(FileStatus::Added)
+        } else if self.repo.is_path_ignored(&path)? {
+            Ok(FileStatus::Ignored)
+        } else {
+            Ok(FileStatus::Untracked)
         }
-        self.diff = Some(marks);
-        Ok(())
     }
 
-    fn diff(&self) -> Option<HashMap<usize, PatchType>> {
-        self.diff.clone()
+    pub fn file_status(&self) -> Option<FileStatus> {
+        self.file_status
     }
-}
-
-mod tests {
-    #[cfg(test)]
-    use super::*;
 
-    #[test]
-    fn test_get_file_content() {
-        let file_path = "./tests/";
+    /// Stage the changes of the given lines of the buffer (0-indexed) into the index.
+    /// A hunk is staged entirely when all of its lines are selected (a deletion is
+    /// selected through the line below it). Otherwise only its selected added lines are.
+    pub fn stage_lines(
+        &mut self,
+        content: &str,
+        file_path: &str,
+        file_name: &str,
+        lines: RangeInclusive<usize>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let path = self
+            .relative_path(&Path::new(file_path).join(file_name))
+            .ok_or("File is not in the repository")?;
+        let index_content = match self.get_index_content(file_path, file_name)? {
+            Some(index_content) => index_content,
+            None => self
+                .get_file_content(file_path, file_name)?
+                .unwrap_or_default(),
+        };
 
-        // sample.txt is a file in the tests directory that must never change
-        let file_name = "sample.txt";
-        let mut git = Git::open(file_path).unwrap();
-        let content = git.get_file_content(file_path, file_name).unwrap();
-        let fs_content = std::fs::read_to_string(file_path.to_string() + file_name).unwrap();
-        assert_eq!(content, fs_content);
+        let new_index = apply_lines(&index_content, content, |line| lines.contains(&line))?;
+        self.write_index_content(&path, Some(&new_index))?;
+        Ok(())
     }
 
-    #[test]
+    /// Unstage the staged changes displayed on the given lines of the buffer (0-indexed).
+    /// The index is rebuilt from HEAD with the staged changes that are not selected.
+    pub fn unstage_lines(
+        &mut self,
+        content: &str,
+        file_path: &str,
+        file_name: &str,
+        lines: RangeInclusive<usize>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let path = self
+            .relative_path(&Path::new(file_path).join(file_name))
+            .ok_or("File is not in the repository")?;
+        let Some(index_content) = self.get_index_content(file_path, file_name)? else {
+            return Err("Nothing staged".into());
+        };
+        let head_content = self.get_file_content(file_path, file_name)?;
+
+        // Lines of the index corresponding to the selected lines of the buffer
+        let to_index = diff_hunks(content, &index_content)?;
+        let (first, last) = (
+            map_line(*lines.start(), &to_index),
+            map_line(*lines.end(), &to_index),
+        );
+
+        let old = head_content.clone().unwrap_or_default();
+        let new_index = apply_lines(&old, &index_content, |line| !(first..=last).contains(&line))?;
+        // A new file whose changes are all unstaged leaves the index
+        if head_content.is_none() && new_index.is_empty() {
+            self.write_index_content(&path, None)?;
+        } else {
+            self.write_index_content(&path, Some(&new_index))?;
+        }
+        Ok(())
+    }
+
+    /// Enable (or disable) the blame annotations. The blame of the file in HEAD
+    /// is computed once, the annotations of the buffer are then updated with the diff.
+    pub fn set_blame(
+        &mut self,
+        file_path: &str,
+        file_name: &str,
+        enabled: bool,
+    ) -> Result<(), git2::Error> {
+        if !enabled {
+            self.head_blame = None;
+            self.blame = None;
+            return Ok(());
+        }
+        let path = self
+            .relative_path(&Path::new(file_path).join(file_name))
+            .ok_or_else(|| git2::Error::from_str("File is not in the repository"))?;
+        // A file that is not in HEAD yet has no committed lines
+        let in_head = match self.head_tree()? {
+            Some(tree) => tree.get_path(&path).is_ok(),
+            None => false,
+        };
+        let mut head_blame = vec![];
+        if in_head {
+            let blame = self.repo.blame_file(&path, None)?;
+            for hunk in blame.iter() {
+                let author = hunk.final_signature();
+                let line = BlameLine {
+                    commit: hunk.final_commit_id(),
+                    author: author.name().unwrap_or_default().to_string(),
+                    time: author.when().seconds(),
+                };
+                head_blame.extend(std::iter::repeat_n(line, hunk.lines_in_hunk()));
+            }
+        }
+        self.head_blame = Some(head_blame);
+        Ok(())
+    }
+
+    pub fn blame(&self) -> Option<&[Option<BlameLine>]> {
+        self.blame.as_deref()
+    }
+
+    /// Description of a commit, like `git show --no-patch`
+    pub fn commit_message(&self, commit: Oid) -> Result<Vec<String>, git2::Error> {
+        let commit = self.repo.find_commit(commit)?;
+        let author = commit.author();
+        let mut lines = vec![
+            format!("commit {}", commit.id()),
+            format!(
+                "Author: {} <{}>",
+                author.name().unwrap_or_default(),
+                author.email().unwrap_or_default()
+            ),
+            format!("Date:   {}", relative_date(author.when().seconds())),
+            String::new(),
+        ];
+        lines.extend(
+            commit
+                .message()
+                .unwrap_or_default()
+                .trim_end()
+                .lines()
+                .map(|line| format!("    {}", line)),
+        );
+        Ok(lines)
+    }
+
+    /// Commits reachable from HEAD that changed the file, most recent first
+    /// (like `git log -- file`, renames are not followed)
+    pub fn history(&self, file_path: &str, file_name: &str) -> Result<Vec<Revision>, git2::Error> {
+        let Some(path) = self.relative_path(&Path::new(file_path).join(file_name)) else {
+            return Ok(vec![]);
+        };
+        if self.head_tree()?.is_none() {
+            return Ok(vec![]);
+        }
+        let blob_id = |commit: &git2::Commit| -> Result<Option<Oid>, git2::Error> {
+            Ok(commit.tree()?.get_path(&path).ok().map(|entry| entry.id()))
+        };
+
+        let mut revwalk = self.repo.revwalk()?;
+        revwalk.push_head()?;
+        revwalk.set_sorting(Sort::TIME)?;
+        let mut history = vec![];
+        for id in revwalk {
+            let commit = self.repo.find_commit(id?)?;
+            let blob = blob_id(&commit)?;
+            // The file changed if it differs from all the parents of the commit
+            let mut changed = blob.is_some() || commit.parent_count() > 0;
+            for parent in commit.parents() {
+                if blob_id(&parent)? == blob {
+                    changed = false;
+                }
+            }
+            if changed {
+                let author = commit.author();
+                history.push(Revision {
+                    commit: commit.id(),
+                    summary: commit.summary().unwrap_or_default().to_string(),
+                    author: author.name().unwrap_or_default().to_string(),
+                    time: author.when().seconds(),
+                });
+            }
+        }
+        Ok(history)
+    }
+
+    /// The versions of the file in the conflict entries of the index, `None` if the
+    /// file is not conflicted
+    pub fn conflict_versions(
+        &self,
+        file_path: &str,
+        file_name: &str,
+    ) -> Result<Option<ConflictVersions>, git2::Error> {
+        let Some(path) = self.relative_path(&Path::new(file_path).join(file_name)) else {
+            return Ok(None);
+        };
+        let index = self.index()?;
+        if !index.has_conflicts() {
+            return Ok(None);
+        }
+        let path = path.to_string_lossy().as_bytes().to_vec();
+        for conflict in index.conflicts()? {
+            let conflict = conflict?;
+            let entries = [&conflict.ancestor, &conflict.our, &conflict.their];
+            if !entries
+                .iter()
+                .any(|entry| matches!(entry, Some(e) if e.path == path))
+            {
+                continue;
+            }
+            let content = |entry: &Option<IndexEntry>| match entry {
+                Some(entry) => self.blob_content(entry.id),
+                None => Ok(None),
+            };
+            return Ok(Some(ConflictVersions {
+                base: content(&conflict.ancestor)?,
+                ours: content(&conflict.our)?,
+                theirs: content(&conflict.their)?,
+            }));
+        }
+        Ok(None)
+    }
+
+    /// Content of the file at the given revision, `None` if the file is not in the revision
+    pub fn get_revision_content(
+        &self,
+        rev: &str,
+        file_path: &str,
+        file_name: &str,
+    ) -> Result<Option<String>, git2::Error> {
+        let tree = self.repo.revparse_single(rev)?.peel_to_tree()?;
+        match self.relative_path(&Path::new(file_path).join(file_name)) {
+            Some(path) => self.tree_content(&tree, &path),
+            None => Ok(None),
+        }
+    }
+
+    /// Move a file on disk and, if it is tracked, in the index (like `git mv`)
+    pub fn move_file(
+        &mut self,
+        old_path: &Path,
+        new_path: &Path,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let old_rel = self.relative_path(old_path);
+        fs::rename(old_path, new_path)?;
+
+        let mut index = self.index()?;
+        let Some((old_rel, mut entry)) =
+            old_rel.and_then(|rel| Some((rel.clone(), index.get_path(&rel, 0)?)))
+        else {
+            // Untracked files are only moved on disk
+            return Ok(());
+        };
+        // The entry keeps its staged content and mode, the changes of the file that are
+        // not staged stay so (a file moved out of the repository is simply removed)
+        if let Some(new_rel) = self.relative_path(new_path) {
+            entry.path = new_rel.to_string_lossy().as_bytes().to_vec();
+            index.add(&entry)?;
+        }
+        index.remove_path(&old_rel)?;
+        index.write()?;
+        Ok(())
+    }
+
+    /// Write the template of the commit message (the summary of the staged changes
+    /// in comment lines) to `.git/COMMIT_EDITMSG` and return its path
+    pub fn prepare_commit(&self) -> Result<PathBuf, git2::Error> {
+        let mut options = StatusOptions::new();
+        options.include_untracked(false);
+        let mut changes = vec![];
+        for entry in self.repo.statuses(Some(&mut options))?.iter() {
+            let status = entry.status();
+            let kind = if status.contains(Status::INDEX_NEW) {
+                "new file"
+            } else if status.contains(Status::INDEX_MODIFIED) {
+                "modified"
+            } else if status.contains(Status::INDEX_DELETED) {
+                "deleted"
+            } else if status.contains(Status::INDEX_RENAMED) {
+                "renamed"
+            } else if status.contains(Status::INDEX_TYPECHANGE) {
+                "typechange"
+            } else {
+                continue;
+            };
+            changes.push(format!(
+                "#\t{}:   {}",
+                kind,
+                entry.path().unwrap_or_default()
+            ));
+        }
+        let merging = self.repo.state() == RepositoryState::Merge;
+        if changes.is_empty() && !merging {
+            return Err(git2::Error::from_str("no changes added to commit"));
+        }
+
+        let mut template = vec![
+            String::new(),
+            "# Please enter the commit message for your changes. Lines starting".to_string(),
+            "# with '#' will be ignored, and an empty message aborts the commit.".to_string(),
+            "#".to_string(),
+            format!("# On branch {}", self.get_ref()?),
+        ];
+        if merging {
+            template.push("# You are concluding a merge.".to_string());
+        }
+        template.push("# Changes to be committed:".to_string());
+        template.extend(changes);
+        template.push("#".to_string());
+
+        let path = self.repo.path().join("COMMIT_EDITMSG");
+        fs::write(&path, template.join("\n") + "\n")
+            .map_err(|e| git2::Error::from_str(&e.to_string()))?;
+        Ok(path)
+    }
+
+    /// Commit the index on HEAD with the signature configured by the user. If a
+    /// merge is being concluded, the merged commits are parents of the new commit.
+    pub fn commit(&mut self, message: &str) -> Result<Oid, git2::Error> {
+        let merging = self.repo.state() == RepositoryState::Merge;
+        let mut merge_heads = vec![];
+        if merging {
+            self.repo.mergehead_foreach(|id| {
+                merge_heads.push(*id);
+                true
+            })?;
+        }
+        let signature = self.repo.signature()?;
+        let mut index = self.index()?;
+        if index.has_conflicts() {
+            return Err(git2::Error::from_str("the index has unresolved conflicts"));
+        }
+        let tree = self.repo.find_tree(index.write_tree()?)?;
+
+        let mut parents = vec![];
+        match self.repo.head() {
+            Ok(head) => parents.push(head.peel_to_commit()?),
+            Err(e) if e.code() == ErrorCode::UnbornBranch => {}
+            Err(e) => return Err(e),
+        }
+        for id in &merge_heads {
+            parents.push(self.repo.find_commit(*id)?);
+        }
+
+        let parents = parents.iter().collect::<Vec<_>>();
+        let oid = self.repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            message,
+            &tree,
+            &parents,
+        )?;
+        if merging {
+            self.repo.cleanup_state()?;
+        }
+        Ok(oid)
+    }
+
+    /// Local and remote branches and tags, in this order (sorted by name)
+    pub fn refs(&self) -> Result<Vec<GitRef>, git2::Error> {
+        let head = self.repo.head().ok();
+        let head = head.as_ref().and_then(|head| head.name());
+        let mut refs = vec![];
+        for reference in self.repo.references()? {
+            let reference = reference?;
+            let Some(name) = reference.name() else {
+                continue;
+            };
+            let (kind, short_name) = if let Some(branch) = name.strip_prefix("refs/heads/") {
+                (RefKind::Local, branch)
+            } else if let Some(branch) = name.strip_prefix("refs/remotes/") {
+                // `origin/HEAD` is an alias of the default branch of the remote
+                if branch.ends_with("/HEAD") {
+                    continue;
+                }
+                (RefKind::Remote, branch)
+            } else if let Some(tag) = name.strip_prefix("refs/tags/") {
+                (RefKind::Tag, tag)
+            } else {
+                continue;
+            };
+            refs.push(GitRef {
+                name: short_name.into(),
+                kind,
+                current: head == Some(name),
+            });
+        }
+        refs.sort_by(|a, b| (a.kind, &a.name).cmp(&(b.kind, &b.name)));
+        Ok(refs)
+    }
+
+    /// Check out a branch or a tag (like `git switch`). The checkout is refused if it
+    /// would overwrite local changes. A remote branch is checked out in a new local
+    /// branch tracking it, or in the local branch of the same name if it already exists
+    /// (which may be on another commit). A tag detaches HEAD.
+    pub fn checkout(&mut self, git_ref: &GitRef) -> Result<(), git2::Error> {
+        // The reference checked out, and the local branch to create for a remote branch
+        let (target, new_branch) = match git_ref.kind {
+            RefKind::Local => (format!("refs/heads/{}", git_ref.name), None),
+            RefKind::Remote => {
+                let (_, local) = git_ref.name.split_once('/').unwrap_or(("", &git_ref.name));
+                match self.repo.find_branch(local, BranchType::Local) {
+                    Ok(_) => (format!("refs/heads/{}", local), None),
+                    Err(_) => (format!("refs/remotes/{}", git_ref.name), Some(local)),
+                }
+            }
+            RefKind::Tag => (format!("refs/tags/{}", git_ref.name), None),
+        };
+        let commit = self.repo.find_reference(&target)?.peel_to_commit()?;
+        self.repo
+            .checkout_tree(commit.as_object(), Some(CheckoutBuilder::new().safe()))?;
+
+        match (git_ref.kind, new_branch) {
+            (RefKind::Tag, _) => self.repo.set_head_detached(commit.id()),
+            (_, Some(local)) => {
+                let mut branch = self.repo.branch(local, &commit, false)?;
+                branch.set_upstream(Some(&git_ref.name))?;
+                self.repo.set_head(&format!("refs/heads/{}", local))
+            }
+            (_, None) => self.repo.set_head(&target),
+        }
+    }
+
+    /// Create a branch at HEAD and switch to it (like `git switch -c`)
+    pub fn create_branch(&mut self, name: &str) -> Result<(), git2::Error> {
+        let commit = self.repo.head()?.peel_to_commit()?;
+        self.repo.branch(name, &commit, false)?;
+        self.repo.set_head(&format!("refs/heads/{}", name))
+    }
+
+    /// Stash the changes of the working tree and of the index (like `git stash`),
+    /// the working tree is then reset to HEAD
+    pub fn stash_save(&mut self) -> Result<(), git2::Error> {
+        let signature = self.repo.signature()?;
+        self.repo.stash_save2(&signature, None, None)?;
+        Ok(())
+    }
+
+    /// The stash list, most recent first
+    pub fn stashes(&mut self) -> Result<Vec<Stash>, git2::Error> {
+        let mut stashes = vec![];
+        self.repo.stash_foreach(|index, message, _| {
+            stashes.push(Stash {
+                index,
+                message: message.into(),
+            });
+            true
+        })?;
+        Ok(stashes)
+    }
+
+    /// Changes of the working tree recorded by a stash, as a unified diff
+    /// (like `git stash show -p`)
+    pub fn stash_diff(&mut self, index: usize) -> Result<Vec<String>, git2::Error> {
+        let mut id = None;
+        self.repo.stash_foreach(|i, _, oid| {
+            if i == index {
+                id = Some(*oid);
+            }
+            id.is_none()
+        })?;
+        let id = id.ok_or_else(|| git2::Error::from_str("no such stash"))?;
+        let commit = self.repo.find_commit(id)?;
+        let base = commit.parent(0)?.tree()?;
+        let diff = self
+            .repo
+            .diff_tree_to_tree(Some(&base), Some(&commit.tree()?), None)?;
+        let mut lines = vec![];
+        diff.print(DiffFormat::Patch, |_, _, line| {
+            let content = String::from_utf8_lossy(line.content());
+            let content = content.trim_end_matches('\n');
+            lines.push(match line.origin() {
+                origin @ ('+' | '-' | ' ') => format!("{}{}", origin, content),
+                _ => content.to_string(),
+            });
+            true
+        })?;
+        Ok(lines)
+    }
+
+    /// Apply a stash and remove it from the list (like `git stash pop`). Like a
+    /// checkout, it is refused if it would overwrite local changes.
+    pub fn stash_pop(&mut self, index: usize) -> Result<(), git2::Error> {
+        self.repo.stash_pop(index, None)
+    }
+}
+
+/// Clean up a commit message like git does by default: comment lines and trailing
+/// whitespaces are removed, consecutive empty lines are collapsed and the leading
+/// and trailing empty lines are removed. An empty result means the commit is aborted.
+pub fn cleanup_message(message: &str) -> String {
+    let mut lines: Vec<&str> = vec![];
+    for line in message.lines().filter(|line| !line.starts_with('#')) {
+        let line = line.trim_end();
+        if !line.is_empty() || lines.last().is_some_and(|last| !last.is_empty()) {
+            lines.push(line);
+        }
+    }
+    while lines.last() == Some(&"") {
+        lines.pop();
+    }
+    match lines.is_empty() {
+        true => String::new(),
+        false => lines.join("\n") + "\n",
+    }
+}
+
+/// Compute the hunks of the diff between two buffers (without context lines)
+pub(super) fn diff_hunks(old: &str, new: &str) -> Result<Vec<RawHunk>, git2::Error> {
+    let mut options = DiffOptions::default();
+    options.context_lines(0);
+
+    let patch = GitPatch::from_buffers(
+        old.as_bytes(),
+        None,
+        new.as_bytes(),
+        None,
+        Some(&mut options),
+    )?;
+
+    (0..patch.num_hunks())
+        .map(|num| {
+            let (hunk, lines) = patch.hunk(num)?;
+            let old_text = (0..lines)
+                .map(|line| patch.line_in_hunk(num, line))
+                .filter(|line| matches!(line, Ok(line) if line.origin() == '-'))
+                .map(|line| Ok(String::from_utf8_lossy(line?.content()).to_string()))
+                .collect::<Result<_, git2::Error>>()?;
+            Ok(RawHunk {
+                old_start: hunk.old_start() as usize,
+                old_lines: hunk.old_lines() as usize,
+                new_start: hunk.new_start() as usize,
+                new_lines: hunk.new_lines() as usize,
+                old_text,
+            })
+        })
+        .collect()
+}
+
+/// Lines of the old buffer replaced by lines of the new buffer (0-indexed)
+pub type LineChange = (Range<usize>, Range<usize>);
+
+/// Hunks of the diff between two buffers, as if `new` was the buffer and `old` its base
+pub fn diff_buffers(old: &str, new: &str) -> Result<Vec<Hunk>, git2::Error> {
+    Ok(hunks(&diff_hunks(old, new)?, false))
+}
+
+/// Lines (0-indexed) changed between two buffers: for each hunk, the lines of the
+/// old buffer and the lines of the new buffer that replace them
+pub fn changed_lines(old: &str, new: &str) -> Result<Vec<LineChange>, git2::Error> {
+    Ok(diff_hunks(old, new)?
+        .iter()
+        .map(|hunk| (hunk.old_range(), hunk.new_range()))
+        .collect())
+}
+
+/// Convert raw hunks to hunks of the buffer
+pub(super) fn hunks(hunks: &[RawHunk], staged: bool) -> Vec<Hunk> {
+    hunks
+        .iter()
+        .map(|hunk| Hunk::from_raw(hunk, staged))
+        .collect()
+}
+
+/// Markers of the gutter for the given hunks. Unstaged hunks come last so they take
+/// precedence over staged ones.
+pub(super) fn markers(hunks: &[Hunk]) -> HashMap<usize, Marker> {
+    let mut marks = HashMap::new();
+    for patch in hunks.iter().map(Patch::from) {
+        let marker = Marker {
+            patch_type: patch.patch_type,
+            staged: patch.staged,
+        };
+        for i in 0..patch.count {
+            marks.insert(patch.start + i, marker);
+        }
+    }
+    marks
+}
+
+/// Map a (0-indexed) line of the old buffer of a diff to the corresponding line of
+/// the new buffer, by shifting it by the size difference of the hunks above it.
+fn map_line(line: usize, hunks: &[RawHunk]) -> usize {
+    let line = line + 1;
+    let offset = hunks
+        .iter()
+        .filter(|hunk| {
+            if hunk.old_lines == 0 {
+                // Lines are inserted after old_start
+                hunk.old_start < line
+            } else {
+                hunk.old_start + hunk.old_lines <= line
+            }
+        })
+        .map(|hunk| hunk.new_lines as isize - hunk.old_lines as isize)
+        .sum::<isize>();
+    (line as isize + offset - 1).max(0) as usize
+}
+
+/// Blame the `len` lines of a buffer from the blame of the lines of HEAD and the
+/// diff between HEAD and the buffer: lines added or changed by the diff are `None`.
+fn blame_lines(head_blame: &[BlameLine], hunks: &[RawHunk], len: usize) -> Vec<Option<BlameLine>> {
+    let mut blame = Vec::with_capacity(len);
+    let mut head_line = 0;
+    for hunk in hunks {
+        // Unchanged lines above the hunk
+        while blame.len() < hunk.new_range().start {
+            blame.push(head_blame.get(head_line).cloned());
+            head_line += 1;
+        }
+        blame.extend(std::iter::repeat_n(None, hunk.new_lines));
+        head_line = hunk.old_range().end;
+    }
+    while blame.len() < len {
+        blame.push(head_blame.get(head_line).cloned());
+        head_line += 1;
+    }
+    blame
+}
+
+/// Human readable age of a date (seconds since the epoch): `3 days ago`
+fn relative_date(time: i64) -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.as_secs() as i64)
+        .unwrap_or(time);
+    let seconds = (now - time).max(0);
+    let (count, unit) = match seconds {
+        s if s < 60 => return "just now".into(),
+        s if s < 3600 => (s / 60, "minute"),
+        s if s < 86400 => (s / 3600, "hour"),
+        s if s < 30 * 86400 => (s / 86400, "day"),
+        s if s < 365 * 86400 => (s / (30 * 86400), "month"),
+        s => (s / (365 * 86400), "year"),
+    };
+    let plural = if count > 1 { "s" } else { "" };
+    format!("{} {}{} ago", count, unit, plural)
+}
+
+/// Apply to `old` the changes of the diff between `old` and `new` that are on the
+/// selected lines of `new` (0-indexed). A hunk whose new lines are all selected is
+/// applied entirely (a pure deletion is selected through the line below it),
+/// otherwise only its selected new lines are added, its old lines are kept.
+fn apply_lines(
+    old: &str,
+    new: &str,
+    selected: impl Fn(usize) -> bool,
+) -> Result<String, git2::Error> {
+    let old_lines = old.split_inclusive('\n').collect::<Vec<_>>();
+    let new_lines = new.split_inclusive('\n').collect::<Vec<_>>();
+
+    let mut result = String::new();
+    let mut old_pos = 0;
+    for hunk in diff_hunks(old, new)? {
+        let (old_range, new_range) = (hunk.old_range(), hunk.new_range());
+
+        result.push_str(&old_lines[old_pos..old_range.start].concat());
+        let whole = match hunk.new_lines {
+            0 => selected(new_range.start),
+            _ => new_range.clone().all(&selected),
+        };
+        if whole {
+            result.push_str(&new_lines[new_range].concat());
+        } else {
+            result.push_str(&old_lines[old_range.clone()].concat());
+            result.extend(
+                new_range
+                    .filter(|line| selected(*line))
+                    .map(|line| new_lines[line]),
+            );
+        }
+        old_pos = old_range.end;
+    }
+    result.push_str(&old_lines[old_pos..].concat());
+    Ok(result)
+}
+
+mod tests {
+    #[cfg(test)]
+    use super::*;
+    #[cfg(test)]
+    use crate::editor::test_utils::{temp_dir, TempDir};
+
+    /// Create an empty git repository in the temporary directory of the system
+    #[cfg(test)]
+    fn temp_repo(name: &str) -> (TempDir, git2::Repository) {
+        let dir = temp_dir(name);
+        let repo = git2::Repository::init(&*dir).unwrap();
+        (dir, repo)
+    }
+
+    /// Get the patches displayed in the gutter for the given content
+    #[cfg(test)]
+    fn get_diff_result(
+        git: &mut Git,
+        content: &str,
+        file_path: &str,
+        file_name: &str,
+    ) -> Result<Vec<Patch>, Box<dyn std::error::Error>> {
+        let hunks = git.get_hunks(content, file_path, file_name)?;
+        Ok(hunks.iter().map(Patch::from).collect())
+    }
+
+    /// Write a file in the repository and add it to the index
+    #[cfg(test)]
+    fn stage_file(repo: &git2::Repository, name: &str, content: &str) {
+        let path = repo.workdir().unwrap().join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new(name)).unwrap();
+        index.write().unwrap();
+    }
+
+    /// Write a file in the repository and commit it on HEAD
+    #[cfg(test)]
+    fn commit_file(repo: &git2::Repository, name: &str, content: &str) {
+        let path = repo.workdir().unwrap().join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new(name)).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = git2::Signature::now("giga", "giga@example.com").unwrap();
+        let parent = repo.head().ok().and_then(|head| head.peel_to_commit().ok());
+        let parents = parent.iter().collect::<Vec<_>>();
+        repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            "commit",
+            &tree,
+            &parents,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_get_file_content() {
+        let file_path = "./tests/";
+
+        // sample.txt is a file in the tests directory that must never change
+        let file_name = "sample.txt";
+        let mut git = Git::open(file_path).unwrap();
+        let content = git.get_file_content(file_path, file_name).unwrap().unwrap();
+        let fs_content = std::fs::read_to_string(file_path.to_string() + file_name).unwrap();
+        assert_eq!(content, fs_content);
+    }
+
+    #[test]
+    fn test_get_file_content_other_repo() {
+        let (dir, repo) = temp_repo("git-other-repo");
+        commit_file(&repo, "src/a.txt", "content of a\n");
+
+        // The process runs in another repository (giga's)
+        let file_dir = dir.join("src").to_str().unwrap().to_string() + "/";
+        let mut git = Git::open(&file_dir).unwrap();
+        assert_eq!(
+            git.get_file_content(&file_dir, "a.txt").unwrap(),
+            Some("content of a\n".to_string())
+        );
+        assert_eq!(git.get_file_content(&file_dir, "b.txt").unwrap(), None);
+    }
+
+    #[test]
+    fn test_open_missing_directory() {
+        let (dir, _repo) = temp_repo("git-missing-dir");
+        let git = Git::open(dir.join("does/not/exist").to_str().unwrap()).unwrap();
+        assert_eq!(
+            fs::canonicalize(git.repo.workdir().unwrap()).unwrap(),
+            fs::canonicalize(&dir).unwrap()
+        );
+    }
+
+    #[test]
     fn test_get_diff_result_new_file() {
         let content = "Hello, World !";
         let file_path = "./tests/";
         let file_name = "new_file.txt";
 
         let mut git = Git::open(file_path).unwrap();
-        let patches = git.get_diff_result(content, file_path, file_name).unwrap();
+        let patches = get_diff_result(&mut git, content, file_path, file_name).unwrap();
         assert_eq!(
             patches[0],
             Patch {
                 start: 0,
                 count: 1,
-                patch_type: PatchType::Added
+                patch_type: PatchType::Added,
+                staged: false,
             }
         );
     }
+
+    #[test]
+    fn test_move_file_tracked() {
+        let (dir, repo) = temp_repo("git-move");
+        fs::write(dir.join("a.txt"), "a").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("a.txt")).unwrap();
+        index.write().unwrap();
+
+        let mut git = Git::open(dir.to_str().unwrap()).unwrap();
+        fs::create_dir(dir.join("sub")).unwrap();
+        git.move_file(&dir.join("a.txt"), &dir.join("sub/b.txt"))
+            .unwrap();
+
+        let mut index = repo.index().unwrap();
+        index.read(true).unwrap();
+        assert!(index.get_path(Path::new("a.txt"), 0).is_none());
+        assert!(index.get_path(Path::new("sub/b.txt"), 0).is_some());
+        assert!(dir.join("sub/b.txt").is_file());
+    }
+
+    #[test]
+    fn test_move_file_keeps_unstaged_changes() {
+        let (dir, repo) = temp_repo("git-move-unstaged");
+        commit_file(&repo, "a.txt", "a\n");
+        fs::write(dir.join("a.txt"), "a\nb\n").unwrap();
+
+        let mut git = Git::open(dir.to_str().unwrap()).unwrap();
+        git.move_file(&dir.join("a.txt"), &dir.join("b.txt"))
+            .unwrap();
+
+        // The staged content is the committed one, the edit is still unstaged
+        let mut index = repo.index().unwrap();
+        index.read(true).unwrap();
+        let entry = index.get_path(Path::new("b.txt"), 0).unwrap();
+        assert_eq!(repo.find_blob(entry.id).unwrap().content(), b"a\n");
+        assert_eq!(entry.mode, 0o100644);
+        let status = repo.status_file(Path::new("b.txt")).unwrap();
+        assert!(status.contains(Status::WT_MODIFIED));
+    }
+
+    #[test]
+    fn test_move_file_untracked() {
+        let (dir, repo) = temp_repo("git-move-untracked");
+        fs::write(dir.join("a.txt"), "a").unwrap();
+
+        let mut git = Git::open(dir.to_str().unwrap()).unwrap();
+        git.move_file(&dir.join("a.txt"), &dir.join("b.txt"))
+            .unwrap();
+
+        assert!(repo.index().unwrap().is_empty());
+        assert!(dir.join("b.txt").is_file());
+    }
+
+    #[test]
+    fn test_unborn_branch() {
+        let (dir, repo) = temp_repo("git-unborn");
+        repo.set_head("refs/heads/trunk").unwrap();
+        let dir_str = dir.to_str().unwrap().to_string() + "/";
+
+        let mut git = Git::open(&dir_str).unwrap();
+        assert_eq!(git.get_ref().unwrap(), "trunk");
+        assert_eq!(git.get_file_content(&dir_str, "a.txt").unwrap(), None);
+        let patches = get_diff_result(&mut git, "a\nb\n", &dir_str, "a.txt").unwrap();
+        assert_eq!(
+            patches,
+            vec![Patch {
+                start: 0,
+                count: 2,
+                patch_type: PatchType::Added,
+                staged: false,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_bare_repository() {
+        let dir = temp_dir("git-bare");
+        git2::Repository::init_bare(&*dir).unwrap();
+
+        assert!(Git::open(dir.to_str().unwrap()).is_none());
+    }
+
+    #[test]
+    fn test_corrupt_repository() {
+        let (dir, repo) = temp_repo("git-corrupt");
+        commit_file(&repo, "a.txt", "a");
+        // HEAD points to a branch whose target does not exist
+        let branch = repo.head().unwrap().name().unwrap().to_string();
+        fs::write(
+            repo.path().join(&branch),
+            "0123456789abcdef0123456789abcdef01234567\n",
+        )
+        .unwrap();
+        let dir_str = dir.to_str().unwrap().to_string() + "/";
+
+        let mut git = Git::open(&dir_str).unwrap();
+        assert!(git.get_file_content(&dir_str, "a.txt").is_err());
+        assert!(get_diff_result(&mut git, "a", &dir_str, "a.txt").is_err());
+    }
+
+    #[test]
+    fn test_diff_staged_and_unstaged() {
+        let (dir, repo) = temp_repo("git-staged");
+        commit_file(&repo, "a.txt", "a\nb\nc\n");
+        stage_file(&repo, "a.txt", "a\nB\nc\n");
+        let dir_str = dir.to_str().unwrap().to_string() + "/";
+
+        let mut git = Git::open(&dir_str).unwrap();
+        git.compute_diff(&dir_str, "a.txt", "new\na\nB\nc\n")
+            .unwrap();
+        let diff = git.diff().unwrap();
+        assert_eq!(
+            diff.get(&0),
+            Some(&Marker {
+                patch_type: PatchType::Added,
+                staged: false
+            })
+        );
+        // The staged change is on the second line of the index, third line of the buffer
+        assert_eq!(
+            diff.get(&2),
+            Some(&Marker {
+                patch_type: PatchType::Changed,
+                staged: true
+            })
+        );
+        assert_eq!(diff.len(), 2);
+
+        // Against HEAD everything is unstaged
+        git.set_diff_base(DiffBase::Head).unwrap();
+        git.compute_diff(&dir_str, "a.txt", "new\na\nB\nc\n")
+            .unwrap();
+        let diff = git.diff().unwrap();
+        assert!(diff.values().all(|marker| !marker.staged));
+    }
+
+    #[test]
+    fn test_diff_against_revision() {
+        let (dir, repo) = temp_repo("git-revision");
+        commit_file(&repo, "a.txt", "a\n");
+        commit_file(&repo, "a.txt", "a\nb\n");
+        let dir_str = dir.to_str().unwrap().to_string() + "/";
+
+        let mut git = Git::open(&dir_str).unwrap();
+        assert!(git.set_diff_base(DiffBase::parse("nope")).is_err());
+        assert_eq!(git.diff_base(), DiffBase::Index);
+
+        assert_eq!(DiffBase::parse("saved"), DiffBase::Saved);
+        git.set_diff_base(DiffBase::parse("HEAD~1")).unwrap();
+        let patches = get_diff_result(&mut git, "a\nb\n", &dir_str, "a.txt").unwrap();
+        assert_eq!(
+            patches,
+            vec![Patch {
+                start: 1,
+                count: 1,
+                patch_type: PatchType::Added,
+                staged: false,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_map_line() {
+        // One line added at the top, one line deleted after the 3rd line
+        let hunks = diff_hunks("a\nb\nc\nd\ne\n", "new\na\nb\nc\ne\n").unwrap();
+        assert_eq!(map_line(0, &hunks), 1);
+        assert_eq!(map_line(2, &hunks), 3);
+        assert_eq!(map_line(4, &hunks), 4);
+    }
+
+    #[test]
+    fn test_hunks_keep_old_lines() {
+        let (dir, repo) = temp_repo("git-hunks");
+        commit_file(&repo, "a.txt", "a\nb\nc\nd\n");
+        let dir_str = dir.to_str().unwrap().to_string() + "/";
+
+        let mut git = Git::open(&dir_str).unwrap();
+        git.compute_diff(&dir_str, "a.txt", "a\nB\nc\n").unwrap();
+        let hunks = git.hunks();
+        assert_eq!(
+            hunks,
+            vec![
+                Hunk {
+                    start: 1,
+                    count: 1,
+                    old_lines: vec!["b\n".to_string()],
+                    staged: false,
+                },
+                Hunk {
+                    start: 3,
+                    count: 0,
+                    old_lines: vec!["d\n".to_string()],
+                    staged: false,
+                }
+            ]
+        );
+        assert_eq!(hunks[0].patch_type(), PatchType::Changed);
+        assert_eq!(hunks[1].patch_type(), PatchType::Deleted);
+        assert!(hunks[0].contains(1));
+        assert!(!hunks[0].contains(2));
+        assert!(hunks[1].contains(3));
+    }
+
+    #[test]
+    fn test_apply_lines() {
+        let old = "a\nb\nc\nd\n";
+        let new = "a\nB\nc\nnew\n";
+        // Everything selected
+        assert_eq!(apply_lines(old, new, |_| true).unwrap(), new);
+        // Nothing selected
+        assert_eq!(apply_lines(old, new, |_| false).unwrap(), old);
+        // Only the first hunk
+        assert_eq!(apply_lines(old, new, |l| l == 1).unwrap(), "a\nB\nc\nd\n");
+        // Partially selected hunk: the old lines are kept
+        let new = "a\nb1\nb2\nc\nd\n";
+        assert_eq!(
+            apply_lines(old, new, |l| l == 2).unwrap(),
+            "a\nb\nb2\nc\nd\n"
+        );
+    }
+
+    #[test]
+    fn test_stage_and_unstage_lines() {
+        let (dir, repo) = temp_repo("git-stage");
+        commit_file(&repo, "a.txt", "a\nb\nc\nd\n");
+        let dir_str = dir.to_str().unwrap().to_string() + "/";
+        let content = "a\nB\nc\nd\nnew\n";
+        fs::write(dir.join("a.txt"), content).unwrap();
+
+        let mut git = Git::open(&dir_str).unwrap();
+        git.stage_lines(content, &dir_str, "a.txt", 4..=4).unwrap();
+        assert_eq!(
+            git.get_index_content(&dir_str, "a.txt").unwrap().unwrap(),
+            "a\nb\nc\nd\nnew\n"
+        );
+        git.stage_lines(content, &dir_str, "a.txt", 1..=1).unwrap();
+        assert_eq!(
+            git.get_index_content(&dir_str, "a.txt").unwrap().unwrap(),
+            content
+        );
+
+        git.unstage_lines(content, &dir_str, "a.txt", 4..=4)
+            .unwrap();
+        assert_eq!(
+            git.get_index_content(&dir_str, "a.txt").unwrap().unwrap(),
+            "a\nB\nc\nd\n"
+        );
+    }
+
+    #[test]
+    fn test_stage_new_file() {
+        let (dir, repo) = temp_repo("git-stage-new");
+        commit_file(&repo, "a.txt", "a\n");
+        let dir_str = dir.to_str().unwrap().to_string() + "/";
+
+        let mut git = Git::open(&dir_str).unwrap();
+        git.stage_lines("new\n", &dir_str, "b.txt", 0..=0).unwrap();
+        assert_eq!(
+            git.get_index_content(&dir_str, "b.txt").unwrap().unwrap(),
+            "new\n"
+        );
+        git.unstage_lines("new\n", &dir_str, "b.txt", 0..=0)
+            .unwrap();
+        assert_eq!(git.get_index_content(&dir_str, "b.txt").unwrap(), None);
+
+        // An executable file is staged with its mode
+        fs::write(dir.join("run.sh"), "echo\n").unwrap();
+        fs::set_permissions(dir.join("run.sh"), fs::Permissions::from_mode(0o755)).unwrap();
+        git.stage_lines("echo\n", &dir_str, "run.sh", 0..=0)
+            .unwrap();
+        let mut index = repo.index().unwrap();
+        index.read(true).unwrap();
+        let entry = index.get_path(Path::new("run.sh"), 0).unwrap();
+        assert_eq!(entry.mode, 0o100755);
+    }
+
+    #[test]
+    fn test_relative_date() {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+        assert_eq!(relative_date(now), "just now");
+        assert_eq!(relative_date(now - 120), "2 minutes ago");
+        assert_eq!(relative_date(now - 3600), "1 hour ago");
+        assert_eq!(relative_date(now - 3 * 86400), "3 days ago");
+        assert_eq!(relative_date(now - 800 * 86400), "2 years ago");
+    }
+
+    #[test]
+    fn test_blame() {
+        let (dir, repo) = temp_repo("git-blame");
+        commit_file(&repo, "a.txt", "a\nb\nc\n");
+        let dir_str = dir.to_str().unwrap().to_string() + "/";
+        let commit = repo.head().unwrap().peel_to_commit().unwrap().id();
+
+        let mut git = Git::open(&dir_str).unwrap();
+        git.compute_diff(&dir_str, "a.txt", "a\nb\nc\n").unwrap();
+        assert_eq!(git.blame(), None);
+
+        git.set_blame(&dir_str, "a.txt", true).unwrap();
+        git.compute_diff(&dir_str, "a.txt", "new\na\nc\n").unwrap();
+        let blame = git.blame().unwrap();
+        assert_eq!(blame.len(), 3);
+        assert_eq!(blame[0], None);
+        assert_eq!(blame[1].as_ref().unwrap().commit, commit);
+        assert_eq!(blame[1].as_ref().unwrap().author, "giga");
+        assert_eq!(blame[2].as_ref().unwrap().commit, commit);
+
+        let message = git.commit_message(commit).unwrap();
+        assert_eq!(message[0], format!("commit {}", commit));
+        assert_eq!(message[4], "    commit");
+
+        git.set_blame(&dir_str, "a.txt", false).unwrap();
+        assert_eq!(git.blame(), None);
+    }
+
+    #[test]
+    fn test_blame_new_file() {
+        let (dir, repo) = temp_repo("git-blame-new");
+        commit_file(&repo, "a.txt", "a\n");
+        let dir_str = dir.to_str().unwrap().to_string() + "/";
+
+        let mut git = Git::open(&dir_str).unwrap();
+        git.set_blame(&dir_str, "b.txt", true).unwrap();
+        git.compute_diff(&dir_str, "b.txt", "new\n").unwrap();
+        assert_eq!(git.blame(), Some(&[None][..]));
+    }
+
+    #[test]
+    fn test_history() {
+        let (dir, repo) = temp_repo("git-history");
+        commit_file(&repo, "a.txt", "v1\n");
+        commit_file(&repo, "b.txt", "other\n");
+        commit_file(&repo, "a.txt", "v2\n");
+        let dir_str = dir.to_str().unwrap().to_string() + "/";
+
+        let git = Git::open(&dir_str).unwrap();
+        let history = git.history(&dir_str, "a.txt").unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].summary, "commit");
+        let old = history[1].commit.to_string();
+        assert_eq!(
+            git.get_revision_content(&old, &dir_str, "a.txt").unwrap(),
+            Some("v1\n".to_string())
+        );
+        assert_eq!(
+            git.get_revision_content(&old, &dir_str, "b.txt").unwrap(),
+            None
+        );
+        assert_eq!(git.history(&dir_str, "c.txt").unwrap(), vec![]);
+    }
+
+    #[test]
+    fn test_changed_lines() {
+        let changes = changed_lines("a\nb\nc\n", "a\nB\nc\nd\n").unwrap();
+        assert_eq!(changes, vec![(1..2, 1..2), (3..3, 3..4)]);
+        let changes = changed_lines("a\nb\nc\n", "c\n").unwrap();
+        assert_eq!(changes, vec![(0..2, 0..0)]);
+    }
+
+    #[test]
+    fn test_detached_head_name() {
+        let (dir, repo) = temp_repo("git-describe");
+        commit_file(&repo, "a.txt", "a\n");
+        let first = repo.head().unwrap().peel_to_commit().unwrap();
+        let git = Git::open(dir.to_str().unwrap()).unwrap();
+
+        repo.set_head_detached(first.id()).unwrap();
+        assert_eq!(git.get_ref().unwrap(), first.id().to_string()[..7]);
+
+        repo.tag_lightweight("v1.0", first.as_object(), false)
+            .unwrap();
+        assert_eq!(git.get_ref().unwrap(), "v1.0");
+
+        commit_file(&repo, "a.txt", "b\n");
+        let second = repo.head().unwrap().peel_to_commit().unwrap();
+        assert_eq!(
+            git.get_ref().unwrap(),
+            format!("v1.0-1-g{}", &second.id().to_string()[..7])
+        );
+    }
+
+    #[test]
+    fn test_repo_status() {
+        let (dir, repo) = temp_repo("git-status");
+        commit_file(&repo, "a.txt", "a\n");
+        let branch = repo.head().unwrap().shorthand().unwrap().to_string();
+        let first = repo.head().unwrap().peel_to_commit().unwrap();
+        let mut git = Git::open(dir.to_str().unwrap()).unwrap();
+
+        git.refresh_status().unwrap();
+        let status = git.status().unwrap();
+        assert_eq!(status.to_string(), branch);
+
+        // Track a branch that is one commit behind and has one commit of its own
+        repo.branch("upstream", &first, false).unwrap();
+        commit_file(&repo, "a.txt", "b\n");
+        repo.set_head("refs/heads/upstream").unwrap();
+        commit_file(&repo, "b.txt", "b\n");
+        repo.set_head(&format!("refs/heads/{}", branch)).unwrap();
+        let mut local = repo.find_branch(&branch, git2::BranchType::Local).unwrap();
+        local.set_upstream(Some("upstream")).unwrap();
+
+        fs::write(dir.join("a.txt"), "c\n").unwrap();
+        stage_file(&repo, "c.txt", "c\n");
+        fs::write(repo.path().join("MERGE_HEAD"), format!("{}\n", first.id())).unwrap();
+        git.refresh_status().unwrap();
+        let status = git.status().unwrap();
+        assert_eq!(status.ahead_behind, Some((1, 1)));
+        assert!(status.unstaged && status.staged);
+        assert_eq!(status.operation.as_deref(), Some("MERGING"));
+        assert_eq!(status.to_string(), format!("{}*+ ↑1↓1 |MERGING", branch));
+    }
+
+    #[test]
+    fn test_worktree() {
+        let (dir, repo) = temp_repo("git-worktree");
+        commit_file(&repo, "a.txt", "a\n");
+        // The worktree is created by git, in the place of the empty directory
+        let path = temp_dir("git-worktree-wt");
+        fs::remove_dir(&*path).unwrap();
+        repo.worktree("feature", &path, None).unwrap();
+        fs::write(path.join("a.txt"), "b\n").unwrap();
+
+        // The worktree has its own HEAD (a branch named after it) and index
+        let path_str = path.to_str().unwrap().to_string() + "/";
+        let mut git = Git::open(&path_str).unwrap();
+        git.refresh_status().unwrap();
+        assert_eq!(
+            git.status().unwrap().to_string(),
+            "worktree feature › feature*"
+        );
+        let patches = get_diff_result(&mut git, "b\n", &path_str, "a.txt").unwrap();
+        assert_eq!(patches.len(), 1);
+
+        // The main working tree is not affected
+        let mut main = Git::open(dir.to_str().unwrap()).unwrap();
+        main.refresh_status().unwrap();
+        assert_eq!(main.status().unwrap().context, None);
+        assert!(!main.status().unwrap().unstaged);
+    }
+
+    #[test]
+    fn test_submodule() {
+        let (dir, repo) = temp_repo("git-submodule");
+        commit_file(&repo, "a.txt", "a\n");
+        let mut submodule = repo
+            .submodule("../lib.git", Path::new("lib"), true)
+            .unwrap();
+        let sub_repo = submodule.open().unwrap();
+        commit_file(&sub_repo, "lib.txt", "lib\n");
+        submodule.add_finalize().unwrap();
+
+        let sub_dir = dir.join("lib").to_str().unwrap().to_string() + "/";
+        let mut git = Git::open(&sub_dir).unwrap();
+        let branch = sub_repo.head().unwrap().shorthand().unwrap().to_string();
+        assert_eq!(git.get_ref().unwrap(), branch);
+        git.refresh_status().unwrap();
+        let name = dir.file_name().unwrap().to_string_lossy().to_string();
+        let super_branch = repo.head().unwrap().shorthand().unwrap().to_string();
+        assert_eq!(
+            git.status().unwrap().context,
+            Some(format!("{}@{}", name, super_branch))
+        );
+
+        // The file is diffed against the tree of the submodule
+        let patches = get_diff_result(&mut git, "lib\n", &sub_dir, "lib.txt").unwrap();
+        assert!(patches.is_empty());
+    }
+
+    #[test]
+    fn test_branches() {
+        let (dir, repo) = temp_repo("git-branches");
+        commit_file(&repo, "a.txt", "a\n");
+        let main = repo.head().unwrap().shorthand().unwrap().to_string();
+        let first = repo.head().unwrap().peel_to_commit().unwrap();
+        repo.tag_lightweight("v1.0", first.as_object(), false)
+            .unwrap();
+        repo.remote("origin", "https://example.com/repo.git")
+            .unwrap();
+        repo.reference("refs/remotes/origin/topic", first.id(), false, "")
+            .unwrap();
+        repo.branch("feature", &first, false).unwrap();
+        commit_file(&repo, "a.txt", "b\n");
+        let mut git = Git::open(dir.to_str().unwrap()).unwrap();
+
+        let refs = git.refs().unwrap();
+        let names = refs.iter().map(GitRef::to_string).collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec![
+                "  feature".to_string(),
+                format!("* {}", main),
+                "  origin/topic (remote)".to_string(),
+                "  v1.0 (tag)".to_string(),
+            ]
+        );
+
+        // The checkout updates the working tree
+        git.checkout(&refs[0]).unwrap();
+        assert_eq!(git.get_ref().unwrap(), "feature");
+        assert_eq!(fs::read_to_string(dir.join("a.txt")).unwrap(), "a\n");
+
+        // A remote branch is checked out in a local branch tracking it
+        git.checkout(&refs[2]).unwrap();
+        assert_eq!(git.get_ref().unwrap(), "topic");
+        let topic = repo.find_branch("topic", BranchType::Local).unwrap();
+        assert_eq!(
+            topic.upstream().unwrap().name().unwrap(),
+            Some("origin/topic")
+        );
+
+        // Local changes that would be overwritten prevent the checkout
+        fs::write(dir.join("a.txt"), "local\n").unwrap();
+        assert!(git.checkout(&refs[1]).is_err());
+        assert_eq!(git.get_ref().unwrap(), "topic");
+        assert_eq!(fs::read_to_string(dir.join("a.txt")).unwrap(), "local\n");
+
+        // A tag detaches HEAD, a new branch starts at HEAD
+        fs::write(dir.join("a.txt"), "a\n").unwrap();
+        git.checkout(&refs[3]).unwrap();
+        assert!(repo.head_detached().unwrap());
+        git.create_branch("fix").unwrap();
+        assert_eq!(git.get_ref().unwrap(), "fix");
+        assert_eq!(repo.head().unwrap().target(), Some(first.id()));
+        assert!(git.create_branch("fix").is_err());
+    }
+
+    #[test]
+    fn test_checkout_remote_with_local_branch() {
+        let (dir, repo) = temp_repo("git-checkout-remote");
+        commit_file(&repo, "a.txt", "a\n");
+        let first = repo.head().unwrap().peel_to_commit().unwrap();
+        repo.branch("feature", &first, false).unwrap();
+        commit_file(&repo, "a.txt", "b\n");
+        let second = repo.head().unwrap().peel_to_commit().unwrap();
+        repo.remote("origin", "https://example.com/repo.git")
+            .unwrap();
+        repo.reference("refs/remotes/origin/feature", second.id(), false, "")
+            .unwrap();
+        let mut git = Git::open(dir.to_str().unwrap()).unwrap();
+
+        // The existing local branch is checked out, with its own commit
+        let remote = GitRef {
+            name: "origin/feature".into(),
+            kind: RefKind::Remote,
+            current: false,
+        };
+        git.checkout(&remote).unwrap();
+        assert_eq!(git.get_ref().unwrap(), "feature");
+        assert_eq!(repo.head().unwrap().target(), Some(first.id()));
+        assert_eq!(fs::read_to_string(dir.join("a.txt")).unwrap(), "a\n");
+        // The index and the working tree match HEAD
+        assert!(repo.statuses(None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_stashes() {
+        let (dir, repo) = temp_repo("git-stashes");
+        let mut config = repo.config().unwrap();
+        config.set_str("user.name", "giga").unwrap();
+        config.set_str("user.email", "giga@example.com").unwrap();
+        commit_file(&repo, "a.txt", "a\n");
+        let mut git = Git::open(dir.to_str().unwrap()).unwrap();
+        assert!(git.stashes().unwrap().is_empty());
+
+        // Stashing restores the committed content
+        fs::write(dir.join("a.txt"), "b\n").unwrap();
+        git.stash_save().unwrap();
+        assert_eq!(fs::read_to_string(dir.join("a.txt")).unwrap(), "a\n");
+        let stashes = git.stashes().unwrap();
+        assert_eq!(stashes.len(), 1);
+        assert!(stashes[0].to_string().starts_with("stash@{0}: WIP on "));
+
+        let diff = git.stash_diff(0).unwrap();
+        assert!(diff.contains(&"-a".to_string()));
+        assert!(diff.contains(&"+b".to_string()));
+        assert!(git.stash_diff(1).is_err());
+
+        // Popping applies the changes and drops the stash
+        git.stash_pop(0).unwrap();
+        assert_eq!(fs::read_to_string(dir.join("a.txt")).unwrap(), "b\n");
+        assert!(git.stashes().unwrap().is_empty());
+        assert!(git.stash_pop(0).is_err());
+    }
+
+    #[test]
+    fn test_file_status() {
+        let (dir, repo) = temp_repo("git-file-status");
+        let dir_str = dir.to_str().unwrap().to_string() + "/";
+        commit_file(&repo, ".gitignore", "*.log\n");
+        stage_file(&repo, "new.txt", "new\n");
+        fs::write(dir.join("untracked.txt"), "a\nb\n").unwrap();
+        fs::write(dir.join("debug.log"), "a\n").unwrap();
+        let mut git = Git::open(&dir_str).unwrap();
+
+        let status = |name| git.get_file_status(&dir_str, name).unwrap();
+        assert_eq!(status(".gitignore"), FileStatus::Tracked);
+        assert_eq!(status("new.txt"), FileStatus::Added);
+        assert_eq!(status("untracked.txt"), FileStatus::Untracked);
+        assert_eq!(status("debug.log"), FileStatus::Ignored);
+
+        // The lines of an untracked file are not marked as added
+        git.compute_diff(&dir_str, "untracked.txt", "a\nb\n")
+            .unwrap();
+        assert_eq!(git.diff(), Some(HashMap::new()));
+        assert_eq!(git.file_status(), Some(FileStatus::Untracked));
+        // Those of a new file are (staged)
+        git.compute_diff(&dir_str, "new.txt", "new\n").unwrap();
+        assert_eq!(git.hunks().len(), 1);
+        assert!(git.hunks()[0].staged);
+    }
+
+    #[test]
+    fn test_commit() {
+        let (dir, repo) = temp_repo("git-commit");
+        let mut config = repo.config().unwrap();
+        config.set_str("user.name", "giga").unwrap();
+        config.set_str("user.email", "giga@example.com").unwrap();
+        let mut git = Git::open(dir.to_str().unwrap()).unwrap();
+        // Nothing staged yet
+        assert!(git.prepare_commit().is_err());
+
+        stage_file(&repo, "a.txt", "a\n");
+        let path = git.prepare_commit().unwrap();
+        assert_eq!(path, repo.path().join("COMMIT_EDITMSG"));
+        let template = fs::read_to_string(&path).unwrap();
+        assert!(template.contains("#\tnew file:   a.txt\n"));
+        // The commit buffer is not diffed against the repository
+        assert!(Git::open(path.to_str().unwrap()).is_none());
+
+        let oid = git.commit("First commit\n").unwrap();
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        assert_eq!(head.id(), oid);
+        assert_eq!(head.message(), Some("First commit\n"));
+        assert_eq!(head.author().name(), Some("giga"));
+        assert_eq!(head.parent_count(), 0);
+
+        stage_file(&repo, "a.txt", "b\n");
+        let oid = git.commit("Second commit\n").unwrap();
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        assert_eq!(head.id(), oid);
+        assert_eq!(head.parent_count(), 1);
+        assert!(git.prepare_commit().is_err());
+    }
+
+    #[test]
+    fn test_cleanup_message() {
+        let message = "\nSummary  \n\n\n\nBody\n# comment\n\n#\n";
+        assert_eq!(cleanup_message(message), "Summary\n\nBody\n");
+        assert_eq!(cleanup_message("# only comments\n\n"), "");
+    }
+
+    #[test]
+    fn test_conflict_versions() {
+        let (dir, repo) = temp_repo("git-conflict");
+        commit_file(&repo, "a.txt", "base\n");
+        let base = repo.head().unwrap().peel_to_commit().unwrap();
+        commit_file(&repo, "a.txt", "ours\n");
+        let dir_str = dir.to_str().unwrap().to_string() + "/";
+
+        // Their commit, on top of the base
+        let blob = repo.blob(b"theirs\n").unwrap();
+        let mut tree = repo.treebuilder(None).unwrap();
+        tree.insert("a.txt", blob, 0o100644).unwrap();
+        let tree = repo.find_tree(tree.write().unwrap()).unwrap();
+        let signature = git2::Signature::now("giga", "giga@example.com").unwrap();
+        let theirs = repo
+            .commit(None, &signature, &signature, "theirs", &tree, &[&base])
+            .unwrap();
+
+        let git = Git::open(&dir_str).unwrap();
+        assert_eq!(git.conflict_versions(&dir_str, "a.txt").unwrap(), None);
+
+        let theirs = repo.find_annotated_commit(theirs).unwrap();
+        repo.merge(&[&theirs], None, None).unwrap();
+        assert_eq!(
+            git.conflict_versions(&dir_str, "a.txt").unwrap(),
+            Some(ConflictVersions {
+                base: Some("base\n".into()),
+                ours: Some("ours\n".into()),
+                theirs: Some("theirs\n".into()),
+            })
+        );
+    }
 }
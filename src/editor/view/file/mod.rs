@@ -6,12 +6,28 @@
 //! - Read operations: they are used to display the file on the screen
 //! - Write operations: they are used to modify the file -> Trigger a recolorization of the file
 pub mod git;
-
-use std::collections::HashMap;
-
+pub mod saved;
+
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    fs,
+    ops::RangeInclusive,
+    path::Path,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use git2::Oid;
 use ropey::Rope;
 
-use self::git::{Git, PatchType, Vcs};
+use self::git::{
+    BlameLine, ConflictVersions, DiffBase, DiffResult, FileStatus, Git, GitRef, Hunk, Marker,
+    Revision, Stash, Vcs,
+};
+use self::saved::SavedFile;
+
+/// Number of groups of edits that can be undone (the oldest snapshots are dropped)
+const UNDO_LIMIT: usize = 100;
 
 /// In-memory representation of a syntax-highlighted file
 pub struct File {
@@ -24,13 +40,78 @@ pub struct File {
     /// The content of the file
     content: Rope,
 
-    /// Optional version control system
-    vcs: Option<Git>,
+    /// What the buffer is compared to: the git repository containing the file, or
+    /// the file saved on disk outside of a repository (`None` for special buffers)
+    vcs: Option<Box<dyn Vcs + Send>>,
+
+    /// Whether the content changed since the file was opened or saved
+    modified: bool,
+
+    /// Changed by every edit (to know whether a diff is outdated), unique among all
+    /// the files so that the diff of a file is never applied to another one
+    revision: usize,
+
+    /// Snapshots of the content taken before each group of edits (for undo)
+    undo_stack: Vec<Rope>,
+}
+
+/// A copy of the buffer (cheap to take, the rope is shared) used to compute its diff
+/// without locking the view
+pub struct Snapshot {
+    pub file_dir: String,
+    pub file_name: String,
+    pub content: Rope,
+    /// Revision of the buffer when the snapshot was taken
+    pub revision: usize,
+    pub base: DiffBase,
+    /// Whether the blame annotations are displayed (they follow the edits)
+    pub blame: bool,
+}
+
+impl File {
+    /// The content of a file as it is loaded in the buffer: tabs are replaced with
+    /// 4 spaces
+    pub fn expand_tabs(content: &str) -> String {
+        content.replace('\t', "    ")
+    }
+
+    /// The git repository of the file, for the operations that only make sense in one
+    fn git(&self) -> Result<&Git, Box<dyn std::error::Error>> {
+        let git = self.vcs.as_ref().and_then(|vcs| vcs.as_git());
+        git.ok_or_else(|| git::not_in_repository().into())
+    }
+
+    fn git_mut(&mut self) -> Result<&mut Git, Box<dyn std::error::Error>> {
+        let git = self.vcs.as_mut().and_then(|vcs| vcs.as_git_mut());
+        git.ok_or_else(|| git::not_in_repository().into())
+    }
+
+    /// Record an edit of the content
+    fn touch(&mut self) {
+        self.modified = true;
+        self.revision = next_revision();
+    }
+}
+
+/// A new revision number, never used before
+fn next_revision() -> usize {
+    static REVISION: AtomicUsize = AtomicUsize::new(0);
+    REVISION.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Open what a file of the given directory is compared to: its git repository,
+/// or the file saved on disk outside of a repository
+pub fn open_vcs(file_dir: &str) -> Box<dyn Vcs + Send> {
+    match Git::open(file_dir) {
+        Some(git) => Box::new(git),
+        None => Box::new(SavedFile::default()),
+    }
 }
 
 pub trait EditorFile {
     fn new(file_path: &str) -> Self;
     fn from_string(content: &str, file_name: &str, file_path: &str) -> Self;
+    fn scratch(content: &str, name: &str) -> Self;
     fn line(&self, index: usize) -> Option<Vec<char>>;
     fn len(&self) -> usize;
     fn insert(&mut self, line: usize, col: usize, c: char);
@@ -39,7 +120,43 @@ pub trait EditorFile {
     fn delete_line(&mut self, line: usize);
     fn git_ref(&self) -> Option<String>;
     fn refresh_diff(&mut self) -> Result<(), Box<dyn std::error::Error>>;
-    fn diff(&self) -> Option<HashMap<usize, PatchType>>;
+    fn snapshot(&self) -> Option<Snapshot>;
+    fn apply_diff(&mut self, revision: usize, result: DiffResult) -> bool;
+    fn file_status(&self) -> Option<FileStatus>;
+    fn diff(&self) -> Option<HashMap<usize, Marker>>;
+    fn hunks(&self) -> Vec<Hunk>;
+    fn replace_lines(&mut self, start: usize, count: usize, text: &str);
+    fn checkpoint(&mut self);
+    fn undo(&mut self) -> bool;
+    fn diff_base(&self) -> Option<DiffBase>;
+    fn set_diff_base(&mut self, base: DiffBase) -> Result<(), Box<dyn std::error::Error>>;
+    fn stage_lines(
+        &mut self,
+        lines: RangeInclusive<usize>,
+    ) -> Result<(), Box<dyn std::error::Error>>;
+    fn unstage_lines(
+        &mut self,
+        lines: RangeInclusive<usize>,
+    ) -> Result<(), Box<dyn std::error::Error>>;
+    fn toggle_blame(&mut self) -> Result<bool, Box<dyn std::error::Error>>;
+    fn blame(&self) -> Option<&[Option<BlameLine>]>;
+    fn commit_message(&self, commit: Oid) -> Result<Vec<String>, Box<dyn std::error::Error>>;
+    fn history(&self) -> Result<Vec<Revision>, Box<dyn std::error::Error>>;
+    fn revision_content(&self, rev: &str) -> Result<Option<String>, Box<dyn std::error::Error>>;
+    fn conflict_versions(&self) -> Result<Option<ConflictVersions>, Box<dyn std::error::Error>>;
+    fn prepare_commit(&self) -> Result<String, Box<dyn std::error::Error>>;
+    fn commit(&mut self, message: &str) -> Result<Oid, Box<dyn std::error::Error>>;
+    fn refs(&self) -> Result<Vec<GitRef>, Box<dyn std::error::Error>>;
+    fn checkout(&mut self, git_ref: &GitRef) -> Result<(), Box<dyn std::error::Error>>;
+    fn create_branch(&mut self, name: &str) -> Result<(), Box<dyn std::error::Error>>;
+    fn stash_save(&mut self) -> Result<(), Box<dyn std::error::Error>>;
+    fn stashes(&mut self) -> Result<Vec<Stash>, Box<dyn std::error::Error>>;
+    fn stash_diff(&mut self, index: usize) -> Result<Vec<String>, Box<dyn std::error::Error>>;
+    fn stash_pop(&mut self, index: usize) -> Result<(), Box<dyn std::error::Error>>;
+    fn is_modified(&self) -> bool;
+    fn mark_saved(&mut self);
+    fn rename(&mut self, file_dir: &str, file_name: &str)
+        -> Result<(), Box<dyn std::error::Error>>;
 }
 
 impl EditorFile for File {
@@ -48,20 +165,39 @@ impl EditorFile for File {
             file_dir: file_path.into(),
             file_name: "New file".to_string(),
             content: Rope::new(),
-            vcs: Git::open("."),
+            vcs: Some(open_vcs(file_path)),
+            modified: false,
+            revision: next_revision(),
+            undo_stack: vec![],
         }
     }
 
     /// Create a File abstraction from a string
     fn from_string(content: &str, file_name: &str, file_dir: &str) -> Self {
-        // Replace tabs with 4 spaces
-        let content = content.replace('\t', "    ");
-        let content = Rope::from_str(&content);
+        let content = Rope::from_str(&Self::expand_tabs(content));
         Self {
             file_dir: file_dir.into(),
             file_name: file_name.into(),
             content,
-            vcs: Git::open("."),
+            // The repository is discovered from the location of the file
+            vcs: Some(open_vcs(file_dir)),
+            modified: false,
+            revision: next_revision(),
+            undo_stack: vec![],
+        }
+    }
+
+    /// Create a File that is not backed by a file on disk nor by a vcs
+    /// (used for special buffers such as the file explorer)
+    fn scratch(content: &str, name: &str) -> Self {
+        Self {
+            file_dir: String::new(),
+            file_name: name.into(),
+            content: Rope::from_str(content),
+            vcs: None,
+            modified: false,
+            revision: next_revision(),
+            undo_stack: vec![],
         }
     }
 
@@ -100,6 +236,7 @@ impl EditorFile for File {
         }
         let char_idx = self.content.line_to_char(line) + col;
         self.content.insert_char(char_idx, c);
+        self.touch();
     }
 
     /// Delete a char at the given position
@@ -118,10 +255,12 @@ impl EditorFile for File {
                 let line = self.content.line_to_char(line);
                 // Remove the newline character
                 self.content.remove(line - 1..line);
+                self.touch();
             }
         } else if col <= line_len {
             let char_idx = self.content.line_to_char(line) + col - 1;
             self.content.remove(char_idx..char_idx + 1);
+            self.touch();
         }
     }
 
@@ -139,23 +278,22 @@ impl EditorFile for File {
         }
         let char_idx = self.content.line_to_char(line) + col;
         self.content.insert_char(char_idx, '\n');
+        self.touch();
     }
 
     fn delete_line(&mut self, line: usize) {
         let start_line = self.content.line_to_char(line);
         let end_line = self.content.line_to_char(line + 1);
         self.content.remove(start_line..end_line);
+        self.touch();
     }
 
     fn git_ref(&self) -> Option<String> {
-        match &self.vcs {
-            Some(vcs) => Some(vcs.get_ref()),
-            None => None,
-        }
+        self.git().ok()?.get_ref().ok()
     }
 
     fn refresh_diff(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        if let None = &self.vcs {
+        if self.vcs.is_none() {
             return Ok(());
         }
         let content = self.to_string();
@@ -163,17 +301,243 @@ impl EditorFile for File {
         vcs.compute_diff(&self.file_dir, &self.file_name, &content)
     }
 
-    fn diff(&self) -> Option<HashMap<usize, PatchType>> {
+    /// Copy of the buffer to compute its diff in the background, `None` for the
+    /// special buffers
+    fn snapshot(&self) -> Option<Snapshot> {
+        let vcs = self.vcs.as_ref()?;
+        Some(Snapshot {
+            file_dir: self.file_dir.clone(),
+            file_name: self.file_name.clone(),
+            content: self.content.clone(),
+            revision: self.revision,
+            base: vcs.diff_base(),
+            blame: vcs.as_git().is_some_and(Git::blame_enabled),
+        })
+    }
+
+    /// Store a diff computed on a snapshot of the buffer, unless the buffer has
+    /// been edited since then. Returns whether the diff was stored.
+    fn apply_diff(&mut self, revision: usize, result: DiffResult) -> bool {
+        if revision != self.revision {
+            return false;
+        }
+        match &mut self.vcs {
+            Some(vcs) => {
+                vcs.apply_diff(result);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn file_status(&self) -> Option<FileStatus> {
+        self.git().ok()?.file_status()
+    }
+
+    fn diff(&self) -> Option<HashMap<usize, Marker>> {
         let vcs = self.vcs.as_ref()?;
 
         vcs.diff()
     }
+
+    fn hunks(&self) -> Vec<Hunk> {
+        match &self.vcs {
+            Some(vcs) => vcs.hunks(),
+            None => vec![],
+        }
+    }
+
+    /// Replace `count` lines starting at `start` with the given text
+    /// (which should end with a new line unless it is empty)
+    fn replace_lines(&mut self, start: usize, count: usize, text: &str) {
+        let len = self.content.len_lines();
+        if start > len {
+            return;
+        }
+        let start_char = self.content.line_to_char(start);
+        let end_char = self.content.line_to_char((start + count).min(len));
+        self.content.remove(start_char..end_char);
+        self.content.insert(start_char, text);
+        self.touch();
+    }
+
+    /// Save the current content so that the next edits can be undone at once
+    fn checkpoint(&mut self) {
+        if self.undo_stack.last() != Some(&self.content) {
+            if self.undo_stack.len() == UNDO_LIMIT {
+                self.undo_stack.remove(0);
+            }
+            self.undo_stack.push(self.content.clone());
+        }
+    }
+
+    /// Restore the content saved by the last checkpoint.
+    /// Returns false if there is nothing to undo.
+    fn undo(&mut self) -> bool {
+        // Skip the snapshots identical to the current content (no edit since then)
+        while let Some(snapshot) = self.undo_stack.pop() {
+            if snapshot != self.content {
+                self.content = snapshot;
+                self.touch();
+                return true;
+            }
+        }
+        false
+    }
+
+    fn diff_base(&self) -> Option<DiffBase> {
+        self.vcs.as_ref().map(|vcs| vcs.diff_base())
+    }
+
+    fn set_diff_base(&mut self, base: DiffBase) -> Result<(), Box<dyn std::error::Error>> {
+        match &mut self.vcs {
+            Some(vcs) => Ok(vcs.set_diff_base(base)?),
+            None => Err(git::not_in_repository().into()),
+        }
+    }
+
+    /// Stage the changes on the given lines and refresh the diff
+    fn stage_lines(
+        &mut self,
+        lines: RangeInclusive<usize>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let content = self.to_string();
+        let (file_dir, file_name) = (self.file_dir.clone(), self.file_name.clone());
+        let git = self.git_mut()?;
+        git.stage_lines(&content, &file_dir, &file_name, lines)?;
+        git.compute_diff(&file_dir, &file_name, &content)
+    }
+
+    /// Unstage the staged changes on the given lines and refresh the diff
+    fn unstage_lines(
+        &mut self,
+        lines: RangeInclusive<usize>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let content = self.to_string();
+        let (file_dir, file_name) = (self.file_dir.clone(), self.file_name.clone());
+        let git = self.git_mut()?;
+        git.unstage_lines(&content, &file_dir, &file_name, lines)?;
+        git.compute_diff(&file_dir, &file_name, &content)
+    }
+
+    /// Enable or disable the blame annotations, returns whether they are enabled
+    fn toggle_blame(&mut self) -> Result<bool, Box<dyn std::error::Error>> {
+        let content = self.to_string();
+        let (file_dir, file_name) = (self.file_dir.clone(), self.file_name.clone());
+        let git = self.git_mut()?;
+        let enabled = git.blame().is_none();
+        git.set_blame(&file_dir, &file_name, enabled)?;
+        git.compute_diff(&file_dir, &file_name, &content)?;
+        Ok(enabled)
+    }
+
+    fn blame(&self) -> Option<&[Option<BlameLine>]> {
+        self.git().ok()?.blame()
+    }
+
+    fn commit_message(&self, commit: Oid) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        Ok(self.git()?.commit_message(commit)?)
+    }
+
+    fn history(&self) -> Result<Vec<Revision>, Box<dyn std::error::Error>> {
+        Ok(self.git()?.history(&self.file_dir, &self.file_name)?)
+    }
+
+    /// Content of the file at the given revision, `None` if it did not exist
+    fn revision_content(&self, rev: &str) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        Ok(self
+            .git()?
+            .get_revision_content(rev, &self.file_dir, &self.file_name)?)
+    }
+
+    fn conflict_versions(&self) -> Result<Option<ConflictVersions>, Box<dyn std::error::Error>> {
+        Ok(self
+            .git()?
+            .conflict_versions(&self.file_dir, &self.file_name)?)
+    }
+
+    /// Prepare the commit message buffer, returns its path
+    fn prepare_commit(&self) -> Result<String, Box<dyn std::error::Error>> {
+        let path = self.git()?.prepare_commit()?;
+        Ok(path.to_string_lossy().to_string())
+    }
+
+    fn commit(&mut self, message: &str) -> Result<Oid, Box<dyn std::error::Error>> {
+        Ok(self.git_mut()?.commit(message)?)
+    }
+
+    fn refs(&self) -> Result<Vec<GitRef>, Box<dyn std::error::Error>> {
+        Ok(self.git()?.refs()?)
+    }
+
+    /// Check out a branch or a tag, the working tree is updated on disk (the buffer
+    /// is not reloaded)
+    fn checkout(&mut self, git_ref: &GitRef) -> Result<(), Box<dyn std::error::Error>> {
+        Ok(self.git_mut()?.checkout(git_ref)?)
+    }
+
+    fn create_branch(&mut self, name: &str) -> Result<(), Box<dyn std::error::Error>> {
+        Ok(self.git_mut()?.create_branch(name)?)
+    }
+
+    /// Stash the changes saved on disk (the buffer is not reloaded)
+    fn stash_save(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        Ok(self.git_mut()?.stash_save()?)
+    }
+
+    fn stashes(&mut self) -> Result<Vec<Stash>, Box<dyn std::error::Error>> {
+        Ok(self.git_mut()?.stashes()?)
+    }
+
+    fn stash_diff(&mut self, index: usize) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        Ok(self.git_mut()?.stash_diff(index)?)
+    }
+
+    fn stash_pop(&mut self, index: usize) -> Result<(), Box<dyn std::error::Error>> {
+        Ok(self.git_mut()?.stash_pop(index)?)
+    }
+
+    fn is_modified(&self) -> bool {
+        self.modified
+    }
+
+    fn mark_saved(&mut self) {
+        self.modified = false;
+    }
+
+    /// Rename (or move) the file on disk, creating the missing parent directories.
+    /// If the file is tracked by git, the index is updated as well (like `git mv`).
+    /// A file that was never saved is only renamed in memory.
+    fn rename(
+        &mut self,
+        file_dir: &str,
+        file_name: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let old_path = Path::new(&self.file_dir).join(&self.file_name);
+        let new_path = Path::new(file_dir).join(file_name);
+
+        if old_path.exists() {
+            fs::create_dir_all(file_dir)?;
+            match self.git_mut() {
+                Ok(git) => git.move_file(&old_path, &new_path)?,
+                Err(_) => fs::rename(&old_path, &new_path)?,
+            }
+        }
+
+        // The file may have been moved to another repository
+        if self.file_dir != file_dir {
+            self.vcs = Some(open_vcs(file_dir));
+        }
+        self.file_dir = file_dir.into();
+        self.file_name = file_name.into();
+        Ok(())
+    }
 }
 
-/// Implement the ToString trait for File (used for saving the file)
-impl ToString for File {
-    fn to_string(&self) -> String {
-        self.content.to_string()
+/// Implement the Display trait for File (used for saving the file)
+impl Display for File {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.content)
     }
 }
 
@@ -209,7 +573,7 @@ mod tests {
             "Hello, World !"
         );
         assert_eq!(file.line(1).unwrap().iter().collect::<String>(), "");
-        assert!(matches!(file.line(2), None))
+        assert!(file.line(2).is_none())
     }
 
     #[test]
@@ -281,6 +645,79 @@ mod tests {
         assert_eq!(file.to_string(), "Hello, World !");
     }
 
+    #[test]
+    fn file_modified() {
+        let mut file = File::from_string("Hello, World !", "test", "test");
+        assert!(!file.is_modified());
+        file.insert(0, 0, '!');
+        assert!(file.is_modified());
+        file.mark_saved();
+        assert!(!file.is_modified());
+        file.delete(0, 1);
+        assert!(file.is_modified());
+    }
+
+    #[test]
+    fn file_replace_lines() {
+        let mut file = File::from_string("a\nb\nc\n", "test", "test");
+        file.replace_lines(1, 1, "B\nBB\n");
+        assert_eq!(file.to_string(), "a\nB\nBB\nc\n");
+        // Insert without removing anything
+        file.replace_lines(0, 0, "0\n");
+        assert_eq!(file.to_string(), "0\na\nB\nBB\nc\n");
+        // Remove without inserting anything
+        file.replace_lines(2, 2, "");
+        assert_eq!(file.to_string(), "0\na\nc\n");
+    }
+
+    #[test]
+    fn file_revision() {
+        let mut file = File::from_string("a\n", "test", "test");
+        let other = File::from_string("a\n", "test", "test");
+        assert_ne!(file.revision, other.revision);
+        let revision = file.revision;
+        file.insert(0, 0, 'b');
+        assert_ne!(file.revision, revision);
+        // Out of bounds edits do nothing
+        let revision = file.revision;
+        file.insert(5, 0, 'b');
+        assert_eq!(file.revision, revision);
+    }
+
+    #[test]
+    fn file_undo() {
+        let mut file = File::from_string("a\n", "test", "test");
+        assert!(!file.undo());
+        file.checkpoint();
+        file.insert(0, 1, 'b');
+        file.insert(0, 2, 'c');
+        file.checkpoint();
+        file.delete_line(0);
+        assert_eq!(file.to_string(), "");
+        assert!(file.undo());
+        assert_eq!(file.to_string(), "abc\n");
+        assert!(file.undo());
+        assert_eq!(file.to_string(), "a\n");
+        assert!(!file.undo());
+
+        // Only the last groups of edits are kept
+        for c in ('a'..='z').cycle().take(UNDO_LIMIT + 1) {
+            file.checkpoint();
+            file.insert(0, 0, c);
+        }
+        assert_eq!(file.undo_stack.len(), UNDO_LIMIT);
+        while file.undo() {}
+        // The first insertion can no longer be undone
+        assert_eq!(file.to_string(), "aa\n");
+    }
+
+    #[test]
+    fn file_scratch() {
+        let file = File::scratch("a\nb", "scratch");
+        assert_eq!(file.len(), 2);
+        assert!(file.git_ref().is_none());
+    }
+
     #[test]
     fn file_from_sting_with_tabs() {
         let file = File::from_string("Hello,\tWorld !", "test", "test");
@@ -0,0 +1,119 @@
+//! # Diff against the saved file
+//!
+//! Outside of a git repository, the buffer is compared to the file saved on disk, so
+//! that the gutter shows the unsaved changes. The file is read again each time the diff
+//! is computed: the markers disappear once the buffer is saved, and a file that does
+//! not exist yet has all its lines marked as added.
+
+use std::{collections::HashMap, fs, path::Path};
+
+use super::git::{self, DiffBase, DiffResult, Hunk, Marker, Vcs};
+
+/// Content of a file on disk, empty if it does not exist (or is not utf-8 encoded)
+pub fn saved_content(file_path: &str, file_name: &str) -> String {
+    fs::read_to_string(Path::new(file_path).join(file_name)).unwrap_or_default()
+}
+
+/// Hunks of the diff between the file on disk and the buffer
+pub(super) fn saved_hunks(
+    content: &str,
+    file_path: &str,
+    file_name: &str,
+) -> Result<Vec<Hunk>, git2::Error> {
+    let saved = saved_content(file_path, file_name);
+    Ok(git::hunks(&git::diff_hunks(&saved, content)?, false))
+}
+
+/// Compares the buffer to the file saved on disk, for the files that are not in a
+/// git repository
+#[derive(Default)]
+pub struct SavedFile {
+    diff: Option<HashMap<usize, Marker>>,
+    hunks: Vec<Hunk>,
+}
+
+impl Vcs for SavedFile {
+    fn get_hunks(
+        &mut self,
+        content: &str,
+        file_path: &str,
+        file_name: &str,
+    ) -> Result<Vec<Hunk>, Box<dyn std::error::Error>> {
+        Ok(saved_hunks(content, file_path, file_name)?)
+    }
+
+    fn diff_result(
+        &mut self,
+        file_path: &str,
+        file_name: &str,
+        content: &str,
+        _with_blame: bool,
+    ) -> Result<DiffResult, Box<dyn std::error::Error>> {
+        Ok(DiffResult {
+            file_status: None,
+            hunks: self.get_hunks(content, file_path, file_name)?,
+            head_changes: None,
+            line_count: content.lines().count(),
+        })
+    }
+
+    fn apply_diff(&mut self, result: DiffResult) {
+        self.diff = Some(git::markers(&result.hunks));
+        self.hunks = result.hunks;
+    }
+
+    fn diff(&self) -> Option<HashMap<usize, Marker>> {
+        self.diff.clone()
+    }
+
+    fn hunks(&self) -> Vec<Hunk> {
+        self.hunks.clone()
+    }
+
+    fn diff_base(&self) -> DiffBase {
+        DiffBase::Saved
+    }
+
+    /// The saved file is the only possible base outside of a repository
+    fn set_diff_base(&mut self, base: DiffBase) -> Result<(), git2::Error> {
+        match base {
+            DiffBase::Saved => Ok(()),
+            _ => Err(git::not_in_repository()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::editor::{test_utils::temp_dir, view::file::git::PatchType};
+
+    #[test]
+    fn diff_against_saved_file() {
+        let temp_dir = temp_dir("saved");
+        let dir = temp_dir.to_str().unwrap();
+        fs::write(Path::new(dir).join("file.txt"), "a\nb\nc\n").unwrap();
+
+        let mut saved = SavedFile::default();
+        saved.compute_diff(dir, "file.txt", "a\nB\nc\nd\n").unwrap();
+        let hunks = saved.hunks();
+        assert_eq!(hunks.len(), 2);
+        assert_eq!(hunks[0].patch_type(), PatchType::Changed);
+        assert_eq!(hunks[0].old_lines, vec!["b\n".to_string()]);
+        assert_eq!(hunks[1].patch_type(), PatchType::Added);
+        assert_eq!(saved.diff().unwrap().len(), 2);
+
+        // Once saved, there is nothing left to show
+        fs::write(Path::new(dir).join("file.txt"), "a\nB\nc\nd\n").unwrap();
+        saved.compute_diff(dir, "file.txt", "a\nB\nc\nd\n").unwrap();
+        assert!(saved.hunks().is_empty());
+
+        // Every line of a new file is added
+        saved.compute_diff(dir, "new.txt", "x\ny\n").unwrap();
+        assert_eq!(saved.hunks()[0].count, 2);
+
+        assert!(saved.as_git().is_none());
+        assert!(saved.as_git().is_none());
+        assert!(saved.set_diff_base(DiffBase::Head).is_err());
+    }
+}
@@ -7,12 +7,21 @@
 pub mod file;
 
 use std::process::exit;
-use std::{collections::HashMap, path};
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    ops::{Range, RangeInclusive},
+    path,
+};
 
 use file::File;
+use git2::Oid;
 
-use self::file::git::PatchType;
-use self::file::EditorFile;
+use self::file::git::{
+    BlameLine, ConflictVersions, DiffBase, DiffResult, FileStatus, GitRef, Hunk, Marker, Revision,
+    Stash,
+};
+use self::file::{EditorFile, Snapshot};
 
 /// The View struct represents the actual portion of the File being displayed.
 pub struct View {
@@ -28,10 +37,53 @@ pub struct View {
     pub width: usize,
     /// The position of the cursor in the view
     pub cursor: (usize, usize),
+    /// The line where the visual selection started (absolute line in the file)
+    pub selection_anchor: Option<usize>,
+    /// Styled ranges of characters of each line (absolute line in the file)
+    pub highlights: Highlights,
+    /// Columns of the file where a vertical ruler is drawn
+    pub rulers: Vec<usize>,
+}
+
+/// Styled ranges of characters of each line of a view
+pub type Highlights = HashMap<usize, Vec<(Range<usize>, Highlight)>>;
+
+/// Style of a range of characters of a line
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Highlight {
+    /// Line only in the new buffer
+    Added,
+    /// Line only in the old buffer
+    Deleted,
+    /// Line that differs between the buffers
+    Changed,
+    /// Words that differ within a changed line
+    ChangedText,
+    /// Placeholder for a line that exists only in the other buffer
+    Filler,
+    /// Our side of a merge conflict
+    Ours,
+    /// The common ancestor in a merge conflict
+    Base,
+    /// Their side of a merge conflict
+    Theirs,
+    /// Conflict marker line (`<<<<<<<`, `=======`...)
+    ConflictMarker,
+    /// Comment (of a commit message or a rebase todo list)
+    Comment,
+    /// Summary line of a commit message
+    Summary,
+    /// Text beyond the rulers of a commit message
+    Overflow,
+    /// Action of a rebase todo list (`pick`, `squash`...)
+    Keyword,
+    /// Commit id of a rebase todo list
+    CommitId,
 }
 
 pub trait FileView {
     fn new(file_path: &str) -> Self;
+    fn scratch(content: &str, name: &str) -> Self;
     fn line(&self, index: usize) -> String;
     fn navigate(&mut self, dx: isize, dy: isize) -> bool;
     fn insert(&mut self, c: char) -> bool;
@@ -41,11 +93,47 @@ pub trait FileView {
     fn dump_file(&self) -> String;
     fn git_ref(&self) -> Option<String>;
     fn refresh_diff(&mut self) -> Result<(), Box<dyn std::error::Error>>;
-    fn diff(&self) -> Option<HashMap<usize, PatchType>>;
+    fn snapshot(&self) -> Option<Snapshot>;
+    fn apply_diff(&mut self, revision: usize, result: DiffResult) -> bool;
+    fn file_status(&self) -> Option<FileStatus>;
+    fn diff(&self) -> Option<HashMap<usize, Marker>>;
     fn file_path(&self) -> String;
     fn file_name(&self) -> String;
     fn file_dir(&self) -> String;
-    fn set_file_name(&mut self, file_name: String);
+    fn rename(&mut self, file_path: &str) -> Result<(), Box<dyn std::error::Error>>;
+    fn hunks(&self) -> Vec<Hunk>;
+    fn revert_hunk(&mut self, hunk: &Hunk);
+    fn replace_lines(&mut self, start: usize, count: usize, text: &str);
+    fn checkpoint(&mut self);
+    fn undo(&mut self) -> bool;
+    fn diff_base(&self) -> Option<DiffBase>;
+    fn set_diff_base(&mut self, base: DiffBase) -> Result<(), Box<dyn std::error::Error>>;
+    fn stage_lines(
+        &mut self,
+        lines: RangeInclusive<usize>,
+    ) -> Result<(), Box<dyn std::error::Error>>;
+    fn unstage_lines(
+        &mut self,
+        lines: RangeInclusive<usize>,
+    ) -> Result<(), Box<dyn std::error::Error>>;
+    fn selection(&self) -> Option<RangeInclusive<usize>>;
+    fn toggle_blame(&mut self) -> Result<bool, Box<dyn std::error::Error>>;
+    fn blame(&self) -> Option<&[Option<BlameLine>]>;
+    fn commit_message(&self, commit: Oid) -> Result<Vec<String>, Box<dyn std::error::Error>>;
+    fn history(&self) -> Result<Vec<Revision>, Box<dyn std::error::Error>>;
+    fn revision_content(&self, rev: &str) -> Result<Option<String>, Box<dyn std::error::Error>>;
+    fn conflict_versions(&self) -> Result<Option<ConflictVersions>, Box<dyn std::error::Error>>;
+    fn prepare_commit(&self) -> Result<String, Box<dyn std::error::Error>>;
+    fn commit(&mut self, message: &str) -> Result<Oid, Box<dyn std::error::Error>>;
+    fn refs(&self) -> Result<Vec<GitRef>, Box<dyn std::error::Error>>;
+    fn checkout(&mut self, git_ref: &GitRef) -> Result<(), Box<dyn std::error::Error>>;
+    fn create_branch(&mut self, name: &str) -> Result<(), Box<dyn std::error::Error>>;
+    fn stash_save(&mut self) -> Result<(), Box<dyn std::error::Error>>;
+    fn stashes(&mut self) -> Result<Vec<Stash>, Box<dyn std::error::Error>>;
+    fn stash_diff(&mut self, index: usize) -> Result<Vec<String>, Box<dyn std::error::Error>>;
+    fn stash_pop(&mut self, index: usize) -> Result<(), Box<dyn std::error::Error>>;
+    fn is_modified(&self) -> bool;
+    fn mark_saved(&mut self);
 }
 
 impl Default for View {
@@ -57,6 +145,9 @@ impl Default for View {
             height: 0,
             width: 0,
             cursor: (0, 0),
+            selection_anchor: None,
+            highlights: HashMap::new(),
+            rulers: vec![],
         }
     }
 }
@@ -70,6 +161,9 @@ impl From<String> for View {
             height: 0,
             width: 0,
             cursor: (0, 0),
+            selection_anchor: None,
+            highlights: HashMap::new(),
+            rulers: vec![],
         }
     }
 }
@@ -100,6 +194,24 @@ impl FileView for View {
             height: 0,
             width: 0,
             cursor: (0, 0),
+            selection_anchor: None,
+            highlights: HashMap::new(),
+            rulers: vec![],
+        }
+    }
+
+    /// Create a view on a buffer that is not backed by a file
+    fn scratch(content: &str, name: &str) -> Self {
+        Self {
+            file: File::scratch(content, name),
+            start_line: 0,
+            start_col: 0,
+            height: 0,
+            width: 0,
+            cursor: (0, 0),
+            selection_anchor: None,
+            highlights: HashMap::new(),
+            rulers: vec![],
         }
     }
 
@@ -209,8 +321,10 @@ impl FileView for View {
         self.file.file_dir.clone() + &self.file.file_name
     }
 
-    fn set_file_name(&mut self, file_name: String) {
-        self.file.file_name = file_name;
+    /// Rename (or move) the file on disk
+    fn rename(&mut self, file_path: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let (file_dir, file_name, _) = split_path_name(file_path);
+        self.file.rename(&file_dir, &file_name)
     }
 
     fn file_name(&self) -> String {
@@ -221,13 +335,151 @@ impl FileView for View {
         self.file.file_dir.clone()
     }
 
-    fn diff(&self) -> Option<HashMap<usize, PatchType>> {
+    fn diff(&self) -> Option<HashMap<usize, Marker>> {
         self.file.diff()
     }
 
     fn refresh_diff(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         self.file.refresh_diff()
     }
+
+    fn snapshot(&self) -> Option<Snapshot> {
+        self.file.snapshot()
+    }
+
+    fn apply_diff(&mut self, revision: usize, result: DiffResult) -> bool {
+        self.file.apply_diff(revision, result)
+    }
+
+    fn file_status(&self) -> Option<FileStatus> {
+        self.file.file_status()
+    }
+
+    fn hunks(&self) -> Vec<Hunk> {
+        self.file.hunks()
+    }
+
+    /// Replace the lines of a hunk with the lines of the diff base (undoable)
+    fn revert_hunk(&mut self, hunk: &Hunk) {
+        self.replace_lines(hunk.start, hunk.count, &hunk.old_lines.concat());
+    }
+
+    /// Replace `count` lines from `start` with the given text (undoable)
+    fn replace_lines(&mut self, start: usize, count: usize, text: &str) {
+        self.file.checkpoint();
+        self.file.replace_lines(start, count, text);
+        // Keep the cursor inside the file
+        self.navigate(0, 0);
+    }
+
+    fn checkpoint(&mut self) {
+        self.file.checkpoint()
+    }
+
+    /// Undo the last group of edits
+    fn undo(&mut self) -> bool {
+        let undone = self.file.undo();
+        // Keep the cursor inside the file
+        self.navigate(0, 0);
+        undone
+    }
+
+    fn diff_base(&self) -> Option<DiffBase> {
+        self.file.diff_base()
+    }
+
+    fn set_diff_base(&mut self, base: DiffBase) -> Result<(), Box<dyn std::error::Error>> {
+        self.file.set_diff_base(base)
+    }
+
+    fn stage_lines(
+        &mut self,
+        lines: RangeInclusive<usize>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.file.stage_lines(lines)
+    }
+
+    fn unstage_lines(
+        &mut self,
+        lines: RangeInclusive<usize>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.file.unstage_lines(lines)
+    }
+
+    /// Lines of the file between the selection anchor and the cursor
+    fn selection(&self) -> Option<RangeInclusive<usize>> {
+        let anchor = self.selection_anchor?;
+        let line = self.cursor.1 + self.start_line;
+        Some(anchor.min(line)..=anchor.max(line))
+    }
+
+    fn toggle_blame(&mut self) -> Result<bool, Box<dyn std::error::Error>> {
+        self.file.toggle_blame()
+    }
+
+    fn blame(&self) -> Option<&[Option<BlameLine>]> {
+        self.file.blame()
+    }
+
+    fn commit_message(&self, commit: Oid) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        self.file.commit_message(commit)
+    }
+
+    fn history(&self) -> Result<Vec<Revision>, Box<dyn std::error::Error>> {
+        self.file.history()
+    }
+
+    fn revision_content(&self, rev: &str) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        self.file.revision_content(rev)
+    }
+
+    fn conflict_versions(&self) -> Result<Option<ConflictVersions>, Box<dyn std::error::Error>> {
+        self.file.conflict_versions()
+    }
+
+    fn prepare_commit(&self) -> Result<String, Box<dyn std::error::Error>> {
+        self.file.prepare_commit()
+    }
+
+    fn commit(&mut self, message: &str) -> Result<Oid, Box<dyn std::error::Error>> {
+        self.file.commit(message)
+    }
+
+    fn refs(&self) -> Result<Vec<GitRef>, Box<dyn std::error::Error>> {
+        self.file.refs()
+    }
+
+    fn checkout(&mut self, git_ref: &GitRef) -> Result<(), Box<dyn std::error::Error>> {
+        self.file.checkout(git_ref)
+    }
+
+    fn create_branch(&mut self, name: &str) -> Result<(), Box<dyn std::error::Error>> {
+        self.file.create_branch(name)
+    }
+
+    fn stash_save(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.file.stash_save()
+    }
+
+    fn stashes(&mut self) -> Result<Vec<Stash>, Box<dyn std::error::Error>> {
+        self.file.stashes()
+    }
+
+    fn stash_diff(&mut self, index: usize) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        self.file.stash_diff(index)
+    }
+
+    fn stash_pop(&mut self, index: usize) -> Result<(), Box<dyn std::error::Error>> {
+        self.file.stash_pop(index)
+    }
+
+    fn is_modified(&self) -> bool {
+        self.file.is_modified()
+    }
+
+    fn mark_saved(&mut self) {
+        self.file.mark_saved()
+    }
 }
 
 impl View {
@@ -296,16 +548,14 @@ impl View {
     }
 }
 
-impl ToString for View {
-    fn to_string(&self) -> String {
+impl Display for View {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let bottom = self
             .height
             .min(self.file.len().saturating_sub(self.start_line));
 
-        (0..bottom)
-            .map(|i| self.line(i))
-            .collect::<Vec<_>>()
-            .join("\n")
+        let lines = (0..bottom).map(|i| self.line(i)).collect::<Vec<_>>();
+        write!(f, "{}", lines.join("\n"))
     }
 }
 
@@ -461,4 +711,19 @@ mod tests {
         assert_eq!(view.cursor, (9, 0));
         assert_eq!(view.to_string(), ", World !");
     }
+
+    #[test]
+    fn view_selection() {
+        let mut view = View::from("a\nb\nc\nd".to_string());
+        view.height = 2;
+        view.width = 10;
+        assert_eq!(view.selection(), None);
+
+        view.navigate(0, 2);
+        view.selection_anchor = Some(2);
+        view.navigate(0, -2);
+        assert_eq!(view.selection(), Some(0..=2));
+        view.navigate(0, 3);
+        assert_eq!(view.selection(), Some(2..=3));
+    }
 }
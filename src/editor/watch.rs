@@ -0,0 +1,141 @@
+//! # Watching the git directory
+//!
+//! The diff of the buffer depends on the state of the repository: it has to be computed
+//! again when HEAD moves (checkout, commit, reset...) or when the index changes (`git add`
+//! in another terminal). Instead of polling the repository, the git directory is watched
+//! with inotify (through the libc crate, like the `signal` module) and the git thread is
+//! notified when one of the files it cares about changes.
+//!
+//! Git replaces its files atomically (it writes `index.lock` and renames it to `index`),
+//! so the directories are watched rather than the files themselves. On other platforms,
+//! the modification times of the files are polled instead.
+
+use std::{path::Path, sync::mpsc::Sender};
+
+use super::GitEvent;
+
+/// Files of the git directory whose changes affect the diff or the status bar
+/// (`logs/HEAD` changes whenever HEAD moves, even when it is not detached)
+const WATCHED: [&str; 9] = [
+    "HEAD",
+    "index",
+    "MERGE_HEAD",
+    "CHERRY_PICK_HEAD",
+    "REVERT_HEAD",
+    "BISECT_LOG",
+    "rebase-merge",
+    "rebase-apply",
+    "logs/HEAD",
+];
+
+/// Watch the given git directory in a background thread, sending a
+/// `GitEvent::RepositoryChanged` each time one of the watched files changes
+#[cfg(target_os = "linux")]
+pub fn watch_git_dir(git_dir: &Path, tx: Sender<GitEvent>) {
+    use std::{ffi::CString, mem::size_of, os::unix::ffi::OsStrExt, thread};
+
+    let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC) };
+    if fd < 0 {
+        return;
+    }
+    let mask = libc::IN_CLOSE_WRITE
+        | libc::IN_MOVED_TO
+        | libc::IN_CREATE
+        | libc::IN_DELETE
+        | libc::IN_MODIFY;
+    // Watch descriptors of the directories, with the prefix of their files in `WATCHED`
+    let mut dirs = vec![];
+    for (dir, prefix) in [(git_dir.to_path_buf(), ""), (git_dir.join("logs"), "logs/")] {
+        let Ok(path) = CString::new(dir.as_os_str().as_bytes()) else {
+            continue;
+        };
+        let wd = unsafe { libc::inotify_add_watch(fd, path.as_ptr(), mask) };
+        if wd >= 0 {
+            dirs.push((wd, prefix));
+        }
+    }
+
+    thread::spawn(move || {
+        let header = size_of::<libc::inotify_event>();
+        let mut buffer = [0u8; 4096];
+        loop {
+            let len = unsafe { libc::read(fd, buffer.as_mut_ptr().cast(), buffer.len()) };
+            if len <= 0 {
+                break;
+            }
+            // The buffer holds a sequence of events, each followed by the name of the file
+            let (mut offset, mut changed) = (0, false);
+            while offset + header <= len as usize {
+                let event = unsafe {
+                    std::ptr::read_unaligned(
+                        buffer.as_ptr().add(offset).cast::<libc::inotify_event>(),
+                    )
+                };
+                let name = &buffer[offset + header..offset + header + event.len as usize];
+                let name = String::from_utf8_lossy(name);
+                let name = name.trim_end_matches('\0');
+                if let Some((_, prefix)) = dirs.iter().find(|(wd, _)| *wd == event.wd) {
+                    changed |= WATCHED.contains(&format!("{}{}", prefix, name).as_str());
+                }
+                offset += header + event.len as usize;
+            }
+            if changed && tx.send(GitEvent::RepositoryChanged).is_err() {
+                break;
+            }
+        }
+        unsafe { libc::close(fd) };
+    });
+}
+
+/// Watch the given git directory in a background thread, sending a
+/// `GitEvent::RepositoryChanged` each time one of the watched files changes
+#[cfg(not(target_os = "linux"))]
+pub fn watch_git_dir(git_dir: &Path, tx: Sender<GitEvent>) {
+    use std::{fs, thread, time::Duration};
+
+    let paths = WATCHED.map(|name| git_dir.join(name));
+    let modified = move || {
+        paths
+            .iter()
+            .map(|path| fs::metadata(path).and_then(|m| m.modified()).ok())
+            .collect::<Vec<_>>()
+    };
+    thread::spawn(move || {
+        let mut last = modified();
+        loop {
+            thread::sleep(Duration::from_millis(500));
+            let current = modified();
+            if current != last && tx.send(GitEvent::RepositoryChanged).is_err() {
+                break;
+            }
+            last = current;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::editor::test_utils::temp_dir;
+    use std::{fs, sync::mpsc, time::Duration};
+
+    #[test]
+    fn watch_index_changes() {
+        let dir = temp_dir("watch");
+        fs::create_dir(dir.join("logs")).unwrap();
+        let (tx, rx) = mpsc::channel();
+        watch_git_dir(&dir, tx);
+
+        // Other files of the git directory are ignored
+        fs::write(dir.join("description"), "").unwrap();
+        assert!(rx.recv_timeout(Duration::from_millis(700)).is_err());
+
+        // Like git, write a lock file and rename it
+        fs::write(dir.join("index.lock"), "").unwrap();
+        fs::rename(dir.join("index.lock"), dir.join("index")).unwrap();
+        assert_eq!(
+            rx.recv_timeout(Duration::from_secs(2)),
+            Ok(GitEvent::RepositoryChanged)
+        );
+    }
+}
@@ -11,17 +11,77 @@
 
 mod editor;
 
-use editor::Editor;
+use editor::{record, script, Editor};
+
+const USAGE: &str = "Usage: giga [--record log] [--replay log] [file]
+       giga -d file1 file2
+       giga [--script script] [-c command]... file";
 
 fn main() {
     let args = std::env::args().collect::<Vec<String>>();
-    if args.len() > 2 {
-        println!("Usage: giga [file]");
-        std::process::exit(1);
+
+    // Compare two files side by side
+    if args.len() == 4 && args[1] == "-d" {
+        match Editor::open_diff(&args[2], &args[3]) {
+            Ok(mut editor) => editor.run(),
+            Err(e) => {
+                eprintln!("Could not compare the files: {}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
     }
 
-    // Optional file to edit
-    let file: Option<&str> = args.get(1).map(|s| s.as_str());
+    // Commands to run without a terminal (in order), and the file to edit
+    let mut script: Option<Vec<String>> = None;
+    let mut file: Option<&str> = None;
+    // Session to record and session to replay
+    let mut record: Option<&str> = None;
+    let mut replay: Option<&str> = None;
+    let mut args = args.iter().skip(1);
+    while let Some(arg) = args.next() {
+        let value = match arg.as_str() {
+            "--script" | "-c" | "--record" | "--replay" => args.next(),
+            _ => None,
+        };
+        match (arg.as_str(), value) {
+            ("--record", Some(path)) => record = Some(path),
+            ("--replay", Some(path)) => replay = Some(path),
+            ("--script", Some(path)) => match std::fs::read_to_string(path) {
+                Ok(content) => script
+                    .get_or_insert_with(Vec::new)
+                    .extend(content.lines().map(String::from)),
+                Err(e) => {
+                    eprintln!("giga: could not read {}: {}", path, e);
+                    std::process::exit(script::EXIT_INVALID);
+                }
+            },
+            // The command line is the default in `-c`
+            ("-c", Some(command)) => {
+                let command = match command.starts_with(':') {
+                    true => command.clone(),
+                    false => format!(":{}", command),
+                };
+                script.get_or_insert_with(Vec::new).push(command);
+            }
+            (path, None) if file.is_none() && !path.starts_with('-') => file = Some(path),
+            _ => {
+                println!("{}", USAGE);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    // A session without a terminal cannot be recorded nor replayed
+    if script.is_some() && (record.is_some() || replay.is_some()) {
+        println!("{}", USAGE);
+        std::process::exit(1);
+    }
+    // A script edits an existing file (or a file it creates), never a default one
+    if script.is_some() && file.is_none() {
+        println!("{}", USAGE);
+        std::process::exit(script::EXIT_INVALID);
+    }
 
     let mut editor = match file {
         // Try to open the file, if it doesn't exist, create a new one
@@ -29,5 +89,24 @@ fn main() {
         // If no file is provided, create a new one with a default name
         None => Editor::open("./Newfile"),
     };
-    editor.run();
+    if let Some(script) = script {
+        std::process::exit(editor.run_script(&script));
+    }
+    if let Some(path) = record {
+        match record::Recorder::create(path) {
+            Ok(recorder) => editor.record(recorder),
+            Err(e) => {
+                eprintln!("giga: could not record the session in {}: {}", path, e);
+                std::process::exit(1);
+            }
+        }
+    }
+    match replay.map(record::read_log) {
+        Some(Ok(events)) => editor.replay(events),
+        Some(Err(e)) => {
+            eprintln!("giga: could not replay the session: {}", e);
+            std::process::exit(1);
+        }
+        None => editor.run(),
+    }
 }